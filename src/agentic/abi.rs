@@ -0,0 +1,362 @@
+use web3::ethabi::{Contract, Event, EventParam, Function, Param, StateMutability, Token};
+use web3::types::{Address, U256};
+
+pub fn parse_abi(abi: &[u8]) -> Result<Contract, Box<dyn std::error::Error>> {
+    if abi.iter().all(|b| b.is_ascii_whitespace()) {
+        return Ok(Contract::load(&b"[]"[..])?);
+    }
+    Ok(Contract::load(abi)?)
+}
+
+pub fn canonical_signature(function: &Function) -> String {
+    let types = function.inputs.iter()
+        .map(|param| param.kind.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("{}({})", function.name, types)
+}
+
+// Picks the function to call. `method` is either a bare name, resolved against the
+// overloads in the ABI by argument count and token types, or a full signature such as
+// "safeTransferFrom(address,address,uint256)" which selects one overload exactly and
+// also works for functions missing from the ABI (with no decodable outputs).
+pub fn resolve_function(contract: &Contract, method: &str, params: &[Token]) -> Result<Function, Box<dyn std::error::Error>> {
+    if method.contains('(') {
+        let wanted = method.replace(' ', "");
+        let declared = contract.functions()
+            .find(|function| canonical_signature(function) == wanted)
+            .cloned();
+        let function = match declared {
+            Some(function) => function,
+            None => parse_signature(&wanted)?,
+        };
+        check_arguments(&function, params)?;
+        return Ok(function);
+    }
+
+    let overloads = contract.functions_by_name(method)
+        .map_err(|_| format!("Function {} not found in ABI", method))?;
+
+    let matching: Vec<&Function> = overloads.iter()
+        .filter(|function| check_arguments(function, params).is_ok())
+        .collect();
+
+    match matching.len() {
+        1 => Ok(matching[0].clone()),
+        0 => Err(format!(
+            "No overload of {} accepts ({}); candidates: {}",
+            method,
+            params.iter().map(describe_token).collect::<Vec<_>>().join(","),
+            overloads.iter().map(canonical_signature).collect::<Vec<_>>().join(", "),
+        ).into()),
+        _ => Err(format!(
+            "Call to {} is ambiguous, use one of: {}",
+            method,
+            matching.iter().map(|function| canonical_signature(function)).collect::<Vec<_>>().join(", "),
+        ).into()),
+    }
+}
+
+#[allow(deprecated)]
+pub fn parse_signature(signature: &str) -> Result<Function, Box<dyn std::error::Error>> {
+    let open = signature.find('(').ok_or("Signature is missing '('")?;
+    if !signature.ends_with(')') {
+        return Err(format!("Malformed function signature {}", signature).into());
+    }
+
+    let name = signature[..open].to_string();
+    let inputs = split_types(&signature[open + 1..signature.len() - 1])?
+        .into_iter()
+        .map(|kind| Ok(Param {
+            name: String::new(),
+            kind: web3::ethabi::param_type::Reader::read(&kind)?,
+            internal_type: None,
+        }))
+        .collect::<Result<Vec<_>, web3::ethabi::Error>>()?;
+
+    Ok(Function {
+        name,
+        inputs,
+        outputs: vec![],
+        constant: None,
+        state_mutability: StateMutability::NonPayable,
+    })
+}
+
+// Parses a human-readable event such as
+// "Transfer(address indexed from, address indexed to, uint256 value)". Parameters marked
+// indexed are read from topics; names are optional, and a trailing "anonymous" marks an
+// anonymous event.
+pub fn parse_event(signature: &str) -> Result<Event, Box<dyn std::error::Error>> {
+    let signature = signature.trim();
+    let signature = signature.strip_prefix("event ").unwrap_or(signature).trim();
+    let (signature, anonymous) = match signature.strip_suffix("anonymous") {
+        Some(rest) => (rest.trim_end(), true),
+        None => (signature, false),
+    };
+
+    let open = signature.find('(').ok_or("Event signature is missing '('")?;
+    if !signature.ends_with(')') {
+        return Err(format!("Malformed event signature {}", signature).into());
+    }
+
+    let name = signature[..open].trim().to_string();
+    let inputs = split_types(&signature[open + 1..signature.len() - 1])?
+        .into_iter()
+        .enumerate()
+        .map(|(index, param)| -> Result<EventParam, Box<dyn std::error::Error>> {
+            let mut words = param.split_whitespace();
+            let kind = words.next().ok_or_else(|| format!("Empty parameter in event {}", name))?;
+            let mut indexed = false;
+            let mut param_name = format!("arg{}", index);
+            for word in words {
+                if word == "indexed" {
+                    indexed = true;
+                } else {
+                    param_name = word.to_string();
+                }
+            }
+            Ok(EventParam {
+                name: param_name,
+                kind: web3::ethabi::param_type::Reader::read(kind)?,
+                indexed,
+            })
+        })
+        .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+
+    Ok(Event { name, inputs, anonymous })
+}
+
+pub fn encode_call(contract: &Contract, method: &str, params: &[Token]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let function = resolve_function(contract, method, params)?;
+    Ok(function.encode_input(params)?)
+}
+
+pub fn decode_output(function: &Function, data: &[u8]) -> Result<Vec<Token>, Box<dyn std::error::Error>> {
+    if function.outputs.is_empty() {
+        return Ok(vec![]);
+    }
+    Ok(function.decode_output(data)?)
+}
+
+fn check_arguments(function: &Function, params: &[Token]) -> Result<(), Box<dyn std::error::Error>> {
+    if function.inputs.len() != params.len() {
+        return Err(format!(
+            "{} expects {} arguments, got {}",
+            canonical_signature(function),
+            function.inputs.len(),
+            params.len(),
+        ).into());
+    }
+
+    for (param, token) in function.inputs.iter().zip(params) {
+        if !token.type_check(&param.kind) {
+            return Err(format!(
+                "Argument {} of {} must be {}, got {}",
+                param.name,
+                canonical_signature(function),
+                param.kind,
+                describe_token(token),
+            ).into());
+        }
+    }
+
+    Ok(())
+}
+
+fn split_types(list: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut types = Vec::new();
+    let mut depth = 0usize;
+    let mut current = String::new();
+
+    for ch in list.chars() {
+        match ch {
+            '(' => { depth += 1; current.push(ch); }
+            ')' => {
+                depth = depth.checked_sub(1)
+                    .ok_or_else(|| format!("Unbalanced ')' in parameter list ({})", list))?;
+                current.push(ch);
+            }
+            ',' if depth == 0 => types.push(std::mem::take(&mut current)),
+            _ => current.push(ch),
+        }
+    }
+    if depth != 0 {
+        return Err(format!("Unbalanced '(' in parameter list ({})", list).into());
+    }
+    if !current.is_empty() {
+        types.push(current);
+    }
+
+    Ok(types)
+}
+
+fn describe_token(token: &Token) -> String {
+    match token {
+        Token::Address(_) => "address".to_string(),
+        Token::FixedBytes(bytes) => format!("bytes{}", bytes.len()),
+        Token::Bytes(_) => "bytes".to_string(),
+        Token::Int(_) => "int".to_string(),
+        Token::Uint(_) => "uint".to_string(),
+        Token::Bool(_) => "bool".to_string(),
+        Token::String(_) => "string".to_string(),
+        Token::FixedArray(items) => format!("{}[{}]", items.first().map(describe_token).unwrap_or_default(), items.len()),
+        Token::Array(items) => format!("{}[]", items.first().map(describe_token).unwrap_or_default()),
+        Token::Tuple(items) => format!("({})", items.iter().map(describe_token).collect::<Vec<_>>().join(",")),
+    }
+}
+
+// Accessors for decoded return values
+pub fn token_at(tokens: &[Token], index: usize) -> Result<Token, Box<dyn std::error::Error>> {
+    tokens.get(index).cloned().ok_or_else(|| format!("Missing return value {}", index).into())
+}
+
+pub fn uint_at(tokens: &[Token], index: usize) -> Result<U256, Box<dyn std::error::Error>> {
+    token_at(tokens, index)?.into_uint().ok_or_else(|| format!("Return value {} is not a uint", index).into())
+}
+
+pub fn address_at(tokens: &[Token], index: usize) -> Result<Address, Box<dyn std::error::Error>> {
+    token_at(tokens, index)?.into_address().ok_or_else(|| format!("Return value {} is not an address", index).into())
+}
+
+pub fn string_at(tokens: &[Token], index: usize) -> Result<String, Box<dyn std::error::Error>> {
+    token_at(tokens, index)?.into_string().ok_or_else(|| format!("Return value {} is not a string", index).into())
+}
+
+pub fn bool_at(tokens: &[Token], index: usize) -> Result<bool, Box<dyn std::error::Error>> {
+    token_at(tokens, index)?.into_bool().ok_or_else(|| format!("Return value {} is not a bool", index).into())
+}
+
+pub fn tuple_at(tokens: &[Token], index: usize) -> Result<Vec<Token>, Box<dyn std::error::Error>> {
+    token_at(tokens, index)?.into_tuple().ok_or_else(|| format!("Return value {} is not a tuple", index).into())
+}
+
+// Interfaces used by the DeFi, NFT and wallet modules
+
+pub const ERC20_ABI: &str = r#"[
+    {"type":"function","name":"name","inputs":[],"outputs":[{"name":"","type":"string"}],"stateMutability":"view"},
+    {"type":"function","name":"symbol","inputs":[],"outputs":[{"name":"","type":"string"}],"stateMutability":"view"},
+    {"type":"function","name":"decimals","inputs":[],"outputs":[{"name":"","type":"uint8"}],"stateMutability":"view"},
+    {"type":"function","name":"totalSupply","inputs":[],"outputs":[{"name":"","type":"uint256"}],"stateMutability":"view"},
+    {"type":"function","name":"balanceOf","inputs":[{"name":"owner","type":"address"}],"outputs":[{"name":"","type":"uint256"}],"stateMutability":"view"},
+    {"type":"function","name":"allowance","inputs":[{"name":"owner","type":"address"},{"name":"spender","type":"address"}],"outputs":[{"name":"","type":"uint256"}],"stateMutability":"view"},
+    {"type":"function","name":"owner","inputs":[],"outputs":[{"name":"","type":"address"}],"stateMutability":"view"},
+    {"type":"function","name":"transfer","inputs":[{"name":"to","type":"address"},{"name":"amount","type":"uint256"}],"outputs":[{"name":"","type":"bool"}],"stateMutability":"nonpayable"},
+    {"type":"function","name":"approve","inputs":[{"name":"spender","type":"address"},{"name":"amount","type":"uint256"}],"outputs":[{"name":"","type":"bool"}],"stateMutability":"nonpayable"},
+    {"type":"function","name":"transferFrom","inputs":[{"name":"from","type":"address"},{"name":"to","type":"address"},{"name":"amount","type":"uint256"}],"outputs":[{"name":"","type":"bool"}],"stateMutability":"nonpayable"},
+    {"type":"event","name":"Transfer","inputs":[{"name":"from","type":"address","indexed":true},{"name":"to","type":"address","indexed":true},{"name":"value","type":"uint256","indexed":false}],"anonymous":false},
+    {"type":"event","name":"Approval","inputs":[{"name":"owner","type":"address","indexed":true},{"name":"spender","type":"address","indexed":true},{"name":"value","type":"uint256","indexed":false}],"anonymous":false}
+]"#;
+
+pub const ERC721_ABI: &str = r#"[
+    {"type":"function","name":"name","inputs":[],"outputs":[{"name":"","type":"string"}],"stateMutability":"view"},
+    {"type":"function","name":"symbol","inputs":[],"outputs":[{"name":"","type":"string"}],"stateMutability":"view"},
+    {"type":"function","name":"totalSupply","inputs":[],"outputs":[{"name":"","type":"uint256"}],"stateMutability":"view"},
+    {"type":"function","name":"balanceOf","inputs":[{"name":"owner","type":"address"}],"outputs":[{"name":"","type":"uint256"}],"stateMutability":"view"},
+    {"type":"function","name":"ownerOf","inputs":[{"name":"tokenId","type":"uint256"}],"outputs":[{"name":"","type":"address"}],"stateMutability":"view"},
+    {"type":"function","name":"tokenURI","inputs":[{"name":"tokenId","type":"uint256"}],"outputs":[{"name":"","type":"string"}],"stateMutability":"view"},
+    {"type":"function","name":"getApproved","inputs":[{"name":"tokenId","type":"uint256"}],"outputs":[{"name":"","type":"address"}],"stateMutability":"view"},
+    {"type":"function","name":"isApprovedForAll","inputs":[{"name":"owner","type":"address"},{"name":"operator","type":"address"}],"outputs":[{"name":"","type":"bool"}],"stateMutability":"view"},
+    {"type":"function","name":"approve","inputs":[{"name":"to","type":"address"},{"name":"tokenId","type":"uint256"}],"outputs":[],"stateMutability":"nonpayable"},
+    {"type":"function","name":"setApprovalForAll","inputs":[{"name":"operator","type":"address"},{"name":"approved","type":"bool"}],"outputs":[],"stateMutability":"nonpayable"},
+    {"type":"function","name":"transferFrom","inputs":[{"name":"from","type":"address"},{"name":"to","type":"address"},{"name":"tokenId","type":"uint256"}],"outputs":[],"stateMutability":"nonpayable"},
+    {"type":"function","name":"safeTransferFrom","inputs":[{"name":"from","type":"address"},{"name":"to","type":"address"},{"name":"tokenId","type":"uint256"}],"outputs":[],"stateMutability":"nonpayable"},
+    {"type":"function","name":"safeTransferFrom","inputs":[{"name":"from","type":"address"},{"name":"to","type":"address"},{"name":"tokenId","type":"uint256"},{"name":"data","type":"bytes"}],"outputs":[],"stateMutability":"nonpayable"},
+    {"type":"function","name":"mint","inputs":[{"name":"to","type":"address"},{"name":"tokenURI","type":"string"}],"outputs":[{"name":"","type":"uint256"}],"stateMutability":"nonpayable"},
+    {"type":"function","name":"burn","inputs":[{"name":"tokenId","type":"uint256"}],"outputs":[],"stateMutability":"nonpayable"},
+    {"type":"event","name":"Transfer","inputs":[{"name":"from","type":"address","indexed":true},{"name":"to","type":"address","indexed":true},{"name":"tokenId","type":"uint256","indexed":true}],"anonymous":false},
+    {"type":"event","name":"Approval","inputs":[{"name":"owner","type":"address","indexed":true},{"name":"approved","type":"address","indexed":true},{"name":"tokenId","type":"uint256","indexed":true}],"anonymous":false},
+    {"type":"event","name":"ApprovalForAll","inputs":[{"name":"owner","type":"address","indexed":true},{"name":"operator","type":"address","indexed":true},{"name":"approved","type":"bool","indexed":false}],"anonymous":false}
+]"#;
+
+pub const UNISWAP_V2_FACTORY_ABI: &str = r#"[
+    {"type":"function","name":"allPairsLength","inputs":[],"outputs":[{"name":"","type":"uint256"}],"stateMutability":"view"},
+    {"type":"function","name":"allPairs","inputs":[{"name":"index","type":"uint256"}],"outputs":[{"name":"","type":"address"}],"stateMutability":"view"},
+    {"type":"function","name":"getPair","inputs":[{"name":"tokenA","type":"address"},{"name":"tokenB","type":"address"}],"outputs":[{"name":"","type":"address"}],"stateMutability":"view"},
+    {"type":"event","name":"PairCreated","inputs":[{"name":"token0","type":"address","indexed":true},{"name":"token1","type":"address","indexed":true},{"name":"pair","type":"address","indexed":false},{"name":"index","type":"uint256","indexed":false}],"anonymous":false}
+]"#;
+
+pub const UNISWAP_V2_PAIR_ABI: &str = r#"[
+    {"type":"function","name":"token0","inputs":[],"outputs":[{"name":"","type":"address"}],"stateMutability":"view"},
+    {"type":"function","name":"token1","inputs":[],"outputs":[{"name":"","type":"address"}],"stateMutability":"view"},
+    {"type":"function","name":"getReserves","inputs":[],"outputs":[{"name":"reserve0","type":"uint112"},{"name":"reserve1","type":"uint112"},{"name":"blockTimestampLast","type":"uint32"}],"stateMutability":"view"},
+    {"type":"function","name":"totalSupply","inputs":[],"outputs":[{"name":"","type":"uint256"}],"stateMutability":"view"},
+    {"type":"function","name":"balanceOf","inputs":[{"name":"owner","type":"address"}],"outputs":[{"name":"","type":"uint256"}],"stateMutability":"view"},
+    {"type":"event","name":"Swap","inputs":[{"name":"sender","type":"address","indexed":true},{"name":"amount0In","type":"uint256","indexed":false},{"name":"amount1In","type":"uint256","indexed":false},{"name":"amount0Out","type":"uint256","indexed":false},{"name":"amount1Out","type":"uint256","indexed":false},{"name":"to","type":"address","indexed":true}],"anonymous":false},
+    {"type":"event","name":"Sync","inputs":[{"name":"reserve0","type":"uint112","indexed":false},{"name":"reserve1","type":"uint112","indexed":false}],"anonymous":false},
+    {"type":"event","name":"Mint","inputs":[{"name":"sender","type":"address","indexed":true},{"name":"amount0","type":"uint256","indexed":false},{"name":"amount1","type":"uint256","indexed":false}],"anonymous":false},
+    {"type":"event","name":"Burn","inputs":[{"name":"sender","type":"address","indexed":true},{"name":"amount0","type":"uint256","indexed":false},{"name":"amount1","type":"uint256","indexed":false},{"name":"to","type":"address","indexed":true}],"anonymous":false}
+]"#;
+
+pub const UNISWAP_V2_ROUTER_ABI: &str = r#"[
+    {"type":"function","name":"addLiquidity","inputs":[{"name":"tokenA","type":"address"},{"name":"tokenB","type":"address"},{"name":"amountADesired","type":"uint256"},{"name":"amountBDesired","type":"uint256"},{"name":"amountAMin","type":"uint256"},{"name":"amountBMin","type":"uint256"},{"name":"to","type":"address"},{"name":"deadline","type":"uint256"}],"outputs":[{"name":"amountA","type":"uint256"},{"name":"amountB","type":"uint256"},{"name":"liquidity","type":"uint256"}],"stateMutability":"nonpayable"},
+    {"type":"function","name":"removeLiquidity","inputs":[{"name":"tokenA","type":"address"},{"name":"tokenB","type":"address"},{"name":"liquidity","type":"uint256"},{"name":"amountAMin","type":"uint256"},{"name":"amountBMin","type":"uint256"},{"name":"to","type":"address"},{"name":"deadline","type":"uint256"}],"outputs":[{"name":"amountA","type":"uint256"},{"name":"amountB","type":"uint256"}],"stateMutability":"nonpayable"},
+    {"type":"function","name":"swapExactTokensForTokens","inputs":[{"name":"amountIn","type":"uint256"},{"name":"amountOutMin","type":"uint256"},{"name":"path","type":"address[]"},{"name":"to","type":"address"},{"name":"deadline","type":"uint256"}],"outputs":[{"name":"amounts","type":"uint256[]"}],"stateMutability":"nonpayable"},
+    {"type":"function","name":"getAmountsOut","inputs":[{"name":"amountIn","type":"uint256"},{"name":"path","type":"address[]"}],"outputs":[{"name":"amounts","type":"uint256[]"}],"stateMutability":"view"}
+]"#;
+
+pub const AAVE_LENDING_POOL_ABI: &str = r#"[
+    {"type":"function","name":"deposit","inputs":[{"name":"asset","type":"address"},{"name":"amount","type":"uint256"},{"name":"onBehalfOf","type":"address"},{"name":"referralCode","type":"uint16"}],"outputs":[],"stateMutability":"nonpayable"},
+    {"type":"function","name":"withdraw","inputs":[{"name":"asset","type":"address"},{"name":"amount","type":"uint256"},{"name":"to","type":"address"}],"outputs":[{"name":"","type":"uint256"}],"stateMutability":"nonpayable"},
+    {"type":"function","name":"borrow","inputs":[{"name":"asset","type":"address"},{"name":"amount","type":"uint256"},{"name":"interestRateMode","type":"uint256"},{"name":"referralCode","type":"uint16"},{"name":"onBehalfOf","type":"address"}],"outputs":[],"stateMutability":"nonpayable"},
+    {"type":"function","name":"repay","inputs":[{"name":"asset","type":"address"},{"name":"amount","type":"uint256"},{"name":"rateMode","type":"uint256"},{"name":"onBehalfOf","type":"address"}],"outputs":[{"name":"","type":"uint256"}],"stateMutability":"nonpayable"},
+    {"type":"function","name":"flashLoan","inputs":[{"name":"receiverAddress","type":"address"},{"name":"assets","type":"address[]"},{"name":"amounts","type":"uint256[]"},{"name":"modes","type":"uint256[]"},{"name":"onBehalfOf","type":"address"},{"name":"params","type":"bytes"},{"name":"referralCode","type":"uint16"}],"outputs":[],"stateMutability":"nonpayable"},
+    {"type":"function","name":"liquidationCall","inputs":[{"name":"collateralAsset","type":"address"},{"name":"debtAsset","type":"address"},{"name":"user","type":"address"},{"name":"debtToCover","type":"uint256"},{"name":"receiveAToken","type":"bool"}],"outputs":[],"stateMutability":"nonpayable"},
+    {"type":"function","name":"getReservesList","inputs":[],"outputs":[{"name":"","type":"address[]"}],"stateMutability":"view"},
+    {"type":"function","name":"getReserveData","inputs":[{"name":"asset","type":"address"}],"outputs":[{"name":"","type":"tuple","components":[{"name":"configuration","type":"tuple","components":[{"name":"data","type":"uint256"}]},{"name":"liquidityIndex","type":"uint128"},{"name":"variableBorrowIndex","type":"uint128"},{"name":"currentLiquidityRate","type":"uint128"},{"name":"currentVariableBorrowRate","type":"uint128"},{"name":"currentStableBorrowRate","type":"uint128"},{"name":"lastUpdateTimestamp","type":"uint40"},{"name":"aTokenAddress","type":"address"},{"name":"stableDebtTokenAddress","type":"address"},{"name":"variableDebtTokenAddress","type":"address"},{"name":"interestRateStrategyAddress","type":"address"},{"name":"id","type":"uint8"}]}],"stateMutability":"view"},
+    {"type":"event","name":"Deposit","inputs":[{"name":"reserve","type":"address","indexed":true},{"name":"user","type":"address","indexed":false},{"name":"onBehalfOf","type":"address","indexed":true},{"name":"amount","type":"uint256","indexed":false},{"name":"referral","type":"uint16","indexed":true}],"anonymous":false},
+    {"type":"event","name":"Borrow","inputs":[{"name":"reserve","type":"address","indexed":true},{"name":"user","type":"address","indexed":false},{"name":"onBehalfOf","type":"address","indexed":true},{"name":"amount","type":"uint256","indexed":false},{"name":"borrowRateMode","type":"uint256","indexed":false},{"name":"borrowRate","type":"uint256","indexed":false},{"name":"referral","type":"uint16","indexed":true}],"anonymous":false},
+    {"type":"event","name":"Repay","inputs":[{"name":"reserve","type":"address","indexed":true},{"name":"user","type":"address","indexed":true},{"name":"repayer","type":"address","indexed":true},{"name":"amount","type":"uint256","indexed":false}],"anonymous":false},
+    {"type":"event","name":"LiquidationCall","inputs":[{"name":"collateralAsset","type":"address","indexed":true},{"name":"debtAsset","type":"address","indexed":true},{"name":"user","type":"address","indexed":true},{"name":"debtToCover","type":"uint256","indexed":false},{"name":"liquidatedCollateralAmount","type":"uint256","indexed":false},{"name":"liquidator","type":"address","indexed":false},{"name":"receiveAToken","type":"bool","indexed":false}],"anonymous":false}
+]"#;
+
+pub const STAKING_REWARDS_ABI: &str = r#"[
+    {"type":"function","name":"stake","inputs":[{"name":"amount","type":"uint256"}],"outputs":[],"stateMutability":"nonpayable"},
+    {"type":"function","name":"withdraw","inputs":[{"name":"amount","type":"uint256"}],"outputs":[],"stateMutability":"nonpayable"},
+    {"type":"function","name":"getReward","inputs":[],"outputs":[],"stateMutability":"nonpayable"},
+    {"type":"function","name":"totalSupply","inputs":[],"outputs":[{"name":"","type":"uint256"}],"stateMutability":"view"},
+    {"type":"function","name":"rewardRate","inputs":[],"outputs":[{"name":"","type":"uint256"}],"stateMutability":"view"},
+    {"type":"function","name":"rewardPerToken","inputs":[],"outputs":[{"name":"","type":"uint256"}],"stateMutability":"view"},
+    {"type":"function","name":"stakingToken","inputs":[],"outputs":[{"name":"","type":"address"}],"stateMutability":"view"},
+    {"type":"function","name":"rewardsToken","inputs":[],"outputs":[{"name":"","type":"address"}],"stateMutability":"view"},
+    {"type":"event","name":"Staked","inputs":[{"name":"user","type":"address","indexed":true},{"name":"amount","type":"uint256","indexed":false}],"anonymous":false},
+    {"type":"event","name":"Withdrawn","inputs":[{"name":"user","type":"address","indexed":true},{"name":"amount","type":"uint256","indexed":false}],"anonymous":false},
+    {"type":"event","name":"RewardPaid","inputs":[{"name":"user","type":"address","indexed":true},{"name":"reward","type":"uint256","indexed":false}],"anonymous":false}
+]"#;
+
+pub const NFT_MARKETPLACE_ABI: &str = r#"[
+    {"type":"function","name":"createListing","inputs":[{"name":"nftContract","type":"address"},{"name":"tokenId","type":"uint256"},{"name":"price","type":"uint256"},{"name":"currency","type":"address"}],"outputs":[],"stateMutability":"nonpayable"},
+    {"type":"function","name":"cancelListing","inputs":[{"name":"nftContract","type":"address"},{"name":"tokenId","type":"uint256"}],"outputs":[],"stateMutability":"nonpayable"},
+    {"type":"function","name":"buyNFT","inputs":[{"name":"nftContract","type":"address"},{"name":"tokenId","type":"uint256"}],"outputs":[],"stateMutability":"payable"},
+    {"type":"function","name":"makeOffer","inputs":[{"name":"nftContract","type":"address"},{"name":"tokenId","type":"uint256"},{"name":"price","type":"uint256"},{"name":"currency","type":"address"},{"name":"expiration","type":"uint256"}],"outputs":[],"stateMutability":"nonpayable"},
+    {"type":"function","name":"acceptOffer","inputs":[{"name":"nftContract","type":"address"},{"name":"tokenId","type":"uint256"},{"name":"offerMaker","type":"address"}],"outputs":[],"stateMutability":"nonpayable"},
+    {"type":"function","name":"getListing","inputs":[{"name":"nftContract","type":"address"},{"name":"tokenId","type":"uint256"}],"outputs":[{"name":"seller","type":"address"},{"name":"price","type":"uint256"},{"name":"currency","type":"address"},{"name":"active","type":"bool"},{"name":"createdAt","type":"uint256"}],"stateMutability":"view"},
+    {"type":"function","name":"getTotalListings","inputs":[],"outputs":[{"name":"","type":"uint256"}],"stateMutability":"view"},
+    {"type":"function","name":"getListingByIndex","inputs":[{"name":"index","type":"uint256"}],"outputs":[{"name":"tokenId","type":"uint256"},{"name":"nftContract","type":"address"}],"stateMutability":"view"},
+    {"type":"function","name":"getTotalVolume","inputs":[],"outputs":[{"name":"","type":"uint256"}],"stateMutability":"view"},
+    {"type":"function","name":"getTotalSales","inputs":[],"outputs":[{"name":"","type":"uint256"}],"stateMutability":"view"},
+    {"type":"function","name":"createAuction","inputs":[{"name":"nftContract","type":"address"},{"name":"tokenId","type":"uint256"},{"name":"startingPrice","type":"uint256"},{"name":"duration","type":"uint256"}],"outputs":[],"stateMutability":"nonpayable"},
+    {"type":"function","name":"bid","inputs":[{"name":"nftContract","type":"address"},{"name":"tokenId","type":"uint256"}],"outputs":[],"stateMutability":"payable"},
+    {"type":"function","name":"endAuction","inputs":[{"name":"nftContract","type":"address"},{"name":"tokenId","type":"uint256"}],"outputs":[],"stateMutability":"nonpayable"},
+    {"type":"function","name":"getAuction","inputs":[{"name":"nftContract","type":"address"},{"name":"tokenId","type":"uint256"}],"outputs":[{"name":"highestBid","type":"uint256"},{"name":"highestBidder","type":"address"},{"name":"endTime","type":"uint256"}],"stateMutability":"view"},
+    {"type":"event","name":"ListingCreated","inputs":[{"name":"nftContract","type":"address","indexed":true},{"name":"tokenId","type":"uint256","indexed":true},{"name":"seller","type":"address","indexed":true},{"name":"price","type":"uint256","indexed":false},{"name":"currency","type":"address","indexed":false}],"anonymous":false},
+    {"type":"event","name":"ListingCancelled","inputs":[{"name":"nftContract","type":"address","indexed":true},{"name":"tokenId","type":"uint256","indexed":true}],"anonymous":false},
+    {"type":"event","name":"Sale","inputs":[{"name":"nftContract","type":"address","indexed":true},{"name":"tokenId","type":"uint256","indexed":true},{"name":"buyer","type":"address","indexed":true},{"name":"seller","type":"address","indexed":false},{"name":"price","type":"uint256","indexed":false}],"anonymous":false}
+]"#;
+
+pub const ERC1271_ABI: &str = r#"[
+    {"type":"function","name":"isValidSignature","inputs":[{"name":"hash","type":"bytes32"},{"name":"signature","type":"bytes"}],"outputs":[{"name":"magicValue","type":"bytes4"}],"stateMutability":"view"}
+]"#;
+
+pub const ENS_REGISTRY_ABI: &str = r#"[
+    {"type":"function","name":"owner","inputs":[{"name":"node","type":"bytes32"}],"outputs":[{"name":"","type":"address"}],"stateMutability":"view"},
+    {"type":"function","name":"resolver","inputs":[{"name":"node","type":"bytes32"}],"outputs":[{"name":"","type":"address"}],"stateMutability":"view"}
+]"#;
+
+pub const ENS_RESOLVER_ABI: &str = r#"[
+    {"type":"function","name":"supportsInterface","inputs":[{"name":"interfaceID","type":"bytes4"}],"outputs":[{"name":"","type":"bool"}],"stateMutability":"view"},
+    {"type":"function","name":"addr","inputs":[{"name":"node","type":"bytes32"}],"outputs":[{"name":"","type":"address"}],"stateMutability":"view"},
+    {"type":"function","name":"name","inputs":[{"name":"node","type":"bytes32"}],"outputs":[{"name":"","type":"string"}],"stateMutability":"view"},
+    {"type":"function","name":"text","inputs":[{"name":"node","type":"bytes32"},{"name":"key","type":"string"}],"outputs":[{"name":"","type":"string"}],"stateMutability":"view"},
+    {"type":"function","name":"resolve","inputs":[{"name":"name","type":"bytes"},{"name":"data","type":"bytes"}],"outputs":[{"name":"","type":"bytes"}],"stateMutability":"view"}
+]"#;
@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+use web3::types::{AccessList, BlockNumber, CallRequest, U256};
+use web3::{helpers, Transport, Web3};
+
+// eth_createAccessList measures gas against current state, which can move before inclusion
+const GAS_LIMIT_MARGIN_PER_MILLE: u64 = 1200;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessListResult {
+    pub access_list: AccessList,
+    pub gas_with_access_list: U256,
+    pub gas_without_access_list: U256,
+    pub gas_saved: U256,
+}
+
+impl AccessListResult {
+    // Declaring slots up front costs intrinsic gas, so short lists on light calls can lose money
+    pub fn saves_gas(&self) -> bool {
+        !self.access_list.is_empty() && !self.gas_saved.is_zero()
+    }
+
+    pub fn gas_limit(&self) -> U256 {
+        with_margin(self.gas_with_access_list)
+    }
+
+    // For sending without the list, when it doesn't pay for itself
+    pub fn gas_limit_without_access_list(&self) -> U256 {
+        with_margin(self.gas_without_access_list)
+    }
+}
+
+fn with_margin(gas: U256) -> U256 {
+    gas * U256::from(GAS_LIMIT_MARGIN_PER_MILLE) / U256::from(1000)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateAccessListResponse {
+    access_list: AccessList,
+    gas_used: U256,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+// Runs eth_createAccessList for the call and compares the gas it reports with a plain
+// eth_estimateGas for the same call
+pub async fn create_access_list<T: Transport>(web3: &Web3<T>, request: CallRequest) -> Result<AccessListResult, Box<dyn std::error::Error>> {
+    let params = vec![helpers::serialize(&request), helpers::serialize(&BlockNumber::Latest)];
+    let response = web3.transport().execute("eth_createAccessList", params).await?;
+    let response: CreateAccessListResponse = serde_json::from_value(response)?;
+
+    if let Some(error) = response.error {
+        return Err(format!("eth_createAccessList failed: {}", error).into());
+    }
+
+    let plain_request = CallRequest {
+        access_list: None,
+        ..request
+    };
+    let gas_without_access_list = web3.eth().estimate_gas(plain_request, None).await?;
+
+    Ok(AccessListResult {
+        access_list: response.access_list,
+        gas_with_access_list: response.gas_used,
+        gas_without_access_list,
+        gas_saved: gas_without_access_list.saturating_sub(response.gas_used),
+    })
+}
@@ -0,0 +1,140 @@
+use web3::signing::keccak256;
+use web3::types::Address;
+
+// EIP-55 checksum form, e.g. 0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed
+pub fn to_checksum(address: &Address) -> String {
+    checksum_with_prefix(address, "")
+}
+
+// EIP-1191: the chain id is mixed into the hash, so a checksum for one chain fails on
+// another. Only chains that adopted it (RSK mainnet 30, testnet 31) expect this form;
+// everywhere else use to_checksum.
+pub fn to_chain_checksum(address: &Address, chain_id: u64) -> String {
+    checksum_with_prefix(address, &format!("{}0x", chain_id))
+}
+
+fn checksum_with_prefix(address: &Address, hash_prefix: &str) -> String {
+    let lower = hex::encode(address.as_bytes());
+    let hash = keccak256(format!("{}{}", hash_prefix, lower).as_bytes());
+
+    let mut checksummed = String::with_capacity(42);
+    checksummed.push_str("0x");
+    for (i, c) in lower.chars().enumerate() {
+        let nibble = (hash[i / 2] >> if i % 2 == 0 { 4 } else { 0 }) & 0x0f;
+        if c.is_ascii_alphabetic() && nibble >= 8 {
+            checksummed.push(c.to_ascii_uppercase());
+        } else {
+            checksummed.push(c);
+        }
+    }
+    checksummed
+}
+
+// Parses a 0x-prefixed, 40 hex digit address. All-lowercase and all-uppercase input
+// carries no checksum and is accepted as is; mixed case must be a valid EIP-55 checksum.
+pub fn parse_address(input: &str) -> Result<Address, Box<dyn std::error::Error>> {
+    parse(input, to_checksum)
+}
+
+// As parse_address, but mixed-case input must carry the EIP-1191 checksum for `chain_id`
+pub fn parse_chain_address(input: &str, chain_id: u64) -> Result<Address, Box<dyn std::error::Error>> {
+    parse(input, |address| to_chain_checksum(address, chain_id))
+}
+
+fn parse(input: &str, checksum: impl Fn(&Address) -> String) -> Result<Address, Box<dyn std::error::Error>> {
+    let input = input.trim();
+    let digits = input.strip_prefix("0x").ok_or_else(|| format!("Address {} must start with 0x", input))?;
+    if digits.len() != 40 {
+        return Err(format!("Address {} must have 40 hex digits, found {}", input, digits.len()).into());
+    }
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Address {} contains non-hex characters", input).into());
+    }
+
+    let address = Address::from_slice(&hex::decode(digits)?);
+
+    let has_lower = digits.chars().any(|c| c.is_ascii_lowercase());
+    let has_upper = digits.chars().any(|c| c.is_ascii_uppercase());
+    if has_lower && has_upper {
+        let expected = checksum(&address);
+        if expected[2..] != *digits {
+            return Err(format!("Address {} has an invalid checksum, expected {}", input, expected).into());
+        }
+    }
+
+    Ok(address)
+}
+
+pub fn is_valid_checksum(input: &str) -> bool {
+    parse_address(input).map(|address| to_checksum(&address) == input).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The examples from EIP-55
+    const EIP55_ADDRESSES: [&str; 8] = [
+        "0x52908400098527886E0F7030069857D2E4169EE7",
+        "0x8617E340B3D01FA5F11F306F4090FD50E238070D",
+        "0xde709f2102306220921060314715629080e2fb77",
+        "0x27b1fdb04752bbc536007a920d24acb045561c26",
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+        "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+        "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+        "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+    ];
+
+    // The RSK mainnet examples from EIP-1191
+    const EIP1191_CHAIN_30_ADDRESSES: [&str; 8] = [
+        "0x5aaEB6053f3e94c9b9a09f33669435E7ef1bEAeD",
+        "0xFb6916095cA1Df60bb79ce92cE3EA74c37c5d359",
+        "0xDBF03B407c01E7CD3cBea99509D93F8Dddc8C6FB",
+        "0xD1220A0Cf47c7B9BE7a2e6ba89F429762E7B9adB",
+        "0x52908400098527886E0F7030069857D2E4169ee7",
+        "0x8617E340b3D01Fa5f11f306f4090fd50E238070D",
+        "0x27b1FdB04752BBc536007A920D24ACB045561c26",
+        "0xDe709F2102306220921060314715629080e2FB77",
+    ];
+
+    // Some of the RSK testnet examples from EIP-1191
+    const EIP1191_CHAIN_31_ADDRESSES: [&str; 4] = [
+        "0xFb6916095CA1dF60bb79CE92ce3Ea74C37c5D359",
+        "0xdbF03B407C01E7cd3cbEa99509D93f8dDDc8C6fB",
+        "0xd1220a0CF47c7B9Be7A2E6Ba89f429762E7b9adB",
+        "0x52908400098527886E0F7030069857D2e4169EE7",
+    ];
+
+    #[test]
+    fn eip55_examples() {
+        for expected in EIP55_ADDRESSES {
+            let address = parse_address(expected).unwrap();
+            assert_eq!(to_checksum(&address).to_lowercase(), expected.to_lowercase());
+            if expected[2..].chars().any(|c| c.is_ascii_lowercase()) && expected[2..].chars().any(|c| c.is_ascii_uppercase()) {
+                assert_eq!(to_checksum(&address), expected);
+                assert!(is_valid_checksum(expected));
+            }
+        }
+    }
+
+    #[test]
+    fn eip55_rejects_a_wrong_checksum() {
+        let flipped = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD";
+        assert!(parse_address(flipped).is_err());
+        assert!(!is_valid_checksum(flipped));
+        // Single-case input carries no checksum
+        assert!(parse_address(&flipped.to_lowercase()).is_ok());
+    }
+
+    #[test]
+    fn eip1191_examples() {
+        for (chain_id, addresses) in [(30, &EIP1191_CHAIN_30_ADDRESSES[..]), (31, &EIP1191_CHAIN_31_ADDRESSES[..])] {
+            for expected in addresses {
+                let address = parse_chain_address(expected, chain_id).unwrap();
+                assert_eq!(to_chain_checksum(&address, chain_id), *expected);
+            }
+        }
+        // A chain 30 checksum is not a valid EIP-55 checksum
+        assert!(parse_address(EIP1191_CHAIN_30_ADDRESSES[0]).is_err());
+    }
+}
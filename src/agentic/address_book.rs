@@ -0,0 +1,179 @@
+use std::collections::{BTreeMap, BTreeSet};
+use serde::{Deserialize, Serialize};
+use web3::types::Address;
+
+use super::address;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContactTrust {
+    Trusted,
+    KnownContract,
+    // Added but not yet vetted; sends to it still raise a warning
+    New,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Contact {
+    pub label: String,
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+    // chain id -> address; a counterparty can use different addresses per chain
+    pub addresses: BTreeMap<u64, Address>,
+    #[serde(default)]
+    pub notes: String,
+    pub trust: ContactTrust,
+}
+
+impl Contact {
+    pub fn new(label: &str, chain_id: u64, address: Address, trust: ContactTrust) -> Self {
+        let mut addresses = BTreeMap::new();
+        addresses.insert(chain_id, address);
+        Self {
+            label: label.to_string(),
+            tags: BTreeSet::new(),
+            addresses,
+            notes: String::new(),
+            trust,
+        }
+    }
+
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tags.insert(tag.to_string());
+        self
+    }
+
+    pub fn with_address(mut self, chain_id: u64, address: Address) -> Self {
+        self.addresses.insert(chain_id, address);
+        self
+    }
+
+    pub fn with_notes(mut self, notes: &str) -> Self {
+        self.notes = notes.to_string();
+        self
+    }
+}
+
+// Contacts keyed by label, which is unique ignoring case
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AddressBook {
+    contacts: BTreeMap<String, Contact>,
+}
+
+impl AddressBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, contact: Contact) -> Result<(), Box<dyn std::error::Error>> {
+        if contact.label.trim().is_empty() {
+            return Err("Contact label must not be empty".into());
+        }
+        if contact.addresses.is_empty() {
+            return Err(format!("Contact {} has no addresses", contact.label).into());
+        }
+        // One address, one contact per chain, so labels in history are unambiguous
+        for (chain_id, address) in &contact.addresses {
+            if let Some(existing) = self.find(*chain_id, *address) {
+                if key(&existing.label) != key(&contact.label) {
+                    return Err(format!("{:?} on chain {} is already saved as {}", address, chain_id, existing.label).into());
+                }
+            }
+        }
+        self.contacts.insert(key(&contact.label), contact);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, label: &str) -> Option<Contact> {
+        self.contacts.remove(&key(label))
+    }
+
+    pub fn get(&self, label: &str) -> Option<&Contact> {
+        self.contacts.get(&key(label))
+    }
+
+    pub fn get_mut(&mut self, label: &str) -> Option<&mut Contact> {
+        self.contacts.get_mut(&key(label))
+    }
+
+    pub fn contacts(&self) -> impl Iterator<Item = &Contact> {
+        self.contacts.values()
+    }
+
+    pub fn with_tag<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = &'a Contact> {
+        self.contacts.values().filter(move |contact| contact.tags.contains(tag))
+    }
+
+    pub fn find(&self, chain_id: u64, address: Address) -> Option<&Contact> {
+        self.contacts.values().find(|contact| contact.addresses.get(&chain_id) == Some(&address))
+    }
+
+    pub fn label_for(&self, chain_id: u64, address: Address) -> Option<&str> {
+        self.find(chain_id, address).map(|contact| contact.label.as_str())
+    }
+
+    // Adds contacts whose labels are not here yet, skipping any whose address is already
+    // saved under another label; existing contacts are kept as they are
+    pub fn merge(&mut self, other: &AddressBook) {
+        for (label, contact) in &other.contacts {
+            if !self.contacts.contains_key(label) {
+                let _ = self.insert(contact.clone());
+            }
+        }
+    }
+}
+
+fn key(label: &str) -> String {
+    label.trim().to_lowercase()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecipientWarning {
+    // Not a contact, not one of our wallets, and never sent to before
+    NeverSeen,
+    // In the address book but still marked New
+    UnvettedContact,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecipientCheck {
+    pub address: Address,
+    pub chain_id: u64,
+    pub label: Option<String>,
+    pub trust: Option<ContactTrust>,
+    pub own_wallet: bool,
+    pub previously_sent: bool,
+    pub warnings: Vec<RecipientWarning>,
+}
+
+impl RecipientCheck {
+    pub fn is_clean(&self) -> bool {
+        self.warnings.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Outgoing,
+    Incoming,
+    // Sent from one of our wallets to itself, e.g. a cancellation
+    SelfTransfer,
+}
+
+#[derive(Debug, Clone)]
+pub struct Counterparty {
+    pub address: Option<Address>,
+    // Contact label, or the wallet name for our own wallets
+    pub label: Option<String>,
+    pub direction: Direction,
+}
+
+impl Counterparty {
+    // The label when there is one, else the address; contract creations have neither
+    pub fn display(&self) -> String {
+        match (&self.label, &self.address) {
+            (Some(label), _) => label.clone(),
+            (None, Some(counterparty)) => address::to_checksum(counterparty),
+            (None, None) => "contract creation".to_string(),
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+use std::path::Path;
+use aes_gcm::aead::{Aead, NewAead, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use web3::types::Address;
+
+use super::keystore_dir::{self, BackupContents};
+use super::wallet_manager::{EncryptedKey, EncryptedSeed, Wallet};
+
+pub const BUNDLE_FORMAT: &str = "agentic-wallet-backup";
+pub const BUNDLE_VERSION: u32 = 1;
+
+const KDF: &str = "pbkdf2-sha256";
+const PBKDF2_ITERATIONS: u32 = 600_000;
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+
+// Everything needed to open the bundle, readable without the password. The header is
+// bound to the ciphertext as associated data, so it can't be edited undetected.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleHeader {
+    pub format: String,
+    pub version: u32,
+    pub created_at: u64,
+    pub wallet_count: usize,
+    pub kdf: String,
+    pub kdf_iterations: u32,
+    pub salt: String,
+    pub nonce: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupBundle {
+    pub header: BundleHeader,
+    pub ciphertext: String,
+    // sha256 over the header and ciphertext; tells a damaged file apart from a wrong password
+    pub checksum: String,
+}
+
+impl BackupBundle {
+    pub fn seal(contents: &BackupContents, password: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let salt: [u8; 32] = rand::random();
+        let nonce: [u8; 12] = rand::random();
+
+        let header = BundleHeader {
+            format: BUNDLE_FORMAT.to_string(),
+            version: BUNDLE_VERSION,
+            created_at: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs(),
+            wallet_count: contents.wallets.len(),
+            kdf: KDF.to_string(),
+            kdf_iterations: PBKDF2_ITERATIONS,
+            salt: hex::encode(salt),
+            nonce: hex::encode(nonce),
+        };
+        let header_bytes = serde_json::to_vec(&header)?;
+
+        let plaintext = serde_json::to_vec(contents)?;
+        let cipher = Aes256Gcm::new(Key::from_slice(&derive_key(password, &salt, PBKDF2_ITERATIONS)?));
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: &plaintext, aad: &header_bytes })
+            .map_err(|_| "Failed to encrypt backup bundle")?;
+
+        Ok(Self {
+            checksum: checksum(&header_bytes, &ciphertext),
+            header,
+            ciphertext: hex::encode(ciphertext),
+        })
+    }
+
+    pub fn open(&self, password: &str) -> Result<BackupContents, Box<dyn std::error::Error>> {
+        self.verify()?;
+
+        let header_bytes = serde_json::to_vec(&self.header)?;
+        let ciphertext = hex::decode(&self.ciphertext)?;
+        let salt = hex::decode(&self.header.salt)?;
+        let nonce = hex::decode(&self.header.nonce)?;
+        if nonce.len() != 12 {
+            return Err("Backup bundle nonce must be 12 bytes".into());
+        }
+
+        let cipher = Aes256Gcm::new(Key::from_slice(&derive_key(password, &salt, self.header.kdf_iterations)?));
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: &header_bytes })
+            .map_err(|_| "Could not decrypt backup bundle, wrong password?")?;
+
+        BackupContents::parse(std::str::from_utf8(&plaintext)?)
+    }
+
+    // Checks format, version and checksum without needing the password
+    pub fn verify(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.header.format != BUNDLE_FORMAT {
+            return Err(format!("Not a wallet backup bundle (format {})", self.header.format).into());
+        }
+        if self.header.version > BUNDLE_VERSION {
+            return Err(format!("Backup bundle version {} is newer than this build supports ({})", self.header.version, BUNDLE_VERSION).into());
+        }
+        if self.header.kdf != KDF {
+            return Err(format!("Unsupported backup bundle KDF {}", self.header.kdf).into());
+        }
+
+        let header_bytes = serde_json::to_vec(&self.header)?;
+        if checksum(&header_bytes, &hex::decode(&self.ciphertext)?) != self.checksum {
+            return Err("Backup bundle checksum mismatch, the file is damaged".into());
+        }
+        Ok(())
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+        keystore_dir::write_atomic(path.as_ref(), self.to_json()?.as_bytes())
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+fn derive_key(password: &str, salt: &[u8], iterations: u32) -> Result<[u8; 32], Box<dyn std::error::Error>> {
+    if iterations == 0 || iterations > MAX_PBKDF2_ITERATIONS {
+        return Err(format!("Backup bundle iteration count {} is out of range", iterations).into());
+    }
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2::<hmac::Hmac<Sha256>>(password.as_bytes(), salt, iterations, &mut key);
+    Ok(key)
+}
+
+fn checksum(header: &[u8], ciphertext: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(header);
+    hasher.update(ciphertext);
+    hex::encode(hasher.finalize())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestoreMode {
+    // Add wallets missing here; on a conflict the wallet already here wins
+    Merge,
+    // Make the wallet set match the backup; on a conflict the backup wins
+    Replace,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictResolution {
+    KeptExisting,
+    TookBackup,
+}
+
+#[derive(Debug, Clone)]
+pub struct AddressConflict {
+    pub address: Address,
+    pub differences: Vec<String>,
+    pub resolution: ConflictResolution,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RestoreReport {
+    pub dry_run: bool,
+    pub added: Vec<Address>,
+    pub unchanged: Vec<Address>,
+    pub conflicts: Vec<AddressConflict>,
+    // Only in Replace mode: wallets here that the backup doesn't have
+    pub removed: Vec<Address>,
+    pub seeds_added: Vec<String>,
+}
+
+impl RestoreReport {
+    pub fn has_changes(&self) -> bool {
+        !self.added.is_empty()
+            || !self.removed.is_empty()
+            || !self.seeds_added.is_empty()
+            || self.conflicts.iter().any(|conflict| conflict.resolution == ConflictResolution::TookBackup)
+    }
+}
+
+// Works out what restoring `backup` over the current state would do, without changing
+// anything. Seeds are only ever added: dropping one would orphan every account derived
+// from it, so Replace leaves seeds the backup doesn't know about in place.
+pub fn plan_restore(
+    wallets: &HashMap<Address, Wallet>,
+    keystore: &HashMap<Address, EncryptedKey>,
+    seeds: &HashMap<String, EncryptedSeed>,
+    backup: &BackupContents,
+    mode: RestoreMode,
+) -> RestoreReport {
+    let mut report = RestoreReport::default();
+
+    for (key, backup_wallet) in &backup.wallets {
+        let address = backup_wallet.address;
+        match wallets.get(key) {
+            None => report.added.push(address),
+            Some(existing) => {
+                let differences = differences(existing, keystore.get(key), backup_wallet, backup.keystore.get(key));
+                if differences.is_empty() {
+                    report.unchanged.push(address);
+                } else {
+                    let resolution = match mode {
+                        RestoreMode::Merge => ConflictResolution::KeptExisting,
+                        RestoreMode::Replace => ConflictResolution::TookBackup,
+                    };
+                    report.conflicts.push(AddressConflict { address, differences, resolution });
+                }
+            }
+        }
+    }
+
+    if mode == RestoreMode::Replace {
+        report.removed = wallets
+            .iter()
+            .filter(|(key, _)| !backup.wallets.contains_key(*key))
+            .map(|(_, wallet)| wallet.address)
+            .collect();
+    }
+
+    report.seeds_added = backup.seeds.keys().filter(|seed_id| !seeds.contains_key(*seed_id)).cloned().collect();
+
+    report.added.sort();
+    report.unchanged.sort();
+    report.conflicts.sort_by_key(|conflict| conflict.address);
+    report.removed.sort();
+    report.seeds_added.sort();
+    report
+}
+
+fn differences(existing: &Wallet, existing_key: Option<&EncryptedKey>, backup: &Wallet, backup_key: Option<&EncryptedKey>) -> Vec<String> {
+    let mut differences = Vec::new();
+
+    match (existing_key, backup_key) {
+        (Some(existing_key), Some(backup_key)) => {
+            // Same key under another password, or a different key altogether; either way
+            // only one of them can be kept
+            if existing_key.encrypted_private_key != backup_key.encrypted_private_key {
+                differences.push("encrypted key differs".to_string());
+            }
+        }
+        (Some(_), None) => differences.push("backup has no key for this wallet".to_string()),
+        (None, Some(_)) => differences.push("only the backup has a key for this wallet".to_string()),
+        (None, None) => {}
+    }
+
+    if existing.name != backup.name {
+        differences.push(format!("name \"{}\" vs \"{}\" in backup", existing.name, backup.name));
+    }
+    if existing.derivation_path != backup.derivation_path {
+        differences.push("derivation path differs".to_string());
+    }
+    if existing.transactions.len() != backup.transactions.len() {
+        differences.push(format!("{} transactions vs {} in backup", existing.transactions.len(), backup.transactions.len()));
+    }
+    if existing.tokens != backup.tokens {
+        differences.push("tracked tokens differ".to_string());
+    }
+
+    differences
+}
@@ -3,13 +3,28 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 use web3::types::{Address, U256, H256, BlockNumber, Transaction, Log, Filter};
 use web3::Web3;
+use web3::ethabi::{Contract, Token};
 use web3::transports::Http;
+use web3::{DuplexTransport, Transport};
 use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct BlockchainUtils {
-    web3: Arc<Web3<Http>>,
+use futures::StreamExt;
+
+use super::abi;
+use super::ens::{EnsResolver, NameOrAddress};
+use super::events::{self, DecodedLog};
+use super::fee_oracle::{FeeEstimate, FeeOracle, FeeSpeed, PercentileFee};
+use super::log_fetcher::{self, LogFetcher, LogFetcherConfig, LogQuery};
+use super::provider_pool::{EndpointHealth, PoolConfig, ProviderPool};
+use super::signature::{self, Signature};
+use super::smart_contract::SmartContract;
+
+#[derive(Debug, Clone)]
+pub struct BlockchainUtils<T: Transport = Http> {
+    web3: Arc<Web3<T>>,
     network_info: Arc<Mutex<NetworkInfo>>,
+    fee_oracle: Arc<FeeOracle<T>>,
+    ens: EnsResolver<T>,
+    log_fetcher: LogFetcher<T>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,7 +33,13 @@ pub struct NetworkInfo {
     pub network_name: String,
     pub rpc_url: String,
     pub block_time: u64,
-    pub gas_price: U256,
+    // None on nodes without eth_feeHistory (pre-London chains, some L2s and devnets)
+    #[serde(default)]
+    pub base_fee_per_gas: Option<U256>,
+    #[serde(default)]
+    pub priority_fee_percentiles: Option<Vec<PercentileFee>>,
+    #[serde(default)]
+    pub endpoints: Vec<EndpointHealth>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +49,10 @@ pub struct TransactionInfo {
     pub to: Option<Address>,
     pub value: U256,
     pub gas_price: U256,
+    #[serde(default)]
+    pub max_fee_per_gas: Option<U256>,
+    #[serde(default)]
+    pub max_priority_fee_per_gas: Option<U256>,
     pub gas_limit: U256,
     pub gas_used: Option<U256>,
     pub status: Option<bool>,
@@ -77,30 +102,111 @@ pub struct TokenInfo {
     pub owner: Option<Address>,
 }
 
-impl BlockchainUtils {
+impl BlockchainUtils<Http> {
     pub async fn new(rpc_url: &str) -> Result<Self, Box<dyn std::error::Error>> {
         let transport = Http::new(rpc_url)?;
+        Self::with_transport(transport, rpc_url).await
+    }
+}
+
+impl BlockchainUtils<ProviderPool> {
+    pub async fn new_pooled(rpc_urls: &[&str], config: PoolConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let pool = ProviderPool::new(rpc_urls, config)?;
+        pool.refresh_health().await;
+
+        let utils = Self::with_transport(pool, &rpc_urls.join(",")).await?;
+        utils.refresh_endpoint_health().await;
+        Ok(utils)
+    }
+
+    pub async fn refresh_endpoint_health(&self) -> Vec<EndpointHealth> {
+        let health = self.web3.transport().refresh_health().await;
+        self.network_info.lock().await.endpoints = health.clone();
+        health
+    }
+
+    // Balance read that `required` endpoints must agree on, pinned to a block all of them have seen
+    pub async fn get_balance_with_quorum(&self, address: Address, required: usize) -> Result<U256, Box<dyn std::error::Error>> {
+        let pool = self.web3.transport();
+        let block = match pool.common_block_height() {
+            Some(height) => serde_json::json!(format!("{:#x}", height)),
+            None => serde_json::json!("latest"),
+        };
+
+        let balance = pool.quorum_request("eth_getBalance", vec![serde_json::json!(address), block], required).await?;
+        Ok(serde_json::from_value(balance)?)
+    }
+}
+
+impl<T: Transport> BlockchainUtils<T> {
+    // Nothing else can hold the web3 built here, so the oracle for it is created alongside;
+    // share it onwards through fee_oracle()
+    pub async fn with_transport(transport: T, endpoint: &str) -> Result<Self, Box<dyn std::error::Error>> {
         let web3 = Arc::new(Web3::new(transport));
+        let fee_oracle = Arc::new(FeeOracle::new(web3.clone()));
+        Self::with_web3(web3, fee_oracle, endpoint).await
+    }
 
+    // `fee_oracle` should be the one the wallet manager and contracts on this web3 share
+    pub async fn with_web3(web3: Arc<Web3<T>>, fee_oracle: Arc<FeeOracle<T>>, endpoint: &str) -> Result<Self, Box<dyn std::error::Error>> {
         let chain_id = web3.eth().chain_id().await?;
         let network_name = Self::get_network_name(chain_id);
-        let gas_price = web3.eth().gas_price().await?;
-        let block_time = Self::estimate_block_time(&web3).await?;
+        let fees = fee_oracle.fee_history().await.ok();
+        let block_time = Self::estimate_block_time(&web3).await;
 
         let network_info = NetworkInfo {
             chain_id,
             network_name,
-            rpc_url: rpc_url.to_string(),
+            rpc_url: endpoint.to_string(),
             block_time,
-            gas_price,
+            base_fee_per_gas: fees.as_ref().map(|fees| fees.next_base_fee_per_gas),
+            priority_fee_percentiles: fees.map(|fees| fees.priority_fee_percentiles),
+            endpoints: Vec::new(),
         };
 
         Ok(Self {
+            ens: EnsResolver::new(web3.clone()),
+            log_fetcher: LogFetcher::new(web3.clone()),
             web3,
             network_info: Arc::new(Mutex::new(network_info)),
+            fee_oracle,
         })
     }
 
+    pub fn with_ens_registry(mut self, registry: Address) -> Self {
+        self.ens = self.ens.with_registry(registry);
+        self
+    }
+
+    pub fn ens(&self) -> &EnsResolver<T> {
+        &self.ens
+    }
+
+    // Chunk sizes and concurrency for get_past_events and get_contract_events; tune these
+    // to the provider's getLogs limits
+    pub fn with_log_fetcher_config(mut self, config: LogFetcherConfig) -> Self {
+        self.log_fetcher = self.log_fetcher.with_config(config);
+        self
+    }
+
+    // For backfills too large to hold in memory; see LogFetcher::backfill
+    pub fn log_fetcher(&self) -> &LogFetcher<T> {
+        &self.log_fetcher
+    }
+
+    pub async fn resolve(&self, target: &NameOrAddress) -> Result<Address, Box<dyn std::error::Error>> {
+        self.ens.resolve(target).await
+    }
+
+    // Verified primary name, see EnsResolver::lookup_address
+    pub async fn lookup_address(&self, address: Address) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        self.ens.lookup_address(address).await
+    }
+
+    pub async fn get_text_record(&self, name: &str, key: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        self.ens.text(name, key).await
+    }
+
     fn get_network_name(chain_id: U256) -> String {
         match chain_id.as_u64() {
             1 => "Ethereum Mainnet".to_string(),
@@ -118,7 +224,15 @@ impl BlockchainUtils {
         }
     }
 
-    async fn estimate_block_time(web3: &Web3<Http>) -> u64 {
+    pub fn web3(&self) -> Arc<Web3<T>> {
+        self.web3.clone()
+    }
+
+    pub fn fee_oracle(&self) -> Arc<FeeOracle<T>> {
+        self.fee_oracle.clone()
+    }
+
+    async fn estimate_block_time(web3: &Web3<T>) -> u64 {
         // Estimate average block time by checking recent blocks
         let latest_block = web3.eth().block_number().await.unwrap_or(U256::zero());
         if latest_block < U256::from(10) {
@@ -149,11 +263,12 @@ impl BlockchainUtils {
     }
 
     pub async fn update_network_info(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let gas_price = self.web3.eth().gas_price().await?;
-        let block_time = Self::estimate_block_time(&self.web3).await?;
+        let fees = self.fee_oracle.fee_history().await.ok();
+        let block_time = Self::estimate_block_time(&self.web3).await;
 
         let mut network_info = self.network_info.lock().await;
-        network_info.gas_price = gas_price;
+        network_info.base_fee_per_gas = fees.as_ref().map(|fees| fees.next_base_fee_per_gas);
+        network_info.priority_fee_percentiles = fees.map(|fees| fees.priority_fee_percentiles);
         network_info.block_time = block_time;
 
         Ok(())
@@ -195,6 +310,8 @@ impl BlockchainUtils {
             to: tx.to,
             value: tx.value,
             gas_price: tx.gas_price.unwrap_or(U256::zero()),
+            max_fee_per_gas: tx.max_fee_per_gas,
+            max_priority_fee_per_gas: tx.max_priority_fee_per_gas,
             gas_limit: tx.gas.unwrap_or(U256::zero()),
             gas_used: receipt.as_ref().map(|r| r.gas_used),
             status: receipt.as_ref().and_then(|r| r.status.map(|s| s.as_u64() == 1)),
@@ -254,19 +371,17 @@ impl BlockchainUtils {
     }
 
     pub async fn get_token_info(&self, address: Address) -> Result<TokenInfo, Box<dyn std::error::Error>> {
-        let contract = super::smart_contract::SmartContract::new(address, vec![], self.web3.clone());
+        let contract = SmartContract::read_only(address, abi::ERC20_ABI.as_bytes().to_vec(), self.web3.clone())?;
 
-        let name_bytes = contract.call("name", vec![]).await?;
-        let name = String::from_utf8_lossy(&name_bytes).to_string();
+        let name = abi::string_at(&contract.call("name", vec![]).await?, 0)?;
+        let symbol = abi::string_at(&contract.call("symbol", vec![]).await?, 0)?;
+        let decimals: u8 = abi::uint_at(&contract.call("decimals", vec![]).await?, 0)?.as_u64() as u8;
+        let total_supply = abi::uint_at(&contract.call("totalSupply", vec![]).await?, 0)?;
 
-        let symbol_bytes = contract.call("symbol", vec![]).await?;
-        let symbol = String::from_utf8_lossy(&symbol_bytes).to_string();
-
-        let decimals: u8 = self.decode_uint256(&contract.call("decimals", vec![]).await?).as_u64() as u8;
-        let total_supply = self.decode_uint256(&contract.call("totalSupply", vec![]).await?);
-
-        let owner = contract.call("owner", vec![]).await.ok()
-            .map(|data| self.decode_address(&data));
+        let owner = match contract.call("owner", vec![]).await {
+            Ok(tokens) => abi::address_at(&tokens, 0).ok(),
+            Err(_) => None,
+        };
 
         Ok(TokenInfo {
             address,
@@ -278,6 +393,7 @@ impl BlockchainUtils {
         })
     }
 
+    // A single eth_getLogs; wide block ranges should go through log_fetcher instead
     pub async fn get_logs(&self, filter: Filter) -> Result<Vec<Log>, Box<dyn std::error::Error>> {
         let logs = self.web3.eth().logs(filter).await?;
         Ok(logs)
@@ -293,6 +409,10 @@ impl BlockchainUtils {
         Ok(gas_price)
     }
 
+    pub async fn get_fee_estimate(&self, speed: FeeSpeed) -> Result<FeeEstimate, Box<dyn std::error::Error>> {
+        self.fee_oracle.estimate(speed).await
+    }
+
     pub async fn get_pending_transactions(&self) -> Result<Vec<H256>, Box<dyn std::error::Error>> {
         let pending_block = self.web3.eth().block(BlockNumber::Pending).await?;
         Ok(pending_block.map(|b| b.transactions).unwrap_or_default())
@@ -348,14 +468,14 @@ impl BlockchainUtils {
         Ok(accounts)
     }
 
+    // eth_sign with one of the node's unlocked accounts; the node applies the EIP-191 prefix
     pub async fn sign_message(&self, address: Address, message: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let signature = self.web3.accounts().sign(message, &web3::signing::SecretKeyRef::new(&[0; 32])).await?;
-        Ok(signature.to_vec())
+        let signature = self.web3.eth().sign(address, web3::types::Bytes(message.to_vec())).await?;
+        Ok(Signature::from_bytes(signature.as_bytes())?.normalized().to_bytes())
     }
 
     pub async fn verify_signature(&self, message: &[u8], signature: &[u8], address: Address) -> Result<bool, Box<dyn std::error::Error>> {
-        let recovered_address = self.web3.accounts().recover(message, signature)?;
-        Ok(recovered_address == address)
+        signature::verify_message(&self.web3, address, message, signature).await
     }
 
     pub async fn get_transaction_count(&self, address: Address) -> Result<U256, Box<dyn std::error::Error>> {
@@ -389,36 +509,12 @@ impl BlockchainUtils {
         Ok(tx_hash)
     }
 
-    pub async fn get_past_events(&self, address: Address, from_block: U256, to_block: U256, topics: Option<Vec<Option<H256>>>) -> Result<Vec<Log>, Box<dyn std::error::Error>> {
-        let filter = Filter {
-            from_block: BlockNumber::Number(from_block).into(),
-            to_block: BlockNumber::Number(to_block).into(),
-            address: Some(vec![address]),
-            topics,
-            ..Default::default()
-        };
-
-        self.get_logs(filter).await
-    }
-
-    pub async fn watch_contract_events(&self, address: Address, event_signature: H256) -> Result<(), Box<dyn std::error::Error>> {
-        let filter = Filter {
-            address: Some(vec![address]),
-            topics: Some(vec![Some(event_signature)]),
-            ..Default::default()
-        };
-
-        let subscription = self.web3.eth_subscribe().subscribe_logs(filter).await?;
-        println!("Subscribed to contract events. Press Ctrl+C to stop.");
-
-        subscription.for_each(|log| async {
-            match log {
-                Ok(log) => println!("Received event: {:?}", log),
-                Err(e) => eprintln!("Error receiving event: {:?}", e),
-            }
-        }).await;
-
-        Ok(())
+    // Every log `address` emitted in the range, decoded against `contract_abi`; logs for
+    // events the ABI doesn't declare are left out
+    pub async fn get_past_events(&self, address: Address, contract_abi: &Contract, from_block: U256, to_block: U256) -> Result<Vec<DecodedLog>, Box<dyn std::error::Error>> {
+        let query = LogQuery::new(vec![address]);
+        let logs = self.log_fetcher.fetch(&query, log_fetcher::block_number(from_block)?, log_fetcher::block_number(to_block)?).await?;
+        Ok(logs.iter().filter_map(|log| events::decode_log(contract_abi, log)).collect())
     }
 
     pub async fn get_block_time(&self, block_number: U256) -> Result<U256, Box<dyn std::error::Error>> {
@@ -506,44 +602,43 @@ impl BlockchainUtils {
         Ok((total_gas_used, total_gas_limit, average_gas_usage))
     }
 
-    pub async fn decode_transaction_input(&self, input: &[u8]) -> Result<(String, Vec<String>), Box<dyn std::error::Error>> {
+    pub async fn decode_transaction_input(&self, contract_abi: &Contract, input: &[u8]) -> Result<(String, Vec<String>), Box<dyn std::error::Error>> {
         if input.len() < 4 {
             return Ok(("".to_string(), vec![]));
         }
 
-        let function_signature = &input[0..4];
+        let selector = &input[0..4];
         let params = &input[4..];
 
-        // This is a simplified implementation. In practice, you'd need a proper ABI decoder.
-        let function_name = format!("0x{}", hex::encode(function_signature));
-        let param_strings = vec![hex::encode(params)];
-
-        Ok((function_name, param_strings))
-    }
-
-    pub async fn encode_function_call(&self, function_signature: &str, params: Vec<web3::types::Bytes>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        // This is a simplified implementation. In practice, you'd use a proper ABI encoder.
-        let mut encoded = Vec::new();
-        encoded.extend_from_slice(&hex::decode(&function_signature[2..])?);
+        let function = match contract_abi.functions().find(|function| function.short_signature() == selector) {
+            Some(function) => function,
+            // Unknown selector, fall back to the raw calldata
+            None => return Ok((format!("0x{}", hex::encode(selector)), vec![hex::encode(params)])),
+        };
 
-        for param in params {
-            encoded.extend_from_slice(&param.0);
-        }
+        let tokens = function.decode_input(params)?;
+        let param_strings = function.inputs.iter()
+            .zip(tokens)
+            .map(|(param, token)| format!("{}: {}", param.name, token))
+            .collect();
 
-        Ok(encoded)
+        Ok((abi::canonical_signature(function), param_strings))
     }
 
-    pub async fn get_contract_events(&self, address: Address, event_signature: &str, from_block: U256) -> Result<Vec<Log>, Box<dyn std::error::Error>> {
-        let event_topic = web3::contract::tokens::Event::new(event_signature, vec![])?.signature();
+    pub async fn encode_function_call(&self, function_signature: &str, params: Vec<Token>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let function = abi::parse_signature(function_signature)?;
+        Ok(function.encode_input(&params)?)
+    }
 
-        let filter = Filter {
-            from_block: BlockNumber::Number(from_block).into(),
-            address: Some(vec![address]),
-            topics: Some(vec![Some(event_topic)]),
-            ..Default::default()
-        };
+    // `event_signature` names the indexed parameters, since there is no ABI to take them
+    // from, e.g. "Transfer(address indexed from, address indexed to, uint256 value)"
+    pub async fn get_contract_events(&self, address: Address, event_signature: &str, from_block: U256) -> Result<Vec<DecodedLog>, Box<dyn std::error::Error>> {
+        let event = abi::parse_event(event_signature)?;
+        let query = LogQuery::new(vec![address]).with_topics(events::topics_for(&event, &[])?);
+        let latest_block = self.web3.eth().block_number().await?.as_u64();
 
-        self.get_logs(filter).await
+        let logs = self.log_fetcher.fetch(&query, log_fetcher::block_number(from_block)?, latest_block).await?;
+        events::decode_logs(&event, &logs)
     }
 
     pub async fn simulate_transaction(&self, tx: web3::types::TransactionRequest) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
@@ -618,13 +713,26 @@ impl BlockchainUtils {
         }
         Ok(results)
     }
+}
 
-    // Helper functions for encoding/decoding
-    fn decode_address(&self, data: &[u8]) -> Address {
-        Address::from_slice(&data[12..32])
-    }
+impl<T: DuplexTransport> BlockchainUtils<T> {
+    pub async fn watch_contract_events(&self, address: Address, event_signature: H256) -> Result<(), Box<dyn std::error::Error>> {
+        let filter = Filter {
+            address: Some(vec![address]),
+            topics: Some(vec![Some(event_signature)]),
+            ..Default::default()
+        };
 
-    fn decode_uint256(&self, data: &[u8]) -> U256 {
-        U256::from_big_endian(data)
+        let subscription = self.web3.eth_subscribe().subscribe_logs(filter).await?;
+        println!("Subscribed to contract events. Press Ctrl+C to stop.");
+
+        subscription.for_each(|log| async {
+            match log {
+                Ok(log) => println!("Received event: {:?}", log),
+                Err(e) => eprintln!("Error receiving event: {:?}", e),
+            }
+        }).await;
+
+        Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,189 @@
+use std::fmt;
+use serde_json::Value;
+use web3::ethabi::{self, Contract, ParamType, Token};
+use web3::types::U256;
+
+// Selectors of the two errors the compiler emits itself
+const ERROR_STRING_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+// Solidity's Panic(uint256) codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanicCode {
+    Generic,
+    AssertionFailed,
+    ArithmeticOverflow,
+    DivisionByZero,
+    InvalidEnumValue,
+    InvalidStorageEncoding,
+    EmptyArrayPop,
+    IndexOutOfBounds,
+    OutOfMemory,
+    UninitializedFunction,
+    Unknown(U256),
+}
+
+impl PanicCode {
+    pub fn from_code(code: U256) -> Self {
+        if code > U256::from(u8::MAX) {
+            return PanicCode::Unknown(code);
+        }
+        match code.low_u32() {
+            0x00 => PanicCode::Generic,
+            0x01 => PanicCode::AssertionFailed,
+            0x11 => PanicCode::ArithmeticOverflow,
+            0x12 => PanicCode::DivisionByZero,
+            0x21 => PanicCode::InvalidEnumValue,
+            0x22 => PanicCode::InvalidStorageEncoding,
+            0x31 => PanicCode::EmptyArrayPop,
+            0x32 => PanicCode::IndexOutOfBounds,
+            0x41 => PanicCode::OutOfMemory,
+            0x51 => PanicCode::UninitializedFunction,
+            _ => PanicCode::Unknown(code),
+        }
+    }
+
+    pub fn code(&self) -> U256 {
+        let code: u8 = match self {
+            PanicCode::Generic => 0x00,
+            PanicCode::AssertionFailed => 0x01,
+            PanicCode::ArithmeticOverflow => 0x11,
+            PanicCode::DivisionByZero => 0x12,
+            PanicCode::InvalidEnumValue => 0x21,
+            PanicCode::InvalidStorageEncoding => 0x22,
+            PanicCode::EmptyArrayPop => 0x31,
+            PanicCode::IndexOutOfBounds => 0x32,
+            PanicCode::OutOfMemory => 0x41,
+            PanicCode::UninitializedFunction => 0x51,
+            PanicCode::Unknown(code) => return *code,
+        };
+        U256::from(code)
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            PanicCode::Generic => "generic compiler panic",
+            PanicCode::AssertionFailed => "assertion failed",
+            PanicCode::ArithmeticOverflow => "arithmetic overflow or underflow",
+            PanicCode::DivisionByZero => "division or modulo by zero",
+            PanicCode::InvalidEnumValue => "conversion to an invalid enum value",
+            PanicCode::InvalidStorageEncoding => "incorrectly encoded storage byte array",
+            PanicCode::EmptyArrayPop => "pop() on an empty array",
+            PanicCode::IndexOutOfBounds => "array index out of bounds",
+            PanicCode::OutOfMemory => "too much memory allocated",
+            PanicCode::UninitializedFunction => "call to an uninitialized function pointer",
+            PanicCode::Unknown(_) => "unknown panic code",
+        }
+    }
+}
+
+// Why a call or gas estimate reverted. SmartContract returns this boxed, so callers can
+// get at it with `error.downcast_ref::<ContractError>()`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContractError {
+    // require(cond, "...") or revert("...")
+    Revert(String),
+    Panic(PanicCode),
+    // A custom error declared in the contract's ABI
+    Custom { name: String, params: Vec<(String, Token)> },
+    // Revert data that matches no known error, e.g. one declared by a contract further
+    // down the call stack
+    UnknownRevert(Vec<u8>),
+    // revert() or require(cond) without a message
+    EmptyRevert,
+}
+
+impl ContractError {
+    // Decodes raw revert data; `abi` supplies the custom errors to try
+    pub fn decode(data: &[u8], abi: Option<&Contract>) -> Self {
+        if data.is_empty() {
+            return ContractError::EmptyRevert;
+        }
+        if data.len() < 4 {
+            return ContractError::UnknownRevert(data.to_vec());
+        }
+
+        let (selector, payload) = data.split_at(4);
+        if selector == ERROR_STRING_SELECTOR {
+            if let Ok(mut tokens) = ethabi::decode(&[ParamType::String], payload) {
+                if let Some(reason) = tokens.remove(0).into_string() {
+                    return ContractError::Revert(reason);
+                }
+            }
+        }
+        if selector == PANIC_SELECTOR {
+            if let Ok(mut tokens) = ethabi::decode(&[ParamType::Uint(256)], payload) {
+                if let Some(code) = tokens.remove(0).into_uint() {
+                    return ContractError::Panic(PanicCode::from_code(code));
+                }
+            }
+        }
+
+        let custom = abi.into_iter().flat_map(|abi| abi.errors()).find_map(|error| {
+            if error.signature().as_bytes()[..4] != *selector {
+                return None;
+            }
+            let tokens = error.decode(payload).ok()?;
+            let params = error.inputs.iter().map(|input| input.name.clone()).zip(tokens).collect();
+            Some(ContractError::Custom { name: error.name.clone(), params })
+        });
+        custom.unwrap_or_else(|| ContractError::UnknownRevert(data.to_vec()))
+    }
+
+    // The revert inside an eth_call or eth_estimateGas failure; None when the failure was
+    // something else, such as a transport error
+    pub fn from_web3_error(error: &web3::Error, abi: Option<&Contract>) -> Option<Self> {
+        let rpc = match error {
+            web3::Error::Rpc(rpc) => rpc,
+            _ => return None,
+        };
+
+        if let Some(data) = rpc.data.as_ref().and_then(find_revert_data) {
+            return Some(Self::decode(&data, abi));
+        }
+
+        // Some nodes leave the data out and only put the reason in the message
+        let message = rpc.message.trim();
+        if let Some(reason) = message.strip_prefix("execution reverted: ") {
+            return Some(ContractError::Revert(reason.to_string()));
+        }
+        if message == "execution reverted" || message == "revert" {
+            return Some(ContractError::EmptyRevert);
+        }
+        None
+    }
+
+    pub fn reason(&self) -> String {
+        match self {
+            ContractError::Revert(reason) => reason.clone(),
+            ContractError::Panic(code) => format!("panic 0x{:02x}: {}", code.code(), code.description()),
+            ContractError::Custom { name, params } => {
+                let params: Vec<String> = params.iter().map(|(name, token)| format!("{}: {}", name, token)).collect();
+                format!("{}({})", name, params.join(", "))
+            }
+            ContractError::UnknownRevert(data) => format!("unrecognized revert data 0x{}", hex::encode(data)),
+            ContractError::EmptyRevert => "reverted without a reason".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ContractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Execution reverted: {}", self.reason())
+    }
+}
+
+impl std::error::Error for ContractError {}
+
+// Nodes disagree on where the data goes: geth uses a hex string, Hardhat and Ganache nest
+// it in an object, and some providers prefix it with "Reverted "
+fn find_revert_data(value: &Value) -> Option<Vec<u8>> {
+    match value {
+        Value::String(text) => {
+            let text = text.strip_prefix("Reverted ").unwrap_or(text);
+            hex::decode(text.strip_prefix("0x")?).ok()
+        }
+        Value::Object(fields) => fields.get("data").and_then(find_revert_data).or_else(|| fields.values().find_map(find_revert_data)),
+        _ => None,
+    }
+}
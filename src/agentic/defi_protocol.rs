@@ -4,12 +4,23 @@ use tokio::sync::Mutex;
 use web3::types::{Address, U256, H256};
 use web3::Web3;
 use web3::transports::Http;
+use web3::Transport;
+use web3::ethabi::Token;
+
+use super::abi;
+use super::fee_oracle::FeeOracle;
+use super::nonce_manager::NonceManager;
+use super::signer::Signer;
+use super::smart_contract::{SentTransaction, SmartContract, TxOptions};
 
 #[derive(Debug, Clone)]
-pub struct DeFiProtocol {
-    web3: Arc<Web3<Http>>,
+pub struct DeFiProtocol<T: Transport = Http> {
+    web3: Arc<Web3<T>>,
     contracts: HashMap<String, Address>,
     liquidity_pools: HashMap<String, LiquidityPool>,
+    nonce_manager: Arc<NonceManager>,
+    fee_oracle: Arc<FeeOracle<T>>,
+    signer: Option<Arc<dyn Signer>>,
 }
 
 #[derive(Debug, Clone)]
@@ -40,8 +51,10 @@ pub struct YieldFarm {
     pub reward_per_token: U256,
 }
 
-impl DeFiProtocol {
-    pub fn new(web3: Arc<Web3<Http>>) -> Self {
+impl<T: Transport> DeFiProtocol<T> {
+    // `nonce_manager` and `fee_oracle` must be the ones shared by every other sender for the
+    // same accounts, e.g. WalletManager::nonce_manager() and WalletManager::fee_oracle()
+    pub fn new(web3: Arc<Web3<T>>, nonce_manager: Arc<NonceManager>, fee_oracle: Arc<FeeOracle<T>>) -> Self {
         let mut contracts = HashMap::new();
         contracts.insert("uniswap_factory".to_string(), Address::zero());
         contracts.insert("aave_lending_pool".to_string(), Address::zero());
@@ -53,18 +66,26 @@ impl DeFiProtocol {
             web3,
             contracts,
             liquidity_pools: HashMap::new(),
+            nonce_manager,
+            fee_oracle,
+            signer: None,
         }
     }
 
+    pub fn with_signer(mut self, signer: Arc<dyn Signer>) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
     pub async fn get_liquidity_pools(&self) -> Result<Vec<LiquidityPool>, Box<dyn std::error::Error>> {
         let factory_address = self.contracts.get("uniswap_factory").unwrap();
-        let factory_contract = super::smart_contract::SmartContract::new(*factory_address, vec![], self.web3.clone());
+        let factory_contract = self.contract(*factory_address, abi::UNISWAP_V2_FACTORY_ABI)?;
 
-        let all_pairs_length: U256 = self.decode_uint256(&factory_contract.call("allPairsLength", vec![]).await?);
+        let all_pairs_length: U256 = abi::uint_at(&factory_contract.call("allPairsLength", vec![]).await?, 0)?;
 
         let mut pools = Vec::new();
         for i in 0..all_pairs_length.as_u64() {
-            let pair_address: Address = self.decode_address(&factory_contract.call("allPairs", vec![self.encode_uint256(U256::from(i))]).await?);
+            let pair_address: Address = abi::address_at(&factory_contract.call("allPairs", vec![Token::Uint(U256::from(i))]).await?, 0)?;
             let pool = self.get_pool_info(pair_address).await?;
             pools.push(pool);
         }
@@ -73,14 +94,14 @@ impl DeFiProtocol {
     }
 
     pub async fn get_pool_info(&self, pool_address: Address) -> Result<LiquidityPool, Box<dyn std::error::Error>> {
-        let pool_contract = super::smart_contract::SmartContract::new(pool_address, vec![], self.web3.clone());
+        let pool_contract = self.contract(pool_address, abi::UNISWAP_V2_PAIR_ABI)?;
 
-        let token_a: Address = self.decode_address(&pool_contract.call("token0", vec![]).await?);
-        let token_b: Address = self.decode_address(&pool_contract.call("token1", vec![]).await?);
+        let token_a: Address = abi::address_at(&pool_contract.call("token0", vec![]).await?, 0)?;
+        let token_b: Address = abi::address_at(&pool_contract.call("token1", vec![]).await?, 0)?;
         let reserves = pool_contract.call("getReserves", vec![]).await?;
-        let reserve_a: U256 = self.decode_uint256(&reserves[0..32]);
-        let reserve_b: U256 = self.decode_uint256(&reserves[32..64]);
-        let total_supply: U256 = self.decode_uint256(&pool_contract.call("totalSupply", vec![]).await?);
+        let reserve_a: U256 = abi::uint_at(&reserves, 0)?;
+        let reserve_b: U256 = abi::uint_at(&reserves, 1)?;
+        let total_supply: U256 = abi::uint_at(&pool_contract.call("totalSupply", vec![]).await?, 0)?;
 
         Ok(LiquidityPool {
             token_a,
@@ -108,21 +129,21 @@ impl DeFiProtocol {
 
     pub async fn add_liquidity(&self, token_a: Address, token_b: Address, amount_a: U256, amount_b: U256) -> Result<H256, Box<dyn std::error::Error>> {
         let router_address = self.contracts.get("sushiswap_router").unwrap();
-        let router_contract = super::smart_contract::SmartContract::new(*router_address, vec![], self.web3.clone());
+        let router_contract = self.contract(*router_address, abi::UNISWAP_V2_ROUTER_ABI)?;
 
         let deadline = U256::from(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() + 3600);
 
         let tx_hash = router_contract.send_transaction(
             "addLiquidity",
             vec![
-                self.encode_address(token_a),
-                self.encode_address(token_b),
-                self.encode_uint256(amount_a),
-                self.encode_uint256(amount_b),
-                self.encode_uint256(amount_a * U256::from(95) / U256::from(100)), // 5% slippage
-                self.encode_uint256(amount_b * U256::from(95) / U256::from(100)), // 5% slippage
-                self.encode_address(Address::zero()), // to address
-                self.encode_uint256(deadline),
+                Token::Address(token_a),
+                Token::Address(token_b),
+                Token::Uint(amount_a),
+                Token::Uint(amount_b),
+                Token::Uint(amount_a * U256::from(95) / U256::from(100)), // 5% slippage
+                Token::Uint(amount_b * U256::from(95) / U256::from(100)), // 5% slippage
+                Token::Address(self.account()?), // to address
+                Token::Uint(deadline),
             ],
             U256::zero(),
         ).await?;
@@ -132,20 +153,20 @@ impl DeFiProtocol {
 
     pub async fn remove_liquidity(&self, token_a: Address, token_b: Address, liquidity: U256) -> Result<H256, Box<dyn std::error::Error>> {
         let router_address = self.contracts.get("sushiswap_router").unwrap();
-        let router_contract = super::smart_contract::SmartContract::new(*router_address, vec![], self.web3.clone());
+        let router_contract = self.contract(*router_address, abi::UNISWAP_V2_ROUTER_ABI)?;
 
         let deadline = U256::from(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() + 3600);
 
         let tx_hash = router_contract.send_transaction(
             "removeLiquidity",
             vec![
-                self.encode_address(token_a),
-                self.encode_address(token_b),
-                self.encode_uint256(liquidity),
-                self.encode_uint256(U256::from(1)), // amountAMin
-                self.encode_uint256(U256::from(1)), // amountBMin
-                self.encode_address(Address::zero()), // to address
-                self.encode_uint256(deadline),
+                Token::Address(token_a),
+                Token::Address(token_b),
+                Token::Uint(liquidity),
+                Token::Uint(U256::from(1)), // amountAMin
+                Token::Uint(U256::from(1)), // amountBMin
+                Token::Address(self.account()?), // to address
+                Token::Uint(deadline),
             ],
             U256::zero(),
         ).await?;
@@ -153,35 +174,39 @@ impl DeFiProtocol {
         Ok(tx_hash)
     }
 
-    pub async fn swap_exact_tokens_for_tokens(&self, amount_in: U256, amount_out_min: U256, path: Vec<Address>) -> Result<H256, Box<dyn std::error::Error>> {
+    // Router swaps touch many storage slots, so they go out with an access list when it saves gas
+    pub async fn swap_exact_tokens_for_tokens(&self, amount_in: U256, amount_out_min: U256, path: Vec<Address>) -> Result<SentTransaction, Box<dyn std::error::Error>> {
         let router_address = self.contracts.get("sushiswap_router").unwrap();
-        let router_contract = super::smart_contract::SmartContract::new(*router_address, vec![], self.web3.clone());
+        let router_contract = self.contract(*router_address, abi::UNISWAP_V2_ROUTER_ABI)?;
 
         let deadline = U256::from(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() + 3600);
 
-        let encoded_path = path.iter().map(|addr| self.encode_address(*addr)).collect::<Vec<_>>();
+        let encoded_path = path.iter().map(|addr| Token::Address(*addr)).collect::<Vec<_>>();
 
-        let tx_hash = router_contract.send_transaction(
+        router_contract.send_transaction_with(
             "swapExactTokensForTokens",
             vec![
-                self.encode_uint256(amount_in),
-                self.encode_uint256(amount_out_min),
-                self.encode_array(encoded_path),
-                self.encode_address(Address::zero()), // to address
-                self.encode_uint256(deadline),
+                Token::Uint(amount_in),
+                Token::Uint(amount_out_min),
+                Token::Array(encoded_path),
+                Token::Address(self.account()?), // to address
+                Token::Uint(deadline),
             ],
             U256::zero(),
-        ).await?;
-
-        Ok(tx_hash)
+            Self::access_list_options(),
+        ).await
     }
 
     pub async fn get_lending_pools(&self) -> Result<Vec<LendingPool>, Box<dyn std::error::Error>> {
         let lending_pool_address = self.contracts.get("aave_lending_pool").unwrap();
-        let lending_contract = super::smart_contract::SmartContract::new(*lending_pool_address, vec![], self.web3.clone());
+        let lending_contract = self.contract(*lending_pool_address, abi::AAVE_LENDING_POOL_ABI)?;
 
-        // This is a simplified implementation. In reality, you'd need to query all reserves
-        let reserves = vec![Address::from_low_u64_be(1), Address::from_low_u64_be(2)]; // Mock reserves
+        let reserves: Vec<Address> = abi::token_at(&lending_contract.call("getReservesList", vec![]).await?, 0)?
+            .into_array()
+            .ok_or("getReservesList did not return an array")?
+            .into_iter()
+            .filter_map(|token| token.into_address())
+            .collect();
 
         let mut pools = Vec::new();
         for reserve in reserves {
@@ -194,10 +219,17 @@ impl DeFiProtocol {
 
     pub async fn get_lending_pool_info(&self, asset: Address) -> Result<LendingPool, Box<dyn std::error::Error>> {
         let lending_pool_address = self.contracts.get("aave_lending_pool").unwrap();
-        let lending_contract = super::smart_contract::SmartContract::new(*lending_pool_address, vec![], self.web3.clone());
+        let lending_contract = self.contract(*lending_pool_address, abi::AAVE_LENDING_POOL_ABI)?;
+
+        let reserve_data = abi::tuple_at(&lending_contract.call("getReserveData", vec![Token::Address(asset)]).await?, 0)?;
+        let a_token = abi::address_at(&reserve_data, 7)?;
+        let stable_debt_token = abi::address_at(&reserve_data, 8)?;
+        let variable_debt_token = abi::address_at(&reserve_data, 9)?;
 
-        let total_supply: U256 = self.decode_uint256(&lending_contract.call("getReserveData", vec![self.encode_address(asset)]).await?);
-        let total_borrow: U256 = self.decode_uint256(&lending_contract.call("getReserveData", vec![self.encode_address(asset)]).await?);
+        // Supply and borrow totals live on the reserve's aToken and debt tokens
+        let total_supply: U256 = self.erc20_total_supply(a_token).await?;
+        let total_borrow: U256 = self.erc20_total_supply(stable_debt_token).await?
+            + self.erc20_total_supply(variable_debt_token).await?;
 
         let utilization_rate = if total_supply > U256::zero() {
             (total_borrow * U256::from(10000)) / total_supply
@@ -220,15 +252,15 @@ impl DeFiProtocol {
 
     pub async fn deposit_to_lending_pool(&self, asset: Address, amount: U256) -> Result<H256, Box<dyn std::error::Error>> {
         let lending_pool_address = self.contracts.get("aave_lending_pool").unwrap();
-        let lending_contract = super::smart_contract::SmartContract::new(*lending_pool_address, vec![], self.web3.clone());
+        let lending_contract = self.contract(*lending_pool_address, abi::AAVE_LENDING_POOL_ABI)?;
 
         let tx_hash = lending_contract.send_transaction(
             "deposit",
             vec![
-                self.encode_address(asset),
-                self.encode_uint256(amount),
-                self.encode_address(Address::zero()), // onBehalfOf
-                self.encode_uint256(U256::zero()), // referralCode
+                Token::Address(asset),
+                Token::Uint(amount),
+                Token::Address(self.account()?), // onBehalfOf
+                Token::Uint(U256::zero()), // referralCode
             ],
             U256::zero(),
         ).await?;
@@ -238,16 +270,16 @@ impl DeFiProtocol {
 
     pub async fn borrow_from_lending_pool(&self, asset: Address, amount: U256) -> Result<H256, Box<dyn std::error::Error>> {
         let lending_pool_address = self.contracts.get("aave_lending_pool").unwrap();
-        let lending_contract = super::smart_contract::SmartContract::new(*lending_pool_address, vec![], self.web3.clone());
+        let lending_contract = self.contract(*lending_pool_address, abi::AAVE_LENDING_POOL_ABI)?;
 
         let tx_hash = lending_contract.send_transaction(
             "borrow",
             vec![
-                self.encode_address(asset),
-                self.encode_uint256(amount),
-                self.encode_uint256(U256::from(1)), // interestRateMode: Stable
-                self.encode_uint256(U256::zero()), // referralCode
-                self.encode_address(Address::zero()), // onBehalfOf
+                Token::Address(asset),
+                Token::Uint(amount),
+                Token::Uint(U256::from(1)), // interestRateMode: Stable
+                Token::Uint(U256::zero()), // referralCode
+                Token::Address(self.account()?), // onBehalfOf
             ],
             U256::zero(),
         ).await?;
@@ -257,15 +289,15 @@ impl DeFiProtocol {
 
     pub async fn repay_to_lending_pool(&self, asset: Address, amount: U256) -> Result<H256, Box<dyn std::error::Error>> {
         let lending_pool_address = self.contracts.get("aave_lending_pool").unwrap();
-        let lending_contract = super::smart_contract::SmartContract::new(*lending_pool_address, vec![], self.web3.clone());
+        let lending_contract = self.contract(*lending_pool_address, abi::AAVE_LENDING_POOL_ABI)?;
 
         let tx_hash = lending_contract.send_transaction(
             "repay",
             vec![
-                self.encode_address(asset),
-                self.encode_uint256(amount),
-                self.encode_uint256(U256::from(1)), // interestRateMode: Stable
-                self.encode_address(Address::zero()), // onBehalfOf
+                Token::Address(asset),
+                Token::Uint(amount),
+                Token::Uint(U256::from(1)), // interestRateMode: Stable
+                Token::Address(self.account()?), // onBehalfOf
             ],
             U256::zero(),
         ).await?;
@@ -289,11 +321,11 @@ impl DeFiProtocol {
     }
 
     pub async fn stake_in_yield_farm(&self, farm_address: Address, amount: U256) -> Result<H256, Box<dyn std::error::Error>> {
-        let farm_contract = super::smart_contract::SmartContract::new(farm_address, vec![], self.web3.clone());
+        let farm_contract = self.contract(farm_address, abi::STAKING_REWARDS_ABI)?;
 
         let tx_hash = farm_contract.send_transaction(
             "stake",
-            vec![self.encode_uint256(amount)],
+            vec![Token::Uint(amount)],
             U256::zero(),
         ).await?;
 
@@ -301,11 +333,11 @@ impl DeFiProtocol {
     }
 
     pub async fn unstake_from_yield_farm(&self, farm_address: Address, amount: U256) -> Result<H256, Box<dyn std::error::Error>> {
-        let farm_contract = super::smart_contract::SmartContract::new(farm_address, vec![], self.web3.clone());
+        let farm_contract = self.contract(farm_address, abi::STAKING_REWARDS_ABI)?;
 
         let tx_hash = farm_contract.send_transaction(
             "withdraw",
-            vec![self.encode_uint256(amount)],
+            vec![Token::Uint(amount)],
             U256::zero(),
         ).await?;
 
@@ -313,7 +345,7 @@ impl DeFiProtocol {
     }
 
     pub async fn claim_rewards(&self, farm_address: Address) -> Result<H256, Box<dyn std::error::Error>> {
-        let farm_contract = super::smart_contract::SmartContract::new(farm_address, vec![], self.web3.clone());
+        let farm_contract = self.contract(farm_address, abi::STAKING_REWARDS_ABI)?;
 
         let tx_hash = farm_contract.send_transaction(
             "getReward",
@@ -338,18 +370,18 @@ impl DeFiProtocol {
 
     pub async fn get_flash_loan(&self, asset: Address, amount: U256) -> Result<H256, Box<dyn std::error::Error>> {
         let lending_pool_address = self.contracts.get("aave_lending_pool").unwrap();
-        let lending_contract = super::smart_contract::SmartContract::new(*lending_pool_address, vec![], self.web3.clone());
+        let lending_contract = self.contract(*lending_pool_address, abi::AAVE_LENDING_POOL_ABI)?;
 
         let tx_hash = lending_contract.send_transaction(
             "flashLoan",
             vec![
-                self.encode_address(Address::zero()), // receiverAddress
-                vec![self.encode_address(asset)], // assets
-                vec![self.encode_uint256(amount)], // amounts
-                vec![self.encode_uint256(U256::zero())], // modes
-                self.encode_address(Address::zero()), // onBehalfOf
-                self.encode_bytes(vec![]), // params
-                self.encode_uint256(U256::zero()), // referralCode
+                Token::Address(Address::zero()), // receiverAddress
+                Token::Array(vec![Token::Address(asset)]), // assets
+                Token::Array(vec![Token::Uint(amount)]), // amounts
+                Token::Array(vec![Token::Uint(U256::zero())]), // modes
+                Token::Address(self.account()?), // onBehalfOf
+                Token::Bytes(vec![]), // params
+                Token::Uint(U256::zero()), // referralCode
             ],
             U256::zero(),
         ).await?;
@@ -364,10 +396,10 @@ impl DeFiProtocol {
 
         if price_a > price_b {
             // Buy from pool_b, sell to pool_a
-            self.swap_exact_tokens_for_tokens(amount, U256::from(1), vec![pool_b.token_a, pool_b.token_b]).await
+            Ok(self.swap_exact_tokens_for_tokens(amount, U256::from(1), vec![pool_b.token_a, pool_b.token_b]).await?.tx_hash)
         } else {
             // Buy from pool_a, sell to pool_b
-            self.swap_exact_tokens_for_tokens(amount, U256::from(1), vec![pool_a.token_a, pool_a.token_b]).await
+            Ok(self.swap_exact_tokens_for_tokens(amount, U256::from(1), vec![pool_a.token_a, pool_a.token_b]).await?.tx_hash)
         }
     }
 
@@ -408,66 +440,55 @@ impl DeFiProtocol {
             // Calculate current allocation and rebalance if needed
             // This is a placeholder implementation
             let swap_tx = self.swap_exact_tokens_for_tokens(U256::from(1000), U256::from(1), vec![token, Address::zero()]).await?;
-            transactions.push(swap_tx);
+            transactions.push(swap_tx.tx_hash);
         }
 
         Ok(transactions)
     }
 
-    pub async fn liquidate_position(&self, user: Address, asset: Address) -> Result<H256, Box<dyn std::error::Error>> {
+    pub async fn liquidate_position(&self, user: Address, asset: Address) -> Result<SentTransaction, Box<dyn std::error::Error>> {
         let lending_pool_address = self.contracts.get("aave_lending_pool").unwrap();
-        let lending_contract = super::smart_contract::SmartContract::new(*lending_pool_address, vec![], self.web3.clone());
+        let lending_contract = self.contract(*lending_pool_address, abi::AAVE_LENDING_POOL_ABI)?;
 
-        let tx_hash = lending_contract.send_transaction(
+        lending_contract.send_transaction_with(
             "liquidationCall",
             vec![
-                self.encode_address(asset), // collateralAsset
-                self.encode_address(asset), // debtAsset
-                self.encode_address(user), // user
-                self.encode_uint256(U256::from(100)), // debtToCover
-                self.encode_bool(false), // receiveAToken
+                Token::Address(asset), // collateralAsset
+                Token::Address(asset), // debtAsset
+                Token::Address(user), // user
+                Token::Uint(U256::from(100)), // debtToCover
+                Token::Bool(false), // receiveAToken
             ],
             U256::zero(),
-        ).await?;
-
-        Ok(tx_hash)
-    }
-
-    // Helper functions for encoding/decoding
-    fn encode_address(&self, address: Address) -> web3::types::Bytes {
-        let mut bytes = vec![0u8; 32];
-        bytes[12..32].copy_from_slice(address.as_bytes());
-        bytes.into()
+            Self::access_list_options(),
+        ).await
     }
 
-    fn encode_uint256(&self, value: U256) -> web3::types::Bytes {
-        let mut bytes = vec![0u8; 32];
-        value.to_big_endian(&mut bytes);
-        bytes.into()
-    }
-
-    fn encode_bool(&self, value: bool) -> web3::types::Bytes {
-        let byte = if value { 1u8 } else { 0u8 };
-        vec![byte].into()
-    }
-
-    fn encode_bytes(&self, value: Vec<u8>) -> web3::types::Bytes {
-        value.into()
+    // Helper functions
+    fn access_list_options() -> TxOptions {
+        TxOptions {
+            access_list: true,
+            ..Default::default()
+        }
     }
 
-    fn encode_array(&self, values: Vec<web3::types::Bytes>) -> web3::types::Bytes {
-        let mut encoded = Vec::new();
-        for value in values {
-            encoded.extend_from_slice(&value.0);
-        }
-        encoded.into()
+    fn contract(&self, address: Address, contract_abi: &str) -> Result<SmartContract<T>, Box<dyn std::error::Error>> {
+        let contract = SmartContract::new(address, contract_abi.as_bytes().to_vec(), self.web3.clone(), self.nonce_manager.clone(), self.fee_oracle.clone())?;
+        Ok(match &self.signer {
+            Some(signer) => contract.with_signer(signer.clone()),
+            None => contract,
+        })
     }
 
-    fn decode_address(&self, data: &[u8]) -> Address {
-        Address::from_slice(&data[12..32])
+    // Recipient and onBehalfOf for every position this protocol opens
+    fn account(&self) -> Result<Address, Box<dyn std::error::Error>> {
+        self.signer.as_ref()
+            .map(|signer| signer.address())
+            .ok_or_else(|| "No signer configured for DeFi writes".into())
     }
 
-    fn decode_uint256(&self, data: &[u8]) -> U256 {
-        U256::from_big_endian(data)
+    async fn erc20_total_supply(&self, token: Address) -> Result<U256, Box<dyn std::error::Error>> {
+        let token_contract = self.contract(token, abi::ERC20_ABI)?;
+        abi::uint_at(&token_contract.call("totalSupply", vec![]).await?, 0)
     }
-}
\ No newline at end of file
+}
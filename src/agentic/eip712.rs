@@ -0,0 +1,414 @@
+use std::collections::{BTreeMap, BTreeSet};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use web3::signing::keccak256;
+use web3::types::{Address, H256, U256};
+
+use super::address;
+use super::signature::Signature;
+
+pub const DOMAIN_TYPE: &str = "EIP712Domain";
+
+// EIP712Domain fields in the order the standard lists them
+const DOMAIN_FIELDS: [(&str, &str); 5] = [
+    ("name", "string"),
+    ("version", "string"),
+    ("chainId", "uint256"),
+    ("verifyingContract", "address"),
+    ("salt", "bytes32"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypedDataField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+impl TypedDataField {
+    pub fn new(name: &str, kind: &str) -> Self {
+        Self { name: name.to_string(), kind: kind.to_string() }
+    }
+}
+
+// Struct name -> fields, as in the "types" member of eth_signTypedData_v4
+pub type Types = BTreeMap<String, Vec<TypedDataField>>;
+
+// Fields left as None are left out of the EIP712Domain type altogether
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Eip712Domain {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verifying_contract: Option<Address>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub salt: Option<H256>,
+}
+
+impl Eip712Domain {
+    pub fn new(name: &str, version: &str, chain_id: u64, verifying_contract: Address) -> Self {
+        Self {
+            name: Some(name.to_string()),
+            version: Some(version.to_string()),
+            chain_id: Some(chain_id),
+            verifying_contract: Some(verifying_contract),
+            salt: None,
+        }
+    }
+
+    pub fn with_salt(mut self, salt: H256) -> Self {
+        self.salt = Some(salt);
+        self
+    }
+
+    pub fn separator(&self) -> Result<H256, Box<dyn std::error::Error>> {
+        let domain = serde_json::to_value(self)?;
+        let mut types = Types::new();
+        types.insert(DOMAIN_TYPE.to_string(), domain_fields(&domain));
+        hash_struct(DOMAIN_TYPE, &domain, &types)
+    }
+}
+
+// A complete eth_signTypedData_v4 document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypedData {
+    pub types: Types,
+    pub primary_type: String,
+    pub domain: Value,
+    pub message: Value,
+}
+
+impl TypedData {
+    pub fn new(domain: &Eip712Domain, primary_type: &str, mut types: Types, message: Value) -> Result<Self, Box<dyn std::error::Error>> {
+        let domain = serde_json::to_value(domain)?;
+        types.insert(DOMAIN_TYPE.to_string(), domain_fields(&domain));
+        let typed_data = Self { types, primary_type: primary_type.to_string(), domain, message };
+        typed_data.validate()?;
+        Ok(typed_data)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let typed_data: Self = serde_json::from_str(json)?;
+        typed_data.validate()?;
+        Ok(typed_data)
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    // Catches undefined struct references up front rather than halfway through hashing
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.primary_type == DOMAIN_TYPE {
+            return Err("primaryType must be a message type, not EIP712Domain".into());
+        }
+        if !self.types.contains_key(&self.primary_type) {
+            return Err(format!("primaryType {} is not defined in types", self.primary_type).into());
+        }
+        for (name, fields) in &self.types {
+            for field in fields {
+                let base = base_type(&field.kind);
+                if !self.types.contains_key(base) && !is_atomic(base) {
+                    return Err(format!("{}.{} has undefined type {}", name, field.name, field.kind).into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Documents that leave EIP712Domain out of "types" get it from the domain's own fields
+    pub fn domain_separator(&self) -> Result<H256, Box<dyn std::error::Error>> {
+        if self.types.contains_key(DOMAIN_TYPE) {
+            return hash_struct(DOMAIN_TYPE, &self.domain, &self.types);
+        }
+        let mut types = self.types.clone();
+        types.insert(DOMAIN_TYPE.to_string(), domain_fields(&self.domain));
+        hash_struct(DOMAIN_TYPE, &self.domain, &types)
+    }
+
+    pub fn struct_hash(&self) -> Result<H256, Box<dyn std::error::Error>> {
+        hash_struct(&self.primary_type, &self.message, &self.types)
+    }
+
+    // keccak256(0x1901 || domainSeparator || hashStruct(message)), the hash that gets signed
+    pub fn digest(&self) -> Result<H256, Box<dyn std::error::Error>> {
+        Ok(typed_data_digest(self.domain_separator()?, self.struct_hash()?))
+    }
+
+    pub fn recover(&self, signature: &[u8]) -> Result<Address, Box<dyn std::error::Error>> {
+        Signature::from_bytes(signature)?.recover(self.digest()?)
+    }
+}
+
+pub fn typed_data_digest(domain_separator: H256, struct_hash: H256) -> H256 {
+    let mut payload = Vec::with_capacity(66);
+    payload.extend_from_slice(&[0x19, 0x01]);
+    payload.extend_from_slice(domain_separator.as_bytes());
+    payload.extend_from_slice(struct_hash.as_bytes());
+    H256::from(keccak256(&payload))
+}
+
+// e.g. Mail(Person from,Person to,string contents)Person(string name,address wallet):
+// the primary type first, then every type it references, sorted by name
+pub fn encode_type(primary_type: &str, types: &Types) -> Result<String, Box<dyn std::error::Error>> {
+    let fields = types.get(primary_type).ok_or_else(|| format!("Type {} is not defined", primary_type))?;
+
+    let mut dependencies = BTreeSet::new();
+    collect_dependencies(primary_type, types, &mut dependencies);
+    dependencies.remove(primary_type);
+
+    let mut encoded = encode_single_type(primary_type, fields);
+    for dependency in &dependencies {
+        encoded.push_str(&encode_single_type(dependency, &types[dependency]));
+    }
+    Ok(encoded)
+}
+
+pub fn type_hash(primary_type: &str, types: &Types) -> Result<H256, Box<dyn std::error::Error>> {
+    Ok(H256::from(keccak256(encode_type(primary_type, types)?.as_bytes())))
+}
+
+pub fn hash_struct(primary_type: &str, data: &Value, types: &Types) -> Result<H256, Box<dyn std::error::Error>> {
+    let fields = types.get(primary_type).ok_or_else(|| format!("Type {} is not defined", primary_type))?;
+    let object = data.as_object().ok_or_else(|| format!("{} value must be an object", primary_type))?;
+
+    let mut encoded = Vec::with_capacity(32 * (fields.len() + 1));
+    encoded.extend_from_slice(type_hash(primary_type, types)?.as_bytes());
+    for field in fields {
+        let value = object.get(&field.name).ok_or_else(|| format!("{} is missing field {}", primary_type, field.name))?;
+        let word = encode_value(&field.kind, value, types).map_err(|e| format!("{}.{}: {}", primary_type, field.name, e))?;
+        encoded.extend_from_slice(&word);
+    }
+    Ok(H256::from(keccak256(&encoded)))
+}
+
+fn encode_single_type(name: &str, fields: &[TypedDataField]) -> String {
+    let members: Vec<String> = fields.iter().map(|field| format!("{} {}", field.kind, field.name)).collect();
+    format!("{}({})", name, members.join(","))
+}
+
+fn collect_dependencies(kind: &str, types: &Types, found: &mut BTreeSet<String>) {
+    let base = base_type(kind);
+    if found.contains(base) {
+        return;
+    }
+    if let Some(fields) = types.get(base) {
+        found.insert(base.to_string());
+        for field in fields {
+            collect_dependencies(&field.kind, types, found);
+        }
+    }
+}
+
+// Person[][3] -> Person
+fn base_type(kind: &str) -> &str {
+    kind.split('[').next().unwrap_or(kind)
+}
+
+fn is_atomic(kind: &str) -> bool {
+    matches!(kind, "string" | "bytes" | "bool" | "address")
+        || size_suffix(kind, "bytes").map_or(false, |size| (1..=32).contains(&size))
+        || size_suffix(kind, "uint").map_or(false, valid_int_size)
+        || size_suffix(kind, "int").map_or(false, valid_int_size)
+}
+
+fn size_suffix(kind: &str, prefix: &str) -> Option<usize> {
+    kind.strip_prefix(prefix)?.parse().ok()
+}
+
+fn valid_int_size(bits: usize) -> bool {
+    bits % 8 == 0 && (8..=256).contains(&bits)
+}
+
+fn encode_value(kind: &str, value: &Value, types: &Types) -> Result<[u8; 32], Box<dyn std::error::Error>> {
+    // Arrays hash the concatenation of their encoded elements, whatever the element type
+    if let Some(element) = kind.strip_suffix(']') {
+        let open = element.rfind('[').ok_or_else(|| format!("Malformed array type {}", kind))?;
+        let (element_kind, length) = (&element[..open], &element[open + 1..]);
+        let items = value.as_array().ok_or_else(|| format!("expected an array for {}", kind))?;
+        if !length.is_empty() && length.parse::<usize>()? != items.len() {
+            return Err(format!("expected {} items for {}, found {}", length, kind, items.len()).into());
+        }
+
+        let mut encoded = Vec::with_capacity(32 * items.len());
+        for item in items {
+            encoded.extend_from_slice(&encode_value(element_kind, item, types)?);
+        }
+        return Ok(keccak256(&encoded));
+    }
+
+    if types.contains_key(kind) {
+        return Ok(hash_struct(kind, value, types)?.0);
+    }
+
+    let mut word = [0u8; 32];
+    match kind {
+        "string" => return Ok(keccak256(value.as_str().ok_or("expected a string")?.as_bytes())),
+        "bytes" => return Ok(keccak256(&decode_hex(value)?)),
+        "bool" => word[31] = value.as_bool().ok_or("expected a boolean")? as u8,
+        "address" => {
+            let parsed = address::parse_address(value.as_str().ok_or("expected an address string")?)?;
+            word[12..].copy_from_slice(parsed.as_bytes());
+        }
+        _ => {
+            if let Some(size) = size_suffix(kind, "bytes").filter(|size| (1..=32).contains(size)) {
+                let bytes = decode_hex(value)?;
+                if bytes.len() != size {
+                    return Err(format!("expected {} bytes for {}, found {}", size, kind, bytes.len()).into());
+                }
+                word[..size].copy_from_slice(&bytes);
+            } else if let Some(bits) = size_suffix(kind, "uint").filter(|bits| valid_int_size(*bits)) {
+                let (negative, magnitude) = parse_integer(value)?;
+                if (negative && !magnitude.is_zero()) || magnitude.bits() > bits {
+                    return Err(format!("value is out of range for {}", kind).into());
+                }
+                magnitude.to_big_endian(&mut word);
+            } else if let Some(bits) = size_suffix(kind, "int").filter(|bits| valid_int_size(*bits)) {
+                let (negative, magnitude) = parse_integer(value)?;
+                let limit = U256::one() << (bits - 1);
+                if (!negative && magnitude >= limit) || (negative && magnitude > limit) {
+                    return Err(format!("value is out of range for {}", kind).into());
+                }
+                // Two's complement over the full word, as the ABI sign-extends
+                let encoded = if negative { (!magnitude).overflowing_add(U256::one()).0 } else { magnitude };
+                encoded.to_big_endian(&mut word);
+            } else {
+                return Err(format!("unknown type {}", kind).into());
+            }
+        }
+    }
+    Ok(word)
+}
+
+// JSON numbers, or decimal and 0x-hex strings for values past 2^53; returns (negative, magnitude)
+fn parse_integer(value: &Value) -> Result<(bool, U256), Box<dyn std::error::Error>> {
+    match value {
+        Value::Number(number) => {
+            if let Some(n) = number.as_u64() {
+                Ok((false, U256::from(n)))
+            } else if let Some(n) = number.as_i64() {
+                Ok((true, U256::from(n.unsigned_abs())))
+            } else {
+                Err(format!("expected an integer, found {}", number).into())
+            }
+        }
+        Value::String(text) => {
+            let (negative, digits) = match text.strip_prefix('-') {
+                Some(rest) => (true, rest),
+                None => (false, text.as_str()),
+            };
+            let magnitude = match digits.strip_prefix("0x") {
+                Some(hex) => U256::from_str_radix(hex, 16).map_err(|_| format!("invalid hex integer {}", text))?,
+                None => U256::from_dec_str(digits).map_err(|_| format!("invalid integer {}", text))?,
+            };
+            Ok((negative, magnitude))
+        }
+        _ => Err("expected an integer".into()),
+    }
+}
+
+fn decode_hex(value: &Value) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let text = value.as_str().ok_or("expected a hex string")?;
+    let digits = text.strip_prefix("0x").ok_or_else(|| format!("{} must be 0x-prefixed hex", text))?;
+    Ok(hex::decode(digits)?)
+}
+
+fn domain_fields(domain: &Value) -> Vec<TypedDataField> {
+    DOMAIN_FIELDS
+        .iter()
+        .filter(|(name, _)| domain.get(name).map_or(false, |value| !value.is_null()))
+        .map(|(name, kind)| TypedDataField::new(name, kind))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::secret::PrivateKey;
+    use super::super::signature;
+
+    // The "Mail" example from EIP-712, signed by the key keccak256("cow")
+    const MAIL: &str = r#"{
+        "types": {
+            "EIP712Domain": [
+                { "name": "name", "type": "string" },
+                { "name": "version", "type": "string" },
+                { "name": "chainId", "type": "uint256" },
+                { "name": "verifyingContract", "type": "address" }
+            ],
+            "Person": [
+                { "name": "name", "type": "string" },
+                { "name": "wallet", "type": "address" }
+            ],
+            "Mail": [
+                { "name": "from", "type": "Person" },
+                { "name": "to", "type": "Person" },
+                { "name": "contents", "type": "string" }
+            ]
+        },
+        "primaryType": "Mail",
+        "domain": {
+            "name": "Ether Mail",
+            "version": "1",
+            "chainId": 1,
+            "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+        },
+        "message": {
+            "from": { "name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826" },
+            "to": { "name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB" },
+            "contents": "Hello, Bob!"
+        }
+    }"#;
+
+    const MAIL_SIGNATURE: &str = "0x4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b915621c";
+
+    fn h256(hex: &str) -> H256 {
+        hex.parse().unwrap()
+    }
+
+    #[test]
+    fn mail_example_hashes() {
+        let mail = TypedData::from_json(MAIL).unwrap();
+
+        assert_eq!(encode_type("Mail", &mail.types).unwrap(), "Mail(Person from,Person to,string contents)Person(string name,address wallet)");
+        assert_eq!(mail.domain_separator().unwrap(), h256("f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f"));
+        assert_eq!(mail.struct_hash().unwrap(), h256("c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e"));
+        assert_eq!(mail.digest().unwrap(), h256("be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"));
+    }
+
+    #[test]
+    fn domain_separator_without_explicit_domain_type() {
+        let mut mail = TypedData::from_json(MAIL).unwrap();
+        mail.types.remove(DOMAIN_TYPE);
+        assert_eq!(mail.domain_separator().unwrap(), h256("f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f"));
+
+        let domain = Eip712Domain::new("Ether Mail", "1", 1, "cccccccccccccccccccccccccccccccccccccccc".parse().unwrap());
+        assert_eq!(domain.separator().unwrap(), h256("f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f"));
+    }
+
+    #[test]
+    fn mail_example_signature() {
+        let mail = TypedData::from_json(MAIL).unwrap();
+        let cow: Address = "cd2a3d9f938e13cd947ec05abc7fe734df8dd826".parse().unwrap();
+
+        let spec_signature = hex::decode(&MAIL_SIGNATURE[2..]).unwrap();
+        assert_eq!(mail.recover(&spec_signature).unwrap(), cow);
+
+        let key = PrivateKey::from_slice(&keccak256(b"cow")).unwrap();
+        assert_eq!(key.address(), cow);
+        let signature = signature::sign_hash(&key, mail.digest().unwrap()).unwrap();
+        assert_eq!(mail.recover(&signature.to_bytes()).unwrap(), cow);
+    }
+
+    #[test]
+    fn rejects_undefined_types() {
+        let broken = MAIL.replace(r#""type": "Person" }"#, r#""type": "Persona" }"#);
+        assert!(TypedData::from_json(&broken).is_err());
+    }
+}
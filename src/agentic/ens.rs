@@ -0,0 +1,304 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use web3::ethabi::{self, ParamType, Token};
+use web3::signing::keccak256;
+use web3::transports::Http;
+use web3::types::{Address, H256};
+use web3::{Transport, Web3};
+
+use super::abi;
+use super::address;
+use super::smart_contract::SmartContract;
+
+// The ENS registry, at the same address on mainnet and the public testnets
+pub const ENS_REGISTRY: &str = "00000000000C2E074eC69A0dFb2997BA6C7d2e1e";
+
+// ENSIP-10 IExtendedResolver
+const EXTENDED_RESOLVER_INTERFACE: [u8; 4] = [0x90, 0x61, 0xb9, 0x23];
+
+// Either side of "send 5 USDC to treasury.eth"; resolve with EnsResolver::resolve
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NameOrAddress {
+    Name(String),
+    Address(Address),
+}
+
+impl From<Address> for NameOrAddress {
+    fn from(address: Address) -> Self {
+        NameOrAddress::Address(address)
+    }
+}
+
+impl FromStr for NameOrAddress {
+    type Err = Box<dyn std::error::Error>;
+
+    // 0x-prefixed input must be a valid address; anything else is taken as a name
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        if input.starts_with("0x") {
+            return Ok(NameOrAddress::Address(address::parse_address(input)?));
+        }
+        Ok(NameOrAddress::Name(normalize(input)?))
+    }
+}
+
+impl fmt::Display for NameOrAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameOrAddress::Name(name) => write!(f, "{}", name),
+            NameOrAddress::Address(address) => write!(f, "{}", address::to_checksum(address)),
+        }
+    }
+}
+
+// Lowercases and checks label structure. This covers ASCII names; full ENSIP-15
+// normalization of Unicode names is not implemented, so those are rejected.
+pub fn normalize(name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let name = name.trim().to_lowercase();
+    if !name.is_ascii() {
+        return Err(format!("Non-ASCII ENS name {} is not supported", name).into());
+    }
+    if name.is_empty() || name.split('.').any(|label| label.is_empty()) {
+        return Err(format!("Invalid ENS name {}", name).into());
+    }
+    if name.chars().any(|c| c.is_whitespace() || c == '/' || c == ':') {
+        return Err(format!("Invalid character in ENS name {}", name).into());
+    }
+    Ok(name)
+}
+
+pub fn namehash(name: &str) -> H256 {
+    let mut node = [0u8; 32];
+    if name.is_empty() {
+        return H256::from(node);
+    }
+    for label in name.rsplit('.') {
+        let mut payload = [0u8; 64];
+        payload[..32].copy_from_slice(&node);
+        payload[32..].copy_from_slice(&keccak256(label.as_bytes()));
+        node = keccak256(&payload);
+    }
+    H256::from(node)
+}
+
+// DNS wire format, as ENSIP-10 resolve() expects: length-prefixed labels, zero-terminated
+pub fn dns_encode(name: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut encoded = Vec::with_capacity(name.len() + 2);
+    for label in name.split('.') {
+        if label.len() > 63 {
+            return Err(format!("ENS label {} is longer than 63 bytes", label).into());
+        }
+        encoded.push(label.len() as u8);
+        encoded.extend_from_slice(label.as_bytes());
+    }
+    encoded.push(0);
+    Ok(encoded)
+}
+
+struct FoundResolver {
+    address: Address,
+    // Found on a parent name, or the resolver implements IExtendedResolver itself
+    extended: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct EnsResolver<T: Transport = Http> {
+    web3: Arc<Web3<T>>,
+    registry: Address,
+}
+
+impl<T: Transport> EnsResolver<T> {
+    pub fn new(web3: Arc<Web3<T>>) -> Self {
+        Self {
+            web3,
+            registry: ENS_REGISTRY.parse().expect("static registry address is valid"),
+        }
+    }
+
+    // For a locally deployed registry, e.g. in tests or on a devnet
+    pub fn with_registry(mut self, registry: Address) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    pub fn registry(&self) -> Address {
+        self.registry
+    }
+
+    pub async fn resolve(&self, target: &NameOrAddress) -> Result<Address, Box<dyn std::error::Error>> {
+        match target {
+            NameOrAddress::Address(address) => Ok(*address),
+            NameOrAddress::Name(name) => self.resolve_name(name).await,
+        }
+    }
+
+    pub async fn resolve_name(&self, name: &str) -> Result<Address, Box<dyn std::error::Error>> {
+        let name = normalize(name)?;
+        let node = namehash(&name);
+        let resolver = self.find_resolver(&name).await?.ok_or_else(|| format!("No resolver for {}", name))?;
+
+        let address = if resolver.extended {
+            let contract = self.resolver_contract(resolver.address)?;
+            let inner = contract.encode_call("addr", &[Token::FixedBytes(node.as_bytes().to_vec())])?;
+            let result = self.extended_call(&contract, &name, inner).await?;
+            ethabi::decode(&[ParamType::Address], &result)?
+                .remove(0)
+                .into_address()
+                .ok_or("Resolver returned a malformed address")?
+        } else {
+            let tokens = self.resolver_contract(resolver.address)?
+                .call("addr", vec![Token::FixedBytes(node.as_bytes().to_vec())])
+                .await?;
+            abi::address_at(&tokens, 0)?
+        };
+
+        if address.is_zero() {
+            return Err(format!("{} has no address record", name).into());
+        }
+        Ok(address)
+    }
+
+    // The primary name for `address`, only if that name resolves back to the same address
+    pub async fn lookup_address(&self, address: Address) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let reverse_name = format!("{}.addr.reverse", hex::encode(address.as_bytes()));
+        let node = namehash(&reverse_name);
+
+        let resolver = self.registry_resolver(node).await?;
+        if resolver.is_zero() {
+            return Ok(None);
+        }
+
+        let tokens = self.resolver_contract(resolver)?
+            .call("name", vec![Token::FixedBytes(node.as_bytes().to_vec())])
+            .await?;
+        let name = abi::string_at(&tokens, 0)?;
+        if name.is_empty() {
+            return Ok(None);
+        }
+
+        // Anyone can set any reverse name for their own address; only trust it if the
+        // forward record agrees
+        match self.resolve_name(&name).await {
+            Ok(forward) if forward == address => Ok(Some(normalize(&name)?)),
+            _ => Ok(None),
+        }
+    }
+
+    pub async fn text(&self, name: &str, key: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let name = normalize(name)?;
+        let node = namehash(&name);
+        let resolver = match self.find_resolver(&name).await? {
+            Some(resolver) => resolver,
+            None => return Ok(None),
+        };
+
+        let params = vec![Token::FixedBytes(node.as_bytes().to_vec()), Token::String(key.to_string())];
+        let value = if resolver.extended {
+            let contract = self.resolver_contract(resolver.address)?;
+            let inner = contract.encode_call("text", &params)?;
+            let result = self.extended_call(&contract, &name, inner).await?;
+            ethabi::decode(&[ParamType::String], &result)?
+                .remove(0)
+                .into_string()
+                .ok_or("Resolver returned a malformed text record")?
+        } else {
+            let tokens = self.resolver_contract(resolver.address)?.call("text", params).await?;
+            abi::string_at(&tokens, 0)?
+        };
+
+        Ok(Some(value).filter(|value| !value.is_empty()))
+    }
+
+    // ENSIP-10: use the name's own resolver, or else the closest ancestor's resolver if
+    // it supports wildcard resolution
+    async fn find_resolver(&self, name: &str) -> Result<Option<FoundResolver>, Box<dyn std::error::Error>> {
+        let mut current = name;
+        loop {
+            let resolver = self.registry_resolver(namehash(current)).await?;
+            if !resolver.is_zero() {
+                let extended = self.supports_extended(resolver).await;
+                if current == name || extended {
+                    return Ok(Some(FoundResolver { address: resolver, extended }));
+                }
+                return Ok(None);
+            }
+
+            match current.split_once('.') {
+                Some((_, parent)) => current = parent,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    async fn registry_resolver(&self, node: H256) -> Result<Address, Box<dyn std::error::Error>> {
+        let registry = SmartContract::read_only(self.registry, abi::ENS_REGISTRY_ABI.as_bytes().to_vec(), self.web3.clone())?;
+        let tokens = registry.call("resolver", vec![Token::FixedBytes(node.as_bytes().to_vec())]).await?;
+        abi::address_at(&tokens, 0)
+    }
+
+    async fn supports_extended(&self, resolver: Address) -> bool {
+        let contract = match self.resolver_contract(resolver) {
+            Ok(contract) => contract,
+            Err(_) => return false,
+        };
+        // Old resolvers without ERC-165 revert here, which means no
+        match contract.call("supportsInterface", vec![Token::FixedBytes(EXTENDED_RESOLVER_INTERFACE.to_vec())]).await {
+            Ok(tokens) => abi::bool_at(&tokens, 0).unwrap_or(false),
+            Err(_) => false,
+        }
+    }
+
+    // Offchain (EIP-3668 CCIP-read) resolvers revert with OffchainLookup, which surfaces
+    // here as a call error; following those lookups is not supported
+    async fn extended_call(&self, contract: &SmartContract<T>, name: &str, data: Vec<u8>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let tokens = contract.call("resolve", vec![Token::Bytes(dns_encode(name)?), Token::Bytes(data)]).await?;
+        abi::token_at(&tokens, 0)?.into_bytes().ok_or_else(|| "Resolver returned malformed bytes".into())
+    }
+
+    fn resolver_contract(&self, resolver: Address) -> Result<SmartContract<T>, Box<dyn std::error::Error>> {
+        SmartContract::read_only(resolver, abi::ENS_RESOLVER_ABI.as_bytes().to_vec(), self.web3.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn h256(hex: &str) -> H256 {
+        hex.parse().unwrap()
+    }
+
+    #[test]
+    fn namehash_matches_eip137() {
+        assert_eq!(namehash(""), H256::zero());
+        assert_eq!(namehash("eth"), h256("93cdeb708b7545dc668eb9280176169d1c33cfd8ed6f04690a0bcc88a93fc4ae"));
+        assert_eq!(namehash("foo.eth"), h256("de9b09fd7c5f901e23a3f19fecc54828e9c848539801e86591bd9801b019f84f"));
+        assert_eq!(namehash("alice.eth"), h256("787192fc5378cc32aa956ddfdedbf26b24e8d78e40109add0eea2c1a012c3dec"));
+    }
+
+    #[test]
+    fn dns_encodes_length_prefixed_labels() {
+        assert_eq!(dns_encode("eth").unwrap(), b"\x03eth\x00".to_vec());
+        assert_eq!(dns_encode("foo.eth").unwrap(), b"\x03foo\x03eth\x00".to_vec());
+        assert_eq!(dns_encode("sub.alice.eth").unwrap(), b"\x03sub\x05alice\x03eth\x00".to_vec());
+
+        let longest = "a".repeat(63);
+        assert_eq!(dns_encode(&format!("{}.eth", longest)).unwrap().len(), 1 + 63 + 1 + 3 + 1);
+        assert!(dns_encode(&format!("{}a.eth", longest)).is_err());
+    }
+
+    #[test]
+    fn normalizes_ascii_names() {
+        assert_eq!(normalize(" Alice.ETH ").unwrap(), "alice.eth");
+        assert!(normalize("alice..eth").is_err());
+        assert!(normalize("ali ce.eth").is_err());
+        assert!(normalize("alicé.eth").is_err());
+
+        match "Vitalik.eth".parse::<NameOrAddress>().unwrap() {
+            NameOrAddress::Name(name) => assert_eq!(name, "vitalik.eth"),
+            NameOrAddress::Address(_) => panic!("parsed a name as an address"),
+        }
+        assert!("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD".parse::<NameOrAddress>().is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use web3::ethabi::{self, Contract, Event, RawLog, Token};
+use web3::signing::keccak256;
+use web3::types::{Address, Log, H256};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedLog {
+    pub address: Address,
+    pub event: String,
+    // Canonical form, e.g. Transfer(address,address,uint256)
+    pub signature: String,
+    // Indexed and non-indexed fields together, in declaration order
+    pub params: Vec<(String, Token)>,
+    pub block_number: Option<u64>,
+    pub block_hash: Option<H256>,
+    pub transaction_hash: Option<H256>,
+    pub transaction_index: Option<u64>,
+    pub log_index: Option<u64>,
+    // Set when a reorg dropped the block this log was in
+    pub removed: bool,
+}
+
+impl DecodedLog {
+    pub fn param(&self, name: &str) -> Option<&Token> {
+        self.params.iter().find(|(param, _)| param == name).map(|(_, value)| value)
+    }
+
+    pub fn address_param(&self, name: &str) -> Option<Address> {
+        self.param(name).cloned().and_then(Token::into_address)
+    }
+
+    pub fn uint_param(&self, name: &str) -> Option<web3::types::U256> {
+        self.param(name).cloned().and_then(Token::into_uint)
+    }
+}
+
+pub fn event_signature(event: &Event) -> String {
+    let types: Vec<String> = event.inputs.iter().map(|input| input.kind.to_string()).collect();
+    format!("{}({})", event.name, types.join(","))
+}
+
+// `event` is either a bare name, which must not be overloaded in the ABI, or a full
+// signature such as "Transfer(address,address,uint256)"
+pub fn resolve_event(contract: &Contract, event: &str) -> Result<Event, Box<dyn std::error::Error>> {
+    if event.contains('(') {
+        let wanted = event.replace(' ', "");
+        return contract
+            .events()
+            .find(|candidate| event_signature(candidate) == wanted)
+            .cloned()
+            .ok_or_else(|| format!("Event {} is not in the ABI", event).into());
+    }
+
+    match contract.events_by_name(event)?.as_slice() {
+        [only] => Ok(only.clone()),
+        overloads => Err(format!(
+            "Event {} is overloaded, pass one of {}",
+            event,
+            overloads.iter().map(event_signature).collect::<Vec<_>>().join(", "),
+        ).into()),
+    }
+}
+
+// Filter topics for `event`: the signature first, unless the event is anonymous, then one
+// entry per indexed parameter where None matches anything
+pub fn topics_for(event: &Event, indexed: &[Option<Token>]) -> Result<Vec<Option<Vec<H256>>>, Box<dyn std::error::Error>> {
+    let indexed_params: Vec<_> = event.inputs.iter().filter(|input| input.indexed).collect();
+    if indexed.len() > indexed_params.len() {
+        return Err(format!("{} has {} indexed parameters, got {} values", event_signature(event), indexed_params.len(), indexed.len()).into());
+    }
+
+    let mut topics = Vec::with_capacity(4);
+    if !event.anonymous {
+        topics.push(Some(vec![event.signature()]));
+    }
+    for (param, value) in indexed_params.iter().zip(indexed) {
+        match value {
+            Some(token) => {
+                if !token.type_check(&param.kind) {
+                    return Err(format!("Indexed parameter {} of {} must be {}", param.name, event_signature(event), param.kind).into());
+                }
+                topics.push(Some(vec![topic_for(token)]));
+            }
+            None => topics.push(None),
+        }
+    }
+    Ok(topics)
+}
+
+// Value types are stored in the topic as their ABI word; strings, bytes and arrays only
+// as a hash, so they can be filtered on but not read back
+pub fn topic_for(token: &Token) -> H256 {
+    match token {
+        Token::String(text) => H256::from(keccak256(text.as_bytes())),
+        Token::Bytes(bytes) => H256::from(keccak256(bytes)),
+        Token::Array(_) | Token::FixedArray(_) | Token::Tuple(_) => H256::from(keccak256(&in_place_encoding(token))),
+        _ => H256::from_slice(&ethabi::encode(std::slice::from_ref(token))),
+    }
+}
+
+// Solidity's encoding for indexed arrays and structs: the elements' encodings back to
+// back, each padded to 32 bytes, with no offset or length words. Nested strings and bytes
+// contribute their padded contents.
+fn in_place_encoding(token: &Token) -> Vec<u8> {
+    match token {
+        Token::String(text) => padded(text.as_bytes()),
+        Token::Bytes(bytes) => padded(bytes),
+        Token::Array(items) | Token::FixedArray(items) | Token::Tuple(items) => items.iter().flat_map(in_place_encoding).collect(),
+        _ => ethabi::encode(std::slice::from_ref(token)),
+    }
+}
+
+fn padded(bytes: &[u8]) -> Vec<u8> {
+    let mut padded = bytes.to_vec();
+    padded.resize((bytes.len() + 31) / 32 * 32, 0);
+    padded
+}
+
+pub fn decode_log_with(event: &Event, log: &Log) -> Result<DecodedLog, Box<dyn std::error::Error>> {
+    let raw = RawLog { topics: log.topics.clone(), data: log.data.0.clone() };
+    let parsed = event.parse_log(raw).map_err(|e| format!("Could not decode {} log: {}", event_signature(event), e))?;
+
+    Ok(DecodedLog {
+        address: log.address,
+        event: event.name.clone(),
+        signature: event_signature(event),
+        params: parsed.params.into_iter().map(|param| (param.name, param.value)).collect(),
+        block_number: log.block_number.map(|number| number.as_u64()),
+        block_hash: log.block_hash,
+        transaction_hash: log.transaction_hash,
+        transaction_index: log.transaction_index.map(|index| index.as_u64()),
+        log_index: log.log_index.map(|index| index.as_u64()),
+        removed: log.removed.unwrap_or(false),
+    })
+}
+
+// Matches `log` by its first topic against the ABI's events, then tries the anonymous ones;
+// None when nothing in the ABI fits
+pub fn decode_log(contract: &Contract, log: &Log) -> Option<DecodedLog> {
+    let first_topic = log.topics.first();
+    let named = contract.events().filter(|event| !event.anonymous && Some(&event.signature()) == first_topic);
+    let anonymous = contract.events().filter(|event| event.anonymous);
+    named.chain(anonymous).find_map(|event| decode_log_with(event, log).ok())
+}
+
+// Logs for an anonymous event can't be filtered by signature, so the node returns every
+// log that fits the indexed values and the ones that don't decode are someone else's
+pub fn decode_logs(event: &Event, logs: &[Log]) -> Result<Vec<DecodedLog>, Box<dyn std::error::Error>> {
+    if event.anonymous {
+        return Ok(logs.iter().filter_map(|log| decode_log_with(event, log).ok()).collect());
+    }
+    logs.iter().map(|log| decode_log_with(event, log)).collect()
+}
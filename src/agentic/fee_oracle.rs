@@ -0,0 +1,201 @@
+use std::sync::{Arc, RwLock};
+use serde::{Deserialize, Serialize};
+use web3::types::{BlockNumber, U256};
+use web3::transports::Http;
+use web3::{Transport, Web3};
+
+const SLOW_PERCENTILE: f64 = 10.0;
+const NORMAL_PERCENTILE: f64 = 50.0;
+const FAST_PERCENTILE: f64 = 90.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FeeSpeed {
+    Slow,
+    Normal,
+    Fast,
+}
+
+impl Default for FeeSpeed {
+    fn default() -> Self {
+        FeeSpeed::Normal
+    }
+}
+
+impl FeeSpeed {
+    fn percentile_index(&self) -> usize {
+        match self {
+            FeeSpeed::Slow => 0,
+            FeeSpeed::Normal => 1,
+            FeeSpeed::Fast => 2,
+        }
+    }
+
+    // Headroom for base fee increases while the transaction waits: each full block can
+    // raise the base fee by 12.5%, so slower presets are allowed fewer blocks of growth
+    fn base_fee_multiplier(&self) -> u64 {
+        match self {
+            FeeSpeed::Slow => 1,
+            FeeSpeed::Normal => 2,
+            FeeSpeed::Fast => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PercentileFee {
+    pub percentile: f64,
+    pub fee: U256,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeHistorySummary {
+    pub base_fee_per_gas: U256,
+    pub next_base_fee_per_gas: U256,
+    pub priority_fee_percentiles: Vec<PercentileFee>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeEstimate {
+    pub base_fee_per_gas: U256,
+    pub max_fee_per_gas: U256,
+    pub max_priority_fee_per_gas: U256,
+}
+
+#[derive(Debug, Clone)]
+pub struct FeeOracleConfig {
+    pub block_count: u64,
+    pub min_priority_fee_per_gas: U256,
+    // Upper bound on max_fee_per_gas for everything this oracle prices, e.g. an agent's budget.
+    // Initial value only; FeeOracle::set_fee_cap changes it for every holder of the oracle.
+    pub fee_cap: Option<U256>,
+}
+
+impl Default for FeeOracleConfig {
+    fn default() -> Self {
+        Self {
+            block_count: 20,
+            min_priority_fee_per_gas: U256::from(100_000_000u64), // 0.1 gwei
+            fee_cap: None,
+        }
+    }
+}
+
+// Meant to be shared as an Arc<FeeOracle> between the wallet manager, contracts and
+// protocols of one agent, so its fee cap covers every send
+#[derive(Debug)]
+pub struct FeeOracle<T: Transport = Http> {
+    web3: Arc<Web3<T>>,
+    config: FeeOracleConfig,
+    fee_cap: RwLock<Option<U256>>,
+}
+
+impl<T: Transport> FeeOracle<T> {
+    pub fn new(web3: Arc<Web3<T>>) -> Self {
+        Self::with_config(web3, FeeOracleConfig::default())
+    }
+
+    pub fn with_config(web3: Arc<Web3<T>>, config: FeeOracleConfig) -> Self {
+        let fee_cap = RwLock::new(config.fee_cap);
+        Self { web3, config, fee_cap }
+    }
+
+    pub fn fee_cap(&self) -> Option<U256> {
+        *self.fee_cap.read().unwrap()
+    }
+
+    pub fn set_fee_cap(&self, fee_cap: Option<U256>) {
+        *self.fee_cap.write().unwrap() = fee_cap;
+    }
+
+    pub async fn fee_history(&self) -> Result<FeeHistorySummary, Box<dyn std::error::Error>> {
+        let percentiles = vec![SLOW_PERCENTILE, NORMAL_PERCENTILE, FAST_PERCENTILE];
+        let history = self.web3.eth().fee_history(
+            U256::from(self.config.block_count),
+            BlockNumber::Latest,
+            Some(percentiles.clone()),
+        ).await?;
+
+        // base_fee_per_gas has one entry per block plus the next block's base fee
+        let next_base_fee_per_gas = *history.base_fee_per_gas.last()
+            .ok_or("Node returned an empty fee history, EIP-1559 may not be active")?;
+        let base_fee_per_gas = history.base_fee_per_gas.iter().rev().nth(1).cloned().unwrap_or(next_base_fee_per_gas);
+
+        let rewards = history.reward.unwrap_or_default();
+        let priority_fee_percentiles = percentiles.iter()
+            .enumerate()
+            .map(|(index, percentile)| {
+                let mut samples: Vec<U256> = rewards.iter()
+                    .filter_map(|block| block.get(index).cloned())
+                    .collect();
+                samples.sort();
+                // Median across blocks so one outlier block doesn't move the estimate
+                let fee = samples.get(samples.len() / 2).cloned().unwrap_or_default();
+                PercentileFee { percentile: *percentile, fee }
+            })
+            .collect();
+
+        Ok(FeeHistorySummary {
+            base_fee_per_gas,
+            next_base_fee_per_gas,
+            priority_fee_percentiles,
+        })
+    }
+
+    pub async fn estimate(&self, speed: FeeSpeed) -> Result<FeeEstimate, Box<dyn std::error::Error>> {
+        let summary = self.fee_history().await?;
+        self.estimate_from(&summary, speed)
+    }
+
+    pub fn estimate_from(&self, summary: &FeeHistorySummary, speed: FeeSpeed) -> Result<FeeEstimate, Box<dyn std::error::Error>> {
+        let base_fee = summary.next_base_fee_per_gas;
+        let mut priority_fee = summary.priority_fee_percentiles
+            .get(speed.percentile_index())
+            .map(|entry| entry.fee)
+            .unwrap_or_default();
+        if priority_fee < self.config.min_priority_fee_per_gas {
+            priority_fee = self.config.min_priority_fee_per_gas;
+        }
+
+        let mut max_fee = base_fee * U256::from(speed.base_fee_multiplier()) + priority_fee;
+
+        if let Some(cap) = self.fee_cap() {
+            if cap <= base_fee {
+                return Err(format!("Fee cap {} wei is at or below the current base fee {} wei", cap, base_fee).into());
+            }
+            if max_fee > cap {
+                max_fee = cap;
+            }
+            if priority_fee > max_fee - base_fee {
+                priority_fee = max_fee - base_fee;
+            }
+        }
+
+        Ok(FeeEstimate {
+            base_fee_per_gas: base_fee,
+            max_fee_per_gas: max_fee,
+            max_priority_fee_per_gas: priority_fee,
+        })
+    }
+
+    // Fees for a replacement transaction: at least `bump_per_mille` of the original on both
+    // fields, as nodes require, and never below what the oracle would pay now
+    pub async fn replacement(&self, max_fee_per_gas: U256, max_priority_fee_per_gas: U256, bump_per_mille: u64) -> Result<FeeEstimate, Box<dyn std::error::Error>> {
+        let current = self.estimate(FeeSpeed::Fast).await?;
+        let bump = |value: U256| value * U256::from(bump_per_mille) / U256::from(1000) + U256::one();
+
+        let max_priority_fee_per_gas = std::cmp::max(bump(max_priority_fee_per_gas), current.max_priority_fee_per_gas);
+        let max_fee_per_gas = std::cmp::max(bump(max_fee_per_gas), current.max_fee_per_gas);
+
+        if let Some(cap) = self.fee_cap() {
+            if max_fee_per_gas > cap {
+                return Err(format!("Replacement needs {} wei per gas, above the fee cap of {} wei", max_fee_per_gas, cap).into());
+            }
+        }
+
+        Ok(FeeEstimate {
+            base_fee_per_gas: current.base_fee_per_gas,
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+        })
+    }
+}
@@ -0,0 +1,238 @@
+use std::fmt;
+use std::str::FromStr;
+use bip39::Mnemonic;
+use hmac::{Hmac, Mac};
+use secp256k1::{PublicKey, Secp256k1};
+use sha2::Sha512;
+use web3::signing::{keccak256, SecretKey};
+
+const HARDENED: u32 = 0x8000_0000;
+
+// BIP-44 prefix for Ethereum accounts; account i lives at m/44'/60'/0'/0/i
+pub const ETHEREUM_PATH_PREFIX: &str = "m/44'/60'/0'/0";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MnemonicLength {
+    Words12,
+    Words24,
+}
+
+impl MnemonicLength {
+    fn entropy_bytes(&self) -> usize {
+        match self {
+            MnemonicLength::Words12 => 16,
+            MnemonicLength::Words24 => 32,
+        }
+    }
+}
+
+pub fn generate_mnemonic(length: MnemonicLength) -> Result<String, Box<dyn std::error::Error>> {
+    let entropy: Vec<u8> = (0..length.entropy_bytes()).map(|_| rand::random::<u8>()).collect();
+    Ok(Mnemonic::from_entropy(&entropy)?.to_string())
+}
+
+// Validates the checksum and word list; the passphrase is BIP-39's optional "25th word"
+pub fn seed_from_mnemonic(phrase: &str, passphrase: &str) -> Result<[u8; 64], Box<dyn std::error::Error>> {
+    let mnemonic = Mnemonic::parse(phrase.trim())?;
+    match mnemonic.word_count() {
+        12 | 15 | 18 | 21 | 24 => Ok(mnemonic.to_seed(passphrase)),
+        count => Err(format!("Unsupported mnemonic length of {} words", count).into()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    pub fn ethereum(index: u32) -> Self {
+        format!("{}/{}", ETHEREUM_PATH_PREFIX, index).parse().expect("static path is valid")
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.0
+    }
+
+    // The last component of a standard m/44'/60'/0'/0/i path
+    pub fn ethereum_index(&self) -> Option<u32> {
+        let prefix = DerivationPath::from_str(ETHEREUM_PATH_PREFIX).ok()?;
+        match self.0.split_last() {
+            Some((&last, parent)) if parent == prefix.indices() && last < HARDENED => Some(last),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let mut components = path.trim().split('/');
+        if components.next() != Some("m") {
+            return Err(format!("Derivation path {} must start with m", path).into());
+        }
+
+        let indices = components
+            .map(|component| {
+                let (number, hardened) = match component.strip_suffix('\'').or_else(|| component.strip_suffix('h')) {
+                    Some(number) => (number, true),
+                    None => (component, false),
+                };
+                let index: u32 = number.parse().map_err(|_| format!("Invalid derivation path component {}", component))?;
+                if index >= HARDENED {
+                    return Err(format!("Derivation path component {} is out of range", component));
+                }
+                Ok(if hardened { index | HARDENED } else { index })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(DerivationPath(indices))
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m")?;
+        for index in &self.0 {
+            if index & HARDENED != 0 {
+                write!(f, "/{}'", index & !HARDENED)?;
+            } else {
+                write!(f, "/{}", index)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// BIP-32 extended private key
+struct ExtendedKey {
+    secret_key: SecretKey,
+    chain_code: [u8; 32],
+}
+
+impl ExtendedKey {
+    fn master(seed: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let (key, chain_code) = hmac_sha512(b"Bitcoin seed", &[seed]);
+        Ok(Self {
+            secret_key: SecretKey::from_slice(&key)?,
+            chain_code,
+        })
+    }
+
+    fn child(&self, index: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        let index_bytes = index.to_be_bytes();
+        let (tweak, chain_code) = if index & HARDENED != 0 {
+            hmac_sha512(&self.chain_code, &[&[0u8], &self.secret_key.secret_bytes(), &index_bytes])
+        } else {
+            let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &self.secret_key);
+            hmac_sha512(&self.chain_code, &[&public_key.serialize(), &index_bytes])
+        };
+
+        // parse256(IL) + k mod n; secp256k1 rejects IL >= n and a zero result, which
+        // BIP-32 says to treat as an invalid index
+        let mut secret_key = self.secret_key;
+        secret_key.add_assign(&tweak).map_err(|_| format!("Index {} derives an invalid key, use the next one", index))?;
+
+        Ok(Self { secret_key, chain_code })
+    }
+}
+
+fn hmac_sha512(key: &[u8], parts: &[&[u8]]) -> ([u8; 32], [u8; 32]) {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    for part in parts {
+        mac.update(part);
+    }
+    let output = mac.finalize().into_bytes();
+
+    let mut left = [0u8; 32];
+    let mut right = [0u8; 32];
+    left.copy_from_slice(&output[..32]);
+    right.copy_from_slice(&output[32..]);
+    (left, right)
+}
+
+pub fn derive_key(seed: &[u8], path: &DerivationPath) -> Result<SecretKey, Box<dyn std::error::Error>> {
+    let mut key = ExtendedKey::master(seed)?;
+    for index in path.indices() {
+        key = key.child(*index)?;
+    }
+    Ok(key.secret_key)
+}
+
+// Identifies a seed without revealing it, so wallets derived from the same seed can be grouped
+pub fn seed_id(seed: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
+    let master = ExtendedKey::master(seed)?;
+    let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &master.secret_key);
+    Ok(hex::encode(&keccak256(&public_key.serialize())[..8]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use web3::types::Address;
+    use super::super::secret::PrivateKey;
+
+    const ABANDON_MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+    // The default mnemonic of Hardhat and Anvil, whose first accounts are well known
+    const TEST_JUNK_MNEMONIC: &str = "test test test test test test test test test test test junk";
+
+    fn address(hex: &str) -> Address {
+        hex.parse().unwrap()
+    }
+
+    #[test]
+    fn bip39_seed_matches_the_reference_vector() {
+        let seed = seed_from_mnemonic(ABANDON_MNEMONIC, "").unwrap();
+        assert_eq!(
+            hex::encode(seed),
+            "5eb00bbddcf069084889a8ab9155568165f5c453ccb85e70811aaed6f6da5fc19a5ac40b389cd370d086206dec8aa6c43daea6690f20ad3d8d48b2d2ce9e38e4",
+        );
+    }
+
+    #[test]
+    fn rejects_mnemonics_with_a_bad_checksum() {
+        let phrase = ABANDON_MNEMONIC.replace("about", "abandon");
+        assert!(seed_from_mnemonic(&phrase, "").is_err());
+    }
+
+    #[test]
+    fn bip32_test_vector_1() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let vectors = [
+            ("m", "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35"),
+            ("m/0'", "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea"),
+            ("m/0'/1", "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368"),
+            ("m/0'/1/2'", "cbce0d719ecf7431d88e6a89fa1483e02e35092af60c042b1df2ff59fa424dca"),
+            ("m/0'/1/2'/2", "0f479245fb19a38a1954c5c7c0ebab2f9bdfd96a17563ef28a6a4b1a2a764ef4"),
+            ("m/0'/1/2'/2/1000000000", "471b76e389e528d6de6d816857e012c5455051cad6660850e58372a6c3e6e7c8"),
+        ];
+
+        for (path, expected) in vectors {
+            let key = derive_key(&seed, &path.parse().unwrap()).unwrap();
+            assert_eq!(hex::encode(key.secret_bytes()), expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn derives_known_ethereum_accounts() {
+        let seed = seed_from_mnemonic(ABANDON_MNEMONIC, "").unwrap();
+        let key = PrivateKey::from_secret_key(&derive_key(&seed, &DerivationPath::ethereum(0)).unwrap());
+        assert_eq!(key.address(), address("9858effd232b4033e47d90003d41ec34ecaeda94"));
+
+        let seed = seed_from_mnemonic(TEST_JUNK_MNEMONIC, "").unwrap();
+        let first = PrivateKey::from_secret_key(&derive_key(&seed, &DerivationPath::ethereum(0)).unwrap());
+        assert_eq!(hex::encode(first.expose_bytes()), "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80");
+        assert_eq!(first.address(), address("f39fd6e51aad88f6f4ce6ab8827279cfffb92266"));
+
+        let second = PrivateKey::from_secret_key(&derive_key(&seed, &DerivationPath::ethereum(1)).unwrap());
+        assert_eq!(second.address(), address("70997970c51812dc3a010c7d01b50e0d17dc79c8"));
+    }
+
+    #[test]
+    fn derivation_paths_round_trip() {
+        let path = DerivationPath::ethereum(7);
+        assert_eq!(path.to_string(), "m/44'/60'/0'/0/7");
+        assert_eq!(path.ethereum_index(), Some(7));
+        assert_eq!("m/44h/60h/0h/0/7".parse::<DerivationPath>().unwrap(), path);
+        assert!("44'/60'".parse::<DerivationPath>().is_err());
+    }
+}
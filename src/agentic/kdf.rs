@@ -0,0 +1,208 @@
+use std::time::{Duration, Instant};
+use argon2::{Algorithm, Argon2, Params, Version};
+use hmac::Hmac;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+// Upper bounds for parameters read back from disk, so a tampered keystore entry can't
+// make an unlock take minutes or gigabytes
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+const MAX_SCRYPT_LOG_N: u8 = 20;
+// scrypt needs 128·r·N bytes
+const MAX_SCRYPT_MEMORY: u64 = 1 << 30;
+const MAX_SCRYPT_P: u32 = 16;
+const MAX_ARGON2_MEMORY_KIB: u32 = 1024 * 1024;
+const MAX_ARGON2_ITERATIONS: u32 = 64;
+const MAX_ARGON2_PARALLELISM: u32 = 16;
+
+// OWASP's floor for argon2id is 19 MiB; benchmarks never go below it
+const MIN_ARGON2_MEMORY_KIB: u32 = 19 * 1024;
+
+// Declared weakest first, so the derived ordering ranks algorithms by strength
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum KdfAlgorithm {
+    Pbkdf2,
+    Scrypt,
+    Argon2id,
+}
+
+// How a keystore entry's encryption key was derived from its password. Stored next to
+// each key and seed, so entries written with different settings can coexist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "algorithm", rename_all = "lowercase")]
+pub enum KdfParams {
+    Pbkdf2 { iterations: u32 },
+    Scrypt { log_n: u8, r: u32, p: u32 },
+    Argon2id { memory_kib: u32, iterations: u32, parallelism: u32 },
+}
+
+impl KdfParams {
+    // What every entry used before parameters were stored with it
+    pub fn legacy() -> Self {
+        KdfParams::Pbkdf2 { iterations: 10_000 }
+    }
+
+    // argon2id, 64 MiB, 3 passes
+    pub fn recommended() -> Self {
+        KdfParams::Argon2id { memory_kib: 64 * 1024, iterations: 3, parallelism: 1 }
+    }
+
+    pub fn algorithm(&self) -> KdfAlgorithm {
+        match self {
+            KdfParams::Pbkdf2 { .. } => KdfAlgorithm::Pbkdf2,
+            KdfParams::Scrypt { .. } => KdfAlgorithm::Scrypt,
+            KdfParams::Argon2id { .. } => KdfAlgorithm::Argon2id,
+        }
+    }
+
+    // True when an entry should be re-encrypted under `current`: a weaker algorithm
+    // (pbkdf2 < scrypt < argon2id), or the same one at a lower cost. Entries stronger than
+    // `current` are left alone.
+    pub fn is_outdated(&self, current: &KdfParams) -> bool {
+        match (self, current) {
+            (KdfParams::Pbkdf2 { iterations }, KdfParams::Pbkdf2 { iterations: current }) => iterations < current,
+            (KdfParams::Scrypt { log_n, r, p }, KdfParams::Scrypt { log_n: current_log_n, r: current_r, p: current_p }) => {
+                scrypt_cost(*log_n, *r, *p) < scrypt_cost(*current_log_n, *current_r, *current_p)
+            }
+            (
+                KdfParams::Argon2id { memory_kib, iterations, .. },
+                KdfParams::Argon2id { memory_kib: current_memory, iterations: current_iterations, .. },
+            ) => memory_kib < current_memory || (*memory_kib as u64) * (*iterations as u64) < (*current_memory as u64) * (*current_iterations as u64),
+            _ => self.algorithm() < current.algorithm(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        match *self {
+            KdfParams::Pbkdf2 { iterations } => {
+                if iterations == 0 || iterations > MAX_PBKDF2_ITERATIONS {
+                    return Err(format!("pbkdf2 iteration count {} is out of range", iterations).into());
+                }
+            }
+            KdfParams::Scrypt { log_n, r, p } => {
+                let memory = 128u64 * r as u64 * (1u64 << log_n.min(MAX_SCRYPT_LOG_N));
+                if log_n == 0 || log_n > MAX_SCRYPT_LOG_N || r == 0 || memory > MAX_SCRYPT_MEMORY || p == 0 || p > MAX_SCRYPT_P {
+                    return Err(format!("scrypt parameters n = 2^{}, r = {}, p = {} are out of range", log_n, r, p).into());
+                }
+            }
+            KdfParams::Argon2id { memory_kib, iterations, parallelism } => {
+                if memory_kib > MAX_ARGON2_MEMORY_KIB || iterations == 0 || iterations > MAX_ARGON2_ITERATIONS || parallelism == 0 || parallelism > MAX_ARGON2_PARALLELISM {
+                    return Err(format!("argon2id parameters m = {} KiB, t = {}, p = {} are out of range", memory_kib, iterations, parallelism).into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn derive_key(&self, password: &str, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>, Box<dyn std::error::Error>> {
+        self.validate()?;
+
+        let mut key = Zeroizing::new([0u8; 32]);
+        match *self {
+            KdfParams::Pbkdf2 { iterations } => {
+                pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt, iterations, key.as_mut());
+            }
+            KdfParams::Scrypt { log_n, r, p } => {
+                let params = scrypt::Params::new(log_n, r, p).map_err(|e| format!("Invalid scrypt parameters: {}", e))?;
+                scrypt::scrypt(password.as_bytes(), salt, &params, key.as_mut()).map_err(|e| format!("scrypt failed: {}", e))?;
+            }
+            KdfParams::Argon2id { memory_kib, iterations, parallelism } => {
+                let params = Params::new(memory_kib, iterations, parallelism, Some(32)).map_err(|e| format!("Invalid argon2id parameters: {}", e))?;
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(password.as_bytes(), salt, key.as_mut())
+                    .map_err(|e| format!("argon2id failed: {}", e))?;
+            }
+        }
+        Ok(key)
+    }
+}
+
+// N·r·p, saturating: neither side of is_outdated has necessarily been validated
+fn scrypt_cost(log_n: u8, r: u32, p: u32) -> u64 {
+    1u64.checked_shl(log_n as u32)
+        .unwrap_or(u64::MAX)
+        .saturating_mul(r as u64)
+        .saturating_mul(p as u64)
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self::recommended()
+    }
+}
+
+// Finds the strongest parameters for `algorithm` that derive a key in about `target` on
+// this host. Run it once at setup and pass the result to WalletManager::with_kdf; it
+// takes a few multiples of `target` to run.
+pub fn benchmark(algorithm: KdfAlgorithm, target: Duration) -> Result<KdfParams, Box<dyn std::error::Error>> {
+    let salt = [0u8; 32];
+
+    match algorithm {
+        KdfAlgorithm::Pbkdf2 => {
+            // pbkdf2 is linear in the iteration count, so one sample is enough
+            let sample = 100_000u32;
+            let elapsed = time(&KdfParams::Pbkdf2 { iterations: sample }, &salt)?;
+            let scaled = sample as f64 * target.as_secs_f64() / elapsed.as_secs_f64().max(1e-6);
+            Ok(KdfParams::Pbkdf2 { iterations: (scaled as u32).clamp(sample, MAX_PBKDF2_ITERATIONS) })
+        }
+        KdfAlgorithm::Scrypt => {
+            // Double n until the next step would overshoot
+            let mut log_n = 14;
+            while log_n < MAX_SCRYPT_LOG_N && time(&KdfParams::Scrypt { log_n, r: 8, p: 1 }, &salt)? * 2 <= target {
+                log_n += 1;
+            }
+            Ok(KdfParams::Scrypt { log_n, r: 8, p: 1 })
+        }
+        KdfAlgorithm::Argon2id => {
+            // Memory is the part that hurts attackers, so spend the budget there first,
+            // then add passes
+            let mut memory_kib = MIN_ARGON2_MEMORY_KIB;
+            while memory_kib * 2 <= MAX_ARGON2_MEMORY_KIB
+                && time(&KdfParams::Argon2id { memory_kib: memory_kib * 2, iterations: 2, parallelism: 1 }, &salt)? <= target
+            {
+                memory_kib *= 2;
+            }
+
+            let mut iterations = 2;
+            while iterations < MAX_ARGON2_ITERATIONS
+                && time(&KdfParams::Argon2id { memory_kib, iterations: iterations + 1, parallelism: 1 }, &salt)? <= target
+            {
+                iterations += 1;
+            }
+            Ok(KdfParams::Argon2id { memory_kib, iterations, parallelism: 1 })
+        }
+    }
+}
+
+fn time(params: &KdfParams, salt: &[u8]) -> Result<Duration, Box<dyn std::error::Error>> {
+    let started = Instant::now();
+    params.derive_key("benchmark", salt)?;
+    Ok(started.elapsed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weaker_algorithms_and_lower_costs_are_outdated() {
+        let scrypt = KdfParams::Scrypt { log_n: 15, r: 8, p: 1 };
+        assert!(KdfParams::legacy().is_outdated(&scrypt));
+        assert!(scrypt.is_outdated(&KdfParams::recommended()));
+        assert!(!KdfParams::recommended().is_outdated(&scrypt));
+
+        assert!(KdfParams::Scrypt { log_n: 14, r: 8, p: 1 }.is_outdated(&scrypt));
+        assert!(!KdfParams::Scrypt { log_n: 14, r: 8, p: 2 }.is_outdated(&scrypt));
+    }
+
+    #[test]
+    fn out_of_range_scrypt_parameters_do_not_overflow() {
+        let huge = KdfParams::Scrypt { log_n: 200, r: u32::MAX, p: u32::MAX };
+        let standard = KdfParams::Scrypt { log_n: 18, r: 8, p: 1 };
+        assert!(huge.validate().is_err());
+        assert!(standard.is_outdated(&huge));
+        assert!(!huge.is_outdated(&standard));
+        assert!(!huge.is_outdated(&huge));
+    }
+}
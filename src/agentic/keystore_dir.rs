@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use web3::types::Address;
+
+use super::address_book::AddressBook;
+use super::wallet_manager::{EncryptedKey, EncryptedSeed, Wallet};
+
+// 1: unversioned backup JSON ({ wallets, keystore }, later with seeds)
+// 2: versioned backups and the one-file-per-key directory layout
+// 3: backup maps keyed by full address instead of web3's abbreviated Display string
+pub const SCHEMA_VERSION: u32 = 3;
+
+const LOCK_FILE: &str = ".lock";
+const KEYS_DIR: &str = "keys";
+const SEEDS_DIR: &str = "seeds";
+const ADDRESS_BOOK_FILE: &str = "address_book.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredKey {
+    pub schema_version: u32,
+    pub wallet: Wallet,
+    pub key: EncryptedKey,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredSeed {
+    pub schema_version: u32,
+    pub seed: EncryptedSeed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredAddressBook {
+    pub schema_version: u32,
+    pub address_book: AddressBook,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackupContents {
+    pub schema_version: u32,
+    pub wallets: HashMap<Address, Wallet>,
+    pub keystore: HashMap<Address, EncryptedKey>,
+    #[serde(default)]
+    pub seeds: HashMap<String, EncryptedSeed>,
+    #[serde(default)]
+    pub address_book: AddressBook,
+}
+
+impl BackupContents {
+    // Loads any backup this crate has written, upgrading older schemas in memory
+    pub fn parse(json: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut value: serde_json::Value = serde_json::from_str(json)?;
+        let version = schema_version(&value);
+        if version > SCHEMA_VERSION {
+            return Err(format!("Backup schema version {} is newer than this build supports ({})", version, SCHEMA_VERSION).into());
+        }
+
+        if version < 3 {
+            let legacy: LegacyBackupContents = serde_json::from_value(value)?;
+            return Ok(legacy.upgrade());
+        }
+
+        value["schema_version"] = serde_json::json!(SCHEMA_VERSION);
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+// Versions 1 and 2 keyed both maps by strings like "0x1234…abcd", which can't be parsed
+// back; the values carry the full address, so the maps are rebuilt from those
+#[derive(Deserialize)]
+struct LegacyBackupContents {
+    wallets: HashMap<String, Wallet>,
+    keystore: HashMap<String, EncryptedKey>,
+    #[serde(default)]
+    seeds: HashMap<String, EncryptedSeed>,
+}
+
+impl LegacyBackupContents {
+    fn upgrade(self) -> BackupContents {
+        BackupContents {
+            schema_version: SCHEMA_VERSION,
+            wallets: self.wallets.into_values().map(|wallet| (wallet.address, wallet)).collect(),
+            keystore: self.keystore.into_values().map(|key| (key.address, key)).collect(),
+            seeds: self.seeds,
+            address_book: AddressBook::default(),
+        }
+    }
+}
+
+fn schema_version(value: &serde_json::Value) -> u32 {
+    value.get("schema_version").and_then(|version| version.as_u64()).unwrap_or(1) as u32
+}
+
+// A keystore directory that several processes can share: one file per key or seed,
+// written atomically, readable only by the owner, and guarded by an advisory lock on
+// `<root>/.lock` (exclusive for writes, shared for reads).
+#[derive(Debug, Clone)]
+pub struct KeystoreDir {
+    root: PathBuf,
+}
+
+impl KeystoreDir {
+    pub fn open(root: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let root = root.as_ref().to_path_buf();
+        for dir in [root.clone(), root.join(KEYS_DIR), root.join(SEEDS_DIR)] {
+            fs::create_dir_all(&dir)?;
+            set_permissions(&dir, 0o700)?;
+        }
+        Ok(Self { root })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn save_key(&self, wallet: &Wallet, key: &EncryptedKey) -> Result<(), Box<dyn std::error::Error>> {
+        let stored = StoredKey {
+            schema_version: SCHEMA_VERSION,
+            wallet: wallet.clone(),
+            key: key.clone(),
+        };
+        let path = self.key_path(wallet.address);
+        self.locked(true, || write_atomic(&path, &serde_json::to_vec_pretty(&stored)?))
+    }
+
+    // Read-modify-write of one key file under a single exclusive lock, so processes sharing
+    // the directory merge into each other's writes instead of overwriting them. `merge`
+    // gets the stored entry, if there is one, and returns what to write.
+    pub fn update_key(&self, address: Address, merge: impl FnOnce(Option<StoredKey>) -> StoredKey) -> Result<StoredKey, Box<dyn std::error::Error>> {
+        let path = self.key_path(address);
+        self.locked(true, || {
+            let current = if path.exists() {
+                let value: serde_json::Value = serde_json::from_slice(&fs::read(&path)?)?;
+                check_version(&value)?;
+                Some(serde_json::from_value::<StoredKey>(value)?)
+            } else {
+                None
+            };
+
+            let mut stored = merge(current);
+            stored.schema_version = SCHEMA_VERSION;
+            write_atomic(&path, &serde_json::to_vec_pretty(&stored)?)?;
+            Ok(stored)
+        })
+    }
+
+    pub fn delete_key(&self, address: Address) -> Result<(), Box<dyn std::error::Error>> {
+        let path = self.key_path(address);
+        self.locked(true, || {
+            if path.exists() {
+                fs::remove_file(&path)?;
+                sync_dir(path.parent().unwrap_or(&self.root))?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn load_keys(&self) -> Result<Vec<StoredKey>, Box<dyn std::error::Error>> {
+        self.locked(false, || {
+            read_json_files(&self.root.join(KEYS_DIR))?
+                .into_iter()
+                .map(|value| check_version(&value).and_then(|_| Ok(serde_json::from_value::<StoredKey>(value)?)))
+                .collect()
+        })
+    }
+
+    pub fn save_seed(&self, seed: &EncryptedSeed) -> Result<(), Box<dyn std::error::Error>> {
+        let stored = StoredSeed {
+            schema_version: SCHEMA_VERSION,
+            seed: seed.clone(),
+        };
+        let path = self.root.join(SEEDS_DIR).join(format!("{}.json", seed.seed_id));
+        self.locked(true, || write_atomic(&path, &serde_json::to_vec_pretty(&stored)?))
+    }
+
+    pub fn load_seeds(&self) -> Result<Vec<StoredSeed>, Box<dyn std::error::Error>> {
+        self.locked(false, || {
+            read_json_files(&self.root.join(SEEDS_DIR))?
+                .into_iter()
+                .map(|value| check_version(&value).and_then(|_| Ok(serde_json::from_value::<StoredSeed>(value)?)))
+                .collect()
+        })
+    }
+
+    pub fn save_address_book(&self, address_book: &AddressBook) -> Result<(), Box<dyn std::error::Error>> {
+        let stored = StoredAddressBook {
+            schema_version: SCHEMA_VERSION,
+            address_book: address_book.clone(),
+        };
+        let path = self.root.join(ADDRESS_BOOK_FILE);
+        self.locked(true, || write_atomic(&path, &serde_json::to_vec_pretty(&stored)?))
+    }
+
+    pub fn load_address_book(&self) -> Result<AddressBook, Box<dyn std::error::Error>> {
+        let path = self.root.join(ADDRESS_BOOK_FILE);
+        self.locked(false, || {
+            if !path.exists() {
+                return Ok(AddressBook::default());
+            }
+            let value: serde_json::Value = serde_json::from_slice(&fs::read(&path)?)?;
+            check_version(&value)?;
+            Ok(serde_json::from_value::<StoredAddressBook>(value)?.address_book)
+        })
+    }
+
+    fn key_path(&self, address: Address) -> PathBuf {
+        self.root.join(KEYS_DIR).join(format!("{:?}.json", address))
+    }
+
+    fn locked<R>(&self, exclusive: bool, f: impl FnOnce() -> Result<R, Box<dyn std::error::Error>>) -> Result<R, Box<dyn std::error::Error>> {
+        let lock_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(self.root.join(LOCK_FILE))?;
+        if exclusive {
+            lock_file.lock_exclusive()?;
+        } else {
+            lock_file.lock_shared()?;
+        }
+
+        let result = f();
+        lock_file.unlock()?;
+        result
+    }
+}
+
+fn check_version(value: &serde_json::Value) -> Result<(), Box<dyn std::error::Error>> {
+    let version = schema_version(value);
+    if version > SCHEMA_VERSION {
+        return Err(format!("Keystore file schema version {} is newer than this build supports ({})", version, SCHEMA_VERSION).into());
+    }
+    Ok(())
+}
+
+fn read_json_files(dir: &Path) -> Result<Vec<serde_json::Value>, Box<dyn std::error::Error>> {
+    let mut values = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        // Leftover temp files from an interrupted write are skipped, never half-read
+        if path.extension().and_then(|extension| extension.to_str()) != Some("json") {
+            continue;
+        }
+        values.push(serde_json::from_slice(&fs::read(&path)?)?);
+    }
+    Ok(values)
+}
+
+// Write to a temp file in the same directory, fsync it, rename over the target and fsync
+// the directory, so readers see either the old file or the new one and a crash loses neither
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = path.parent().ok_or("Keystore path has no parent directory")?;
+    let file_name = path.file_name().and_then(|name| name.to_str()).ok_or("Invalid keystore file name")?;
+    let temp_path = dir.join(format!(".{}.{}.tmp", file_name, hex::encode(rand::random::<[u8; 8]>())));
+
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let mut file = create_private(&temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)?;
+        sync_dir(dir)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+#[cfg(unix)]
+fn create_private(path: &Path) -> Result<File, Box<dyn std::error::Error>> {
+    use std::os::unix::fs::OpenOptionsExt;
+    Ok(OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)?)
+}
+
+#[cfg(not(unix))]
+fn create_private(path: &Path) -> Result<File, Box<dyn std::error::Error>> {
+    Ok(OpenOptions::new().write(true).create_new(true).open(path)?)
+}
+
+#[cfg(unix)]
+fn set_permissions(path: &Path, mode: u32) -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_permissions(_path: &Path, _mode: u32) -> Result<(), Box<dyn std::error::Error>> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    Ok(())
+}
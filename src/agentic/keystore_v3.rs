@@ -0,0 +1,322 @@
+use aes::Aes128;
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use hmac::Hmac;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use web3::signing::keccak256;
+use web3::types::Address;
+use zeroize::Zeroizing;
+
+use super::secret::PrivateKey;
+
+type Aes128Ctr = ctr::Ctr128BE<Aes128>;
+
+const CIPHER: &str = "aes-128-ctr";
+const DERIVED_KEY_LEN: usize = 32;
+// Reject parameters that would take minutes or gigabytes to derive from an untrusted file
+const MAX_SCRYPT_LOG_N: u8 = 20;
+// scrypt needs 128·r·N bytes; geth's standard parameters use 256 MiB
+const MAX_SCRYPT_MEMORY: u64 = 1 << 30;
+const MAX_SCRYPT_P: u32 = 16;
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+
+// Web3 Secret Storage, the keystore format shared by geth, MetaMask and foundry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeystoreV3 {
+    pub version: u8,
+    pub id: String,
+    #[serde(default)]
+    pub address: Option<String>,
+    // geth wrote "Crypto" in some releases
+    #[serde(alias = "Crypto")]
+    pub crypto: KeystoreCrypto,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeystoreCrypto {
+    pub cipher: String,
+    pub cipherparams: CipherParams,
+    pub ciphertext: String,
+    pub kdf: String,
+    pub kdfparams: serde_json::Value,
+    pub mac: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CipherParams {
+    pub iv: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ScryptParams {
+    dklen: usize,
+    n: u64,
+    r: u32,
+    p: u32,
+    salt: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Pbkdf2Params {
+    c: u32,
+    dklen: usize,
+    prf: String,
+    salt: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeystoreKdf {
+    Scrypt { log_n: u8, r: u32, p: u32 },
+    Pbkdf2 { iterations: u32 },
+}
+
+impl KeystoreKdf {
+    // geth's default: n = 2^18, r = 8, p = 1
+    pub fn standard() -> Self {
+        KeystoreKdf::Scrypt { log_n: 18, r: 8, p: 1 }
+    }
+
+    // geth's --lightkdf: n = 2^12, r = 8, p = 6
+    pub fn light() -> Self {
+        KeystoreKdf::Scrypt { log_n: 12, r: 8, p: 6 }
+    }
+}
+
+impl Default for KeystoreKdf {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+impl KeystoreV3 {
+    pub fn encrypt(private_key: &PrivateKey, password: &str, kdf: KeystoreKdf) -> Result<Self, Box<dyn std::error::Error>> {
+        let salt: [u8; 32] = rand::random();
+        let iv: [u8; 16] = rand::random();
+
+        let (kdf_name, kdfparams, derived_key) = match kdf {
+            KeystoreKdf::Scrypt { log_n, r, p } => {
+                let derived_key = derive_scrypt(password, &salt, log_n, r, p)?;
+                let params = ScryptParams { dklen: DERIVED_KEY_LEN, n: 1u64 << log_n, r, p, salt: hex::encode(salt) };
+                ("scrypt", serde_json::to_value(params)?, derived_key)
+            }
+            KeystoreKdf::Pbkdf2 { iterations } => {
+                let derived_key = derive_pbkdf2(password, &salt, iterations)?;
+                let params = Pbkdf2Params { c: iterations, dklen: DERIVED_KEY_LEN, prf: "hmac-sha256".to_string(), salt: hex::encode(salt) };
+                ("pbkdf2", serde_json::to_value(params)?, derived_key)
+            }
+        };
+
+        let mut ciphertext = private_key.expose_bytes().to_vec();
+        Aes128Ctr::new(derived_key[..16].into(), (&iv).into()).apply_keystream(&mut ciphertext);
+
+        let address = private_key.address();
+
+        Ok(Self {
+            version: 3,
+            id: random_uuid(),
+            address: Some(hex::encode(address.as_bytes())),
+            crypto: KeystoreCrypto {
+                cipher: CIPHER.to_string(),
+                cipherparams: CipherParams { iv: hex::encode(iv) },
+                ciphertext: hex::encode(&ciphertext),
+                kdf: kdf_name.to_string(),
+                kdfparams,
+                mac: hex::encode(mac(derived_key.as_ref(), &ciphertext)),
+            },
+        })
+    }
+
+    pub fn decrypt(&self, password: &str) -> Result<PrivateKey, Box<dyn std::error::Error>> {
+        if self.version != 3 {
+            return Err(format!("Unsupported keystore version {}", self.version).into());
+        }
+        if self.crypto.cipher != CIPHER {
+            return Err(format!("Unsupported keystore cipher {}", self.crypto.cipher).into());
+        }
+
+        let derived_key = match self.crypto.kdf.as_str() {
+            "scrypt" => {
+                let params: ScryptParams = serde_json::from_value(self.crypto.kdfparams.clone())?;
+                check_dklen(params.dklen)?;
+                if !params.n.is_power_of_two() || params.n < 2 {
+                    return Err("Keystore scrypt n must be a power of two".into());
+                }
+                let log_n = params.n.trailing_zeros() as u8;
+                derive_scrypt(password, &hex::decode(&params.salt)?, log_n, params.r, params.p)?
+            }
+            "pbkdf2" => {
+                let params: Pbkdf2Params = serde_json::from_value(self.crypto.kdfparams.clone())?;
+                check_dklen(params.dklen)?;
+                if params.prf != "hmac-sha256" {
+                    return Err(format!("Unsupported keystore PRF {}", params.prf).into());
+                }
+                derive_pbkdf2(password, &hex::decode(&params.salt)?, params.c)?
+            }
+            other => return Err(format!("Unsupported keystore KDF {}", other).into()),
+        };
+
+        // Decrypted in place, so the buffer holds the plaintext key afterwards
+        let mut ciphertext = Zeroizing::new(hex::decode(&self.crypto.ciphertext)?);
+        let expected_mac = hex::decode(&self.crypto.mac)?;
+        if mac(derived_key.as_ref(), &ciphertext).as_slice() != expected_mac.as_slice() {
+            return Err("Keystore MAC mismatch, wrong password?".into());
+        }
+
+        let iv = hex::decode(&self.crypto.cipherparams.iv)?;
+        if iv.len() != 16 {
+            return Err("Keystore IV must be 16 bytes".into());
+        }
+        Aes128Ctr::new(derived_key[..16].into(), iv.as_slice().into()).apply_keystream(ciphertext.as_mut_slice());
+
+        let private_key = PrivateKey::from_slice(&ciphertext)?;
+
+        if let Some(address) = self.address()? {
+            if private_key.address() != address {
+                return Err("Keystore address does not match the decrypted key".into());
+            }
+        }
+
+        Ok(private_key)
+    }
+
+    pub fn address(&self) -> Result<Option<Address>, Box<dyn std::error::Error>> {
+        match &self.address {
+            Some(address) => Ok(Some(address.trim_start_matches("0x").parse()?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+// RFC 4122 version 4
+fn random_uuid() -> String {
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = hex::encode(bytes);
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+fn check_dklen(dklen: usize) -> Result<(), Box<dyn std::error::Error>> {
+    if dklen != DERIVED_KEY_LEN {
+        return Err(format!("Unsupported keystore dklen {}", dklen).into());
+    }
+    Ok(())
+}
+
+fn derive_scrypt(password: &str, salt: &[u8], log_n: u8, r: u32, p: u32) -> Result<Zeroizing<[u8; DERIVED_KEY_LEN]>, Box<dyn std::error::Error>> {
+    if log_n > MAX_SCRYPT_LOG_N {
+        return Err(format!("Keystore scrypt n = 2^{} is above the supported maximum", log_n).into());
+    }
+    // Checked before scrypt allocates, since a failed multi-GB allocation aborts the process
+    let memory = 128u64.checked_mul(r as u64).and_then(|bytes| bytes.checked_shl(log_n as u32));
+    if r == 0 || memory.map_or(true, |memory| memory > MAX_SCRYPT_MEMORY) {
+        return Err(format!("Keystore scrypt r = {} with n = 2^{} needs more memory than allowed", r, log_n).into());
+    }
+    if p == 0 || p > MAX_SCRYPT_P {
+        return Err(format!("Keystore scrypt p = {} is out of range", p).into());
+    }
+    let params = scrypt::Params::new(log_n, r, p).map_err(|e| format!("Invalid scrypt parameters: {}", e))?;
+    let mut derived_key = Zeroizing::new([0u8; DERIVED_KEY_LEN]);
+    scrypt::scrypt(password.as_bytes(), salt, &params, derived_key.as_mut()).map_err(|e| format!("scrypt failed: {}", e))?;
+    Ok(derived_key)
+}
+
+fn derive_pbkdf2(password: &str, salt: &[u8], iterations: u32) -> Result<Zeroizing<[u8; DERIVED_KEY_LEN]>, Box<dyn std::error::Error>> {
+    if iterations == 0 || iterations > MAX_PBKDF2_ITERATIONS {
+        return Err(format!("Keystore pbkdf2 iteration count {} is out of range", iterations).into());
+    }
+    let mut derived_key = Zeroizing::new([0u8; DERIVED_KEY_LEN]);
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt, iterations, derived_key.as_mut());
+    Ok(derived_key)
+}
+
+fn mac(derived_key: &[u8], ciphertext: &[u8]) -> [u8; 32] {
+    let mut payload = Zeroizing::new(Vec::with_capacity(16 + ciphertext.len()));
+    payload.extend_from_slice(&derived_key[16..32]);
+    payload.extend_from_slice(ciphertext);
+    keccak256(&payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The test vectors from the Web3 Secret Storage definition
+    const PASSWORD: &str = "testpassword";
+    const PRIVATE_KEY_HEX: &str = "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d";
+
+    const PBKDF2_KEYSTORE: &str = r#"{
+        "crypto": {
+            "cipher": "aes-128-ctr",
+            "cipherparams": { "iv": "6087dab2f9fdbbfaddc31a909735c1e6" },
+            "ciphertext": "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+            "kdf": "pbkdf2",
+            "kdfparams": {
+                "c": 262144,
+                "dklen": 32,
+                "prf": "hmac-sha256",
+                "salt": "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+            },
+            "mac": "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+        },
+        "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+        "version": 3
+    }"#;
+
+    const SCRYPT_KEYSTORE: &str = r#"{
+        "crypto": {
+            "cipher": "aes-128-ctr",
+            "cipherparams": { "iv": "83dbcc02d8ccb40e466191a123791e0e" },
+            "ciphertext": "d172bf743a674da9cdad04534d56926ef8358534d458fffccd4e6ad2fbde479c",
+            "kdf": "scrypt",
+            "kdfparams": {
+                "dklen": 32,
+                "n": 262144,
+                "p": 8,
+                "r": 1,
+                "salt": "ab0c7876052600dd703518d6fc3fe8984592145b591fc8fb5c6d43190334ba19"
+            },
+            "mac": "2103ac29920d71da29f15d75b4a16dbe95cfd7ff8faea1056c33131d846e3097"
+        },
+        "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+        "version": 3
+    }"#;
+
+    #[test]
+    fn decrypts_the_pbkdf2_test_vector() {
+        let keystore = KeystoreV3::from_json(PBKDF2_KEYSTORE).unwrap();
+        let private_key = keystore.decrypt(PASSWORD).unwrap();
+        assert_eq!(hex::encode(private_key.expose_bytes()), PRIVATE_KEY_HEX);
+    }
+
+    #[test]
+    fn decrypts_the_scrypt_test_vector() {
+        let keystore = KeystoreV3::from_json(SCRYPT_KEYSTORE).unwrap();
+        let private_key = keystore.decrypt(PASSWORD).unwrap();
+        assert_eq!(hex::encode(private_key.expose_bytes()), PRIVATE_KEY_HEX);
+    }
+
+    #[test]
+    fn wrong_password_fails_the_mac_check() {
+        let keystore = KeystoreV3::from_json(PBKDF2_KEYSTORE).unwrap();
+        assert!(keystore.decrypt("wrongpassword").is_err());
+    }
+
+    #[test]
+    fn encrypted_keystores_round_trip_through_json() {
+        let private_key = PrivateKey::from_slice(&hex::decode(PRIVATE_KEY_HEX).unwrap()).unwrap();
+        let keystore = KeystoreV3::encrypt(&private_key, PASSWORD, KeystoreKdf::Pbkdf2 { iterations: 1_000 }).unwrap();
+
+        let parsed = KeystoreV3::from_json(&keystore.to_json().unwrap()).unwrap();
+        assert_eq!(parsed.address().unwrap(), Some(private_key.address()));
+        assert_eq!(parsed.decrypt(PASSWORD).unwrap().expose_bytes(), private_key.expose_bytes());
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use web3::transports::Http;
+use web3::error::TransportError;
+use web3::types::{Address, BlockNumber, FilterBuilder, Log, H256, U256};
+use web3::{Transport, Web3};
+
+use super::keystore_dir;
+
+// Phrases nodes and hosted providers use when a getLogs range holds too many results
+// (Infura -32005, Alchemy, QuickNode, geth and erigon limits)
+const RANGE_ERROR_HINTS: &[&str] = &[
+    "more than",
+    "block range",
+    "blocks range",
+    "range is too large",
+    "range too large",
+    "response size",
+    "query timeout",
+];
+
+// Throttling, which is retried rather than split. Infura reports its rate limits as -32005
+// too, so the code alone doesn't decide. An HTTP 429 is matched on the transport's status
+// code, never as a substring, since block numbers in range messages can contain "429".
+const RATE_LIMIT_HINTS: &[&str] = &[
+    "too many requests",
+    "rate limit",
+    "rate-limit",
+    "rate limited",
+    "request limit",
+    "daily request count",
+    "compute units",
+    "throughput",
+];
+
+#[derive(Debug, Clone)]
+pub struct LogFetcherConfig {
+    // Blocks per request to start with; adapts as requests fail or succeed
+    pub initial_chunk: u64,
+    pub max_chunk: u64,
+    // Requests in flight at once
+    pub concurrency: usize,
+    // Retries for errors other than "too many results", e.g. timeouts or rate limits
+    pub max_retries: u32,
+    pub retry_delay: Duration,
+}
+
+impl Default for LogFetcherConfig {
+    fn default() -> Self {
+        Self {
+            initial_chunk: 2_000,
+            max_chunk: 50_000,
+            concurrency: 4,
+            max_retries: 3,
+            retry_delay: Duration::from_millis(500),
+        }
+    }
+}
+
+// The address and topic part of an eth_getLogs filter; the fetcher supplies the blocks.
+// Topics are in the shape events::topics_for returns.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogQuery {
+    pub addresses: Vec<Address>,
+    pub topics: Vec<Option<Vec<H256>>>,
+}
+
+impl LogQuery {
+    pub fn new(addresses: Vec<Address>) -> Self {
+        Self { addresses, topics: Vec::new() }
+    }
+
+    pub fn with_topics(mut self, topics: Vec<Option<Vec<H256>>>) -> Self {
+        self.topics = topics;
+        self
+    }
+
+    pub fn filter_builder(&self) -> FilterBuilder {
+        let mut topics = self.topics.iter().cloned();
+        let mut builder = FilterBuilder::default().topics(
+            topics.next().flatten(),
+            topics.next().flatten(),
+            topics.next().flatten(),
+            topics.next().flatten(),
+        );
+        if !self.addresses.is_empty() {
+            builder = builder.address(self.addresses.clone());
+        }
+        builder
+    }
+}
+
+// Progress of a backfill: every block below next_block has been handed to the caller
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogCheckpoint {
+    pub query: LogQuery,
+    pub from_block: u64,
+    pub to_block: u64,
+    pub next_block: u64,
+}
+
+impl LogCheckpoint {
+    pub fn load(path: impl AsRef<Path>) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        match std::fs::read_to_string(path) {
+            Ok(json) => Ok(Some(serde_json::from_str(&json)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+        keystore_dir::write_atomic(path.as_ref(), &serde_json::to_vec_pretty(self)?)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.next_block > self.to_block
+    }
+}
+
+enum RangeError {
+    // The node wants a smaller range
+    TooLarge,
+    Failed(web3::Error),
+}
+
+#[derive(Debug, Clone)]
+pub struct LogFetcher<T: Transport = Http> {
+    web3: Arc<Web3<T>>,
+    config: LogFetcherConfig,
+    checkpoint_path: Option<PathBuf>,
+}
+
+impl<T: Transport> LogFetcher<T> {
+    pub fn new(web3: Arc<Web3<T>>) -> Self {
+        Self { web3, config: LogFetcherConfig::default(), checkpoint_path: None }
+    }
+
+    pub fn with_config(mut self, config: LogFetcherConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    // Makes backfill record its progress here and pick up from it when run again
+    pub fn with_checkpoint(mut self, path: impl Into<PathBuf>) -> Self {
+        self.checkpoint_path = Some(path.into());
+        self
+    }
+
+    // All matching logs in [from_block, to_block], in chain order
+    pub async fn fetch(&self, query: &LogQuery, from_block: u64, to_block: u64) -> Result<Vec<Log>, Box<dyn std::error::Error>> {
+        let mut logs = Vec::new();
+        self.run(query, from_block, to_block, from_block, |batch, _| {
+            logs.extend_from_slice(batch);
+            Ok(())
+        }).await?;
+        Ok(logs)
+    }
+
+    // Hands logs to `on_batch` in chain order as contiguous ranges complete, without holding
+    // the whole result in memory. With a checkpoint set, an interrupted run resumes after
+    // the last batch `on_batch` accepted.
+    pub async fn backfill<F>(&self, query: &LogQuery, from_block: u64, to_block: u64, mut on_batch: F) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: FnMut(&[Log]) -> Result<(), Box<dyn std::error::Error>>,
+    {
+        let start = match self.load_checkpoint(query, from_block, to_block)? {
+            Some(checkpoint) => checkpoint.next_block,
+            None => from_block,
+        };
+
+        self.run(query, from_block, to_block, start, |batch, next_block| {
+            on_batch(batch)?;
+            if let Some(path) = &self.checkpoint_path {
+                LogCheckpoint { query: query.clone(), from_block, to_block, next_block }.save(path)?;
+            }
+            Ok(())
+        }).await
+    }
+
+    fn load_checkpoint(&self, query: &LogQuery, from_block: u64, to_block: u64) -> Result<Option<LogCheckpoint>, Box<dyn std::error::Error>> {
+        let path = match &self.checkpoint_path {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        match LogCheckpoint::load(path)? {
+            Some(checkpoint) if checkpoint.query == *query && checkpoint.from_block == from_block && checkpoint.to_block == to_block => Ok(Some(checkpoint)),
+            Some(_) => Err(format!("Checkpoint {} belongs to a different query or range; remove it to start over", path.display()).into()),
+            None => Ok(None),
+        }
+    }
+
+    // Schedules chunks from `start` in waves of `concurrency`, halving any chunk the node
+    // refuses and growing the chunk size again after clean waves. Completed chunks are
+    // delivered strictly in block order, along with the block after them.
+    async fn run<F>(&self, query: &LogQuery, from_block: u64, to_block: u64, start: u64, mut deliver: F) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: FnMut(&[Log], u64) -> Result<(), Box<dyn std::error::Error>>,
+    {
+        if from_block > to_block {
+            return Err(format!("Block range {}..={} is empty", from_block, to_block).into());
+        }
+
+        let mut chunk = self.config.initial_chunk.max(1);
+        let mut cursor = start;
+        let mut next_block = start;
+        let mut retried: VecDeque<(u64, u64)> = VecDeque::new();
+        let mut completed: BTreeMap<u64, (u64, Vec<Log>)> = BTreeMap::new();
+        let mut last_delivered: Option<(u64, U256)> = None;
+
+        while next_block <= to_block {
+            let mut wave = Vec::with_capacity(self.config.concurrency);
+            while wave.len() < self.config.concurrency.max(1) {
+                if let Some(range) = retried.pop_front() {
+                    wave.push(range);
+                } else if cursor <= to_block {
+                    let end = cursor.saturating_add(chunk - 1).min(to_block);
+                    wave.push((cursor, end));
+                    cursor = end + 1;
+                } else {
+                    break;
+                }
+            }
+
+            let results = join_all(wave.iter().map(|&(start, end)| self.fetch_range(query, start, end))).await;
+
+            let mut split = false;
+            for ((start, end), result) in wave.into_iter().zip(results) {
+                match result {
+                    Ok(logs) => {
+                        completed.insert(start, (end, logs));
+                    }
+                    Err(RangeError::TooLarge) if end > start => {
+                        let middle = start + (end - start) / 2;
+                        retried.push_back((start, middle));
+                        retried.push_back((middle + 1, end));
+                        split = true;
+                    }
+                    Err(RangeError::TooLarge) => {
+                        return Err(format!("Block {} alone has more matching logs than the node will return", start).into());
+                    }
+                    Err(RangeError::Failed(e)) => return Err(format!("eth_getLogs for blocks {}..={} failed: {}", start, end, e).into()),
+                }
+            }
+            retried.make_contiguous().sort();
+
+            chunk = if split { (chunk / 2).max(1) } else { chunk.saturating_mul(2).min(self.config.max_chunk.max(1)) };
+
+            while let Some((end, mut logs)) = completed.remove(&next_block) {
+                sort_and_dedup(&mut logs, &mut last_delivered)?;
+                deliver(&logs, end + 1)?;
+                next_block = end + 1;
+            }
+        }
+        Ok(())
+    }
+
+    async fn fetch_range(&self, query: &LogQuery, start: u64, end: u64) -> Result<Vec<Log>, RangeError> {
+        let filter = query
+            .filter_builder()
+            .from_block(BlockNumber::Number(start.into()))
+            .to_block(BlockNumber::Number(end.into()))
+            .build();
+
+        let mut attempt = 0;
+        loop {
+            match self.web3.eth().logs(filter.clone()).await {
+                Ok(logs) => return Ok(logs),
+                Err(e) if is_range_error(&e) => return Err(RangeError::TooLarge),
+                Err(_) if attempt < self.config.max_retries => {
+                    attempt += 1;
+                    tokio::time::sleep(self.config.retry_delay * attempt).await;
+                }
+                Err(e) => return Err(RangeError::Failed(e)),
+            }
+        }
+    }
+}
+
+// Nodes don't promise an order, and some return a log twice or repeat the boundary block of
+// the previous range; anything at or before the last delivered position is dropped. Logs
+// without a position (pending ones) can't be placed, so they fail the batch.
+fn sort_and_dedup(logs: &mut Vec<Log>, last_delivered: &mut Option<(u64, U256)>) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(log) = logs.iter().find(|log| log.block_number.is_none() || log.log_index.is_none()) {
+        return Err(format!("Node returned a log without a block number or log index (transaction {:?})", log.transaction_hash).into());
+    }
+    let position = |log: &Log| (log.block_number.unwrap_or_default().as_u64(), log.log_index.unwrap_or_default());
+
+    logs.sort_by_key(position);
+    logs.dedup_by_key(|log| position(log));
+    if let Some(last) = *last_delivered {
+        logs.retain(|log| position(log) > last);
+    }
+    if let Some(log) = logs.last() {
+        *last_delivered = Some(position(log));
+    }
+    Ok(())
+}
+
+fn is_range_error(error: &web3::Error) -> bool {
+    let (code, message) = match error {
+        web3::Error::Rpc(rpc) => (Some(rpc.code.code()), rpc.message.to_lowercase()),
+        web3::Error::Transport(TransportError::Code(_)) => return false,
+        web3::Error::Transport(transport) => (None, format!("{:?}", transport).to_lowercase()),
+        _ => return false,
+    };
+
+    if RANGE_ERROR_HINTS.iter().any(|hint| message.contains(hint)) {
+        return true;
+    }
+    // Infura's "query returned more than 10000 results" and its rate limits share -32005
+    code == Some(-32005) && !RATE_LIMIT_HINTS.iter().any(|hint| message.contains(hint))
+}
+
+// Caller-supplied block numbers as U256; anything past u64 is an error rather than a panic
+pub fn block_number(value: U256) -> Result<u64, Box<dyn std::error::Error>> {
+    if value > U256::from(u64::MAX) {
+        return Err(format!("Block number {} is out of range", value).into());
+    }
+    Ok(value.low_u64())
+}
@@ -4,13 +4,26 @@ use tokio::sync::Mutex;
 use web3::types::{Address, U256, H256};
 use web3::Web3;
 use web3::transports::Http;
+use web3::Transport;
+use web3::ethabi::Token;
+
+use super::abi;
+use super::fee_oracle::FeeOracle;
+use super::ens::{EnsResolver, NameOrAddress};
+use super::nonce_manager::NonceManager;
+use super::signer::Signer;
+use super::smart_contract::SmartContract;
 
 #[derive(Debug, Clone)]
-pub struct NFTMarketplace {
-    web3: Arc<Web3<Http>>,
+pub struct NFTMarketplace<T: Transport = Http> {
+    web3: Arc<Web3<T>>,
     marketplace_contract: Address,
     nft_contracts: HashMap<String, Address>,
     listings: Arc<Mutex<HashMap<U256, NFTListing>>>,
+    nonce_manager: Arc<NonceManager>,
+    fee_oracle: Arc<FeeOracle<T>>,
+    signer: Option<Arc<dyn Signer>>,
+    ens: EnsResolver<T>,
 }
 
 #[derive(Debug, Clone)]
@@ -50,8 +63,10 @@ pub struct NFTMetadata {
     pub rarity_score: f64,
 }
 
-impl NFTMarketplace {
-    pub fn new(web3: Arc<Web3<Http>>, marketplace_contract: Address) -> Self {
+impl<T: Transport> NFTMarketplace<T> {
+    // `nonce_manager` and `fee_oracle` must be the ones shared by every other sender for the
+    // same accounts, e.g. WalletManager::nonce_manager() and WalletManager::fee_oracle()
+    pub fn new(web3: Arc<Web3<T>>, marketplace_contract: Address, nonce_manager: Arc<NonceManager>, fee_oracle: Arc<FeeOracle<T>>) -> Self {
         let mut nft_contracts = HashMap::new();
         nft_contracts.insert("cryptopunks".to_string(), Address::from_low_u64_be(1));
         nft_contracts.insert("bored_ape_yacht_club".to_string(), Address::from_low_u64_be(2));
@@ -60,23 +75,37 @@ impl NFTMarketplace {
         nft_contracts.insert("doodles".to_string(), Address::from_low_u64_be(5));
 
         Self {
+            ens: EnsResolver::new(web3.clone()),
             web3,
             marketplace_contract,
             nft_contracts,
             listings: Arc::new(Mutex::new(HashMap::new())),
+            nonce_manager,
+            fee_oracle,
+            signer: None,
         }
     }
 
+    pub fn with_signer(mut self, signer: Arc<dyn Signer>) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    pub fn with_ens_registry(mut self, registry: Address) -> Self {
+        self.ens = self.ens.with_registry(registry);
+        self
+    }
+
     pub async fn create_listing(&self, nft_contract: Address, token_id: U256, price: U256, currency: Address) -> Result<H256, Box<dyn std::error::Error>> {
-        let marketplace_contract = super::smart_contract::SmartContract::new(self.marketplace_contract, vec![], self.web3.clone());
+        let marketplace_contract = self.contract(self.marketplace_contract, abi::NFT_MARKETPLACE_ABI)?;
 
         let tx_hash = marketplace_contract.send_transaction(
             "createListing",
             vec![
-                self.encode_address(nft_contract),
-                self.encode_uint256(token_id),
-                self.encode_uint256(price),
-                self.encode_address(currency),
+                Token::Address(nft_contract),
+                Token::Uint(token_id),
+                Token::Uint(price),
+                Token::Address(currency),
             ],
             U256::zero(),
         ).await?;
@@ -84,7 +113,7 @@ impl NFTMarketplace {
         let listing = NFTListing {
             token_id,
             nft_contract,
-            seller: Address::zero(), // Would be set by contract
+            seller: self.signer.as_ref().map(|signer| signer.address()).unwrap_or_default(),
             price,
             currency,
             active: true,
@@ -98,13 +127,13 @@ impl NFTMarketplace {
     }
 
     pub async fn cancel_listing(&self, nft_contract: Address, token_id: U256) -> Result<H256, Box<dyn std::error::Error>> {
-        let marketplace_contract = super::smart_contract::SmartContract::new(self.marketplace_contract, vec![], self.web3.clone());
+        let marketplace_contract = self.contract(self.marketplace_contract, abi::NFT_MARKETPLACE_ABI)?;
 
         let tx_hash = marketplace_contract.send_transaction(
             "cancelListing",
             vec![
-                self.encode_address(nft_contract),
-                self.encode_uint256(token_id),
+                Token::Address(nft_contract),
+                Token::Uint(token_id),
             ],
             U256::zero(),
         ).await?;
@@ -118,13 +147,13 @@ impl NFTMarketplace {
     }
 
     pub async fn buy_nft(&self, nft_contract: Address, token_id: U256, max_price: U256) -> Result<H256, Box<dyn std::error::Error>> {
-        let marketplace_contract = super::smart_contract::SmartContract::new(self.marketplace_contract, vec![], self.web3.clone());
+        let marketplace_contract = self.contract(self.marketplace_contract, abi::NFT_MARKETPLACE_ABI)?;
 
         let tx_hash = marketplace_contract.send_transaction(
             "buyNFT",
             vec![
-                self.encode_address(nft_contract),
-                self.encode_uint256(token_id),
+                Token::Address(nft_contract),
+                Token::Uint(token_id),
             ],
             max_price,
         ).await?;
@@ -136,16 +165,16 @@ impl NFTMarketplace {
     }
 
     pub async fn make_offer(&self, nft_contract: Address, token_id: U256, price: U256, currency: Address, expiration: U256) -> Result<H256, Box<dyn std::error::Error>> {
-        let marketplace_contract = super::smart_contract::SmartContract::new(self.marketplace_contract, vec![], self.web3.clone());
+        let marketplace_contract = self.contract(self.marketplace_contract, abi::NFT_MARKETPLACE_ABI)?;
 
         let tx_hash = marketplace_contract.send_transaction(
             "makeOffer",
             vec![
-                self.encode_address(nft_contract),
-                self.encode_uint256(token_id),
-                self.encode_uint256(price),
-                self.encode_address(currency),
-                self.encode_uint256(expiration),
+                Token::Address(nft_contract),
+                Token::Uint(token_id),
+                Token::Uint(price),
+                Token::Address(currency),
+                Token::Uint(expiration),
             ],
             U256::zero(),
         ).await?;
@@ -154,14 +183,14 @@ impl NFTMarketplace {
     }
 
     pub async fn accept_offer(&self, nft_contract: Address, token_id: U256, offer_maker: Address) -> Result<H256, Box<dyn std::error::Error>> {
-        let marketplace_contract = super::smart_contract::SmartContract::new(self.marketplace_contract, vec![], self.web3.clone());
+        let marketplace_contract = self.contract(self.marketplace_contract, abi::NFT_MARKETPLACE_ABI)?;
 
         let tx_hash = marketplace_contract.send_transaction(
             "acceptOffer",
             vec![
-                self.encode_address(nft_contract),
-                self.encode_uint256(token_id),
-                self.encode_address(offer_maker),
+                Token::Address(nft_contract),
+                Token::Uint(token_id),
+                Token::Address(offer_maker),
             ],
             U256::zero(),
         ).await?;
@@ -173,43 +202,44 @@ impl NFTMarketplace {
     }
 
     pub async fn get_listing(&self, nft_contract: Address, token_id: U256) -> Result<Option<NFTListing>, Box<dyn std::error::Error>> {
-        let marketplace_contract = super::smart_contract::SmartContract::new(self.marketplace_contract, vec![], self.web3.clone());
+        let marketplace_contract = self.contract(self.marketplace_contract, abi::NFT_MARKETPLACE_ABI)?;
 
         let result = marketplace_contract.call(
             "getListing",
             vec![
-                self.encode_address(nft_contract),
-                self.encode_uint256(token_id),
+                Token::Address(nft_contract),
+                Token::Uint(token_id),
             ],
         ).await?;
 
-        if result.is_empty() {
+        let seller = abi::address_at(&result, 0)?;
+        if seller == Address::zero() {
             return Ok(None);
         }
 
         let listing = NFTListing {
             token_id,
             nft_contract,
-            seller: self.decode_address(&result[12..32]),
-            price: self.decode_uint256(&result[32..64]),
-            currency: self.decode_address(&result[76..96]),
-            active: result[96] != 0,
-            created_at: self.decode_uint256(&result[97..129]),
+            seller,
+            price: abi::uint_at(&result, 1)?,
+            currency: abi::address_at(&result, 2)?,
+            active: abi::bool_at(&result, 3)?,
+            created_at: abi::uint_at(&result, 4)?,
         };
 
         Ok(Some(listing))
     }
 
     pub async fn get_all_listings(&self) -> Result<Vec<NFTListing>, Box<dyn std::error::Error>> {
-        let marketplace_contract = super::smart_contract::SmartContract::new(self.marketplace_contract, vec![], self.web3.clone());
+        let marketplace_contract = self.contract(self.marketplace_contract, abi::NFT_MARKETPLACE_ABI)?;
 
-        let total_listings: U256 = self.decode_uint256(&marketplace_contract.call("getTotalListings", vec![]).await?);
+        let total_listings: U256 = abi::uint_at(&marketplace_contract.call("getTotalListings", vec![]).await?, 0)?;
 
         let mut listings = Vec::new();
         for i in 0..total_listings.as_u64() {
-            let listing_data = marketplace_contract.call("getListingByIndex", vec![self.encode_uint256(U256::from(i))]).await?;
-            let token_id = self.decode_uint256(&listing_data[0..32]);
-            let nft_contract = self.decode_address(&listing_data[32..52]);
+            let listing_data = marketplace_contract.call("getListingByIndex", vec![Token::Uint(U256::from(i))]).await?;
+            let token_id = abi::uint_at(&listing_data, 0)?;
+            let nft_contract = abi::address_at(&listing_data, 1)?;
 
             if let Some(listing) = self.get_listing(nft_contract, token_id).await? {
                 listings.push(listing);
@@ -220,9 +250,9 @@ impl NFTMarketplace {
     }
 
     pub async fn get_nft_metadata(&self, nft_contract: Address, token_id: U256) -> Result<NFTMetadata, Box<dyn std::error::Error>> {
-        let nft_contract_instance = super::smart_contract::SmartContract::new(nft_contract, vec![], self.web3.clone());
+        let nft_contract_instance = self.contract(nft_contract, abi::ERC721_ABI)?;
 
-        let token_uri: String = String::from_utf8(nft_contract_instance.call("tokenURI", vec![self.encode_uint256(token_id)]).await?)?;
+        let token_uri: String = abi::string_at(&nft_contract_instance.call("tokenURI", vec![Token::Uint(token_id)]).await?, 0)?;
 
         // In a real implementation, you'd fetch the metadata from IPFS or HTTP URL
         // For this example, we'll create mock metadata
@@ -249,11 +279,11 @@ impl NFTMarketplace {
     }
 
     pub async fn get_collection_info(&self, collection_address: Address) -> Result<NFTCollection, Box<dyn std::error::Error>> {
-        let nft_contract = super::smart_contract::SmartContract::new(collection_address, vec![], self.web3.clone());
+        let nft_contract = self.contract(collection_address, abi::ERC721_ABI)?;
 
-        let name: String = String::from_utf8(nft_contract.call("name", vec![]).await?)?;
-        let symbol: String = String::from_utf8(nft_contract.call("symbol", vec![]).await?)?;
-        let total_supply: U256 = self.decode_uint256(&nft_contract.call("totalSupply", vec![]).await?);
+        let name: String = abi::string_at(&nft_contract.call("name", vec![]).await?, 0)?;
+        let symbol: String = abi::string_at(&nft_contract.call("symbol", vec![]).await?, 0)?;
+        let total_supply: U256 = abi::uint_at(&nft_contract.call("totalSupply", vec![]).await?, 0)?;
 
         // Mock floor price and volume - in reality, you'd calculate these from marketplace data
         let floor_price = U256::from(1000000000000000000); // 1 ETH
@@ -287,15 +317,21 @@ impl NFTMarketplace {
         Ok(prices[0])
     }
 
+    // (new owner, block number) for every Transfer of the token, oldest first; the first
+    // entry is the mint
     pub async fn get_nft_ownership_history(&self, nft_contract: Address, token_id: U256) -> Result<Vec<(Address, U256)>, Box<dyn std::error::Error>> {
-        // This would typically query transfer events from the NFT contract
-        // For this example, we'll return mock data
-        let history = vec![
-            (Address::from_low_u64_be(1), U256::from(1000000)),
-            (Address::from_low_u64_be(2), U256::from(2000000)),
-            (Address::from_low_u64_be(3), U256::from(3000000)),
-        ];
+        let contract = SmartContract::read_only(nft_contract, abi::ERC721_ABI.as_bytes().to_vec(), self.web3.clone())?;
+        let latest_block = U256::from(self.web3.eth().block_number().await?.as_u64());
 
+        let transfers = contract
+            .get_events_where("Transfer", &[None, None, Some(Token::Uint(token_id))], U256::zero(), latest_block)
+            .await?;
+
+        let history = transfers
+            .iter()
+            .filter(|transfer| !transfer.removed)
+            .filter_map(|transfer| Some((transfer.address_param("to")?, U256::from(transfer.block_number?))))
+            .collect();
         Ok(history)
     }
 
@@ -327,11 +363,11 @@ impl NFTMarketplace {
     }
 
     pub async fn get_marketplace_stats(&self) -> Result<HashMap<String, U256>, Box<dyn std::error::Error>> {
-        let marketplace_contract = super::smart_contract::SmartContract::new(self.marketplace_contract, vec![], self.web3.clone());
+        let marketplace_contract = self.contract(self.marketplace_contract, abi::NFT_MARKETPLACE_ABI)?;
 
-        let total_volume: U256 = self.decode_uint256(&marketplace_contract.call("getTotalVolume", vec![]).await?);
-        let total_listings: U256 = self.decode_uint256(&marketplace_contract.call("getTotalListings", vec![]).await?);
-        let total_sales: U256 = self.decode_uint256(&marketplace_contract.call("getTotalSales", vec![]).await?);
+        let total_volume: U256 = abi::uint_at(&marketplace_contract.call("getTotalVolume", vec![]).await?, 0)?;
+        let total_listings: U256 = abi::uint_at(&marketplace_contract.call("getTotalListings", vec![]).await?, 0)?;
+        let total_sales: U256 = abi::uint_at(&marketplace_contract.call("getTotalSales", vec![]).await?, 0)?;
 
         let mut stats = HashMap::new();
         stats.insert("total_volume".to_string(), total_volume);
@@ -360,15 +396,16 @@ impl NFTMarketplace {
         Ok(offers)
     }
 
-    pub async fn transfer_nft(&self, nft_contract: Address, from: Address, to: Address, token_id: U256) -> Result<H256, Box<dyn std::error::Error>> {
-        let nft_contract_instance = super::smart_contract::SmartContract::new(nft_contract, vec![], self.web3.clone());
+    pub async fn transfer_nft(&self, nft_contract: Address, from: Address, to: NameOrAddress, token_id: U256) -> Result<H256, Box<dyn std::error::Error>> {
+        let to = self.ens.resolve(&to).await?;
+        let nft_contract_instance = self.contract(nft_contract, abi::ERC721_ABI)?;
 
         let tx_hash = nft_contract_instance.send_transaction(
             "transferFrom",
             vec![
-                self.encode_address(from),
-                self.encode_address(to),
-                self.encode_uint256(token_id),
+                Token::Address(from),
+                Token::Address(to),
+                Token::Uint(token_id),
             ],
             U256::zero(),
         ).await?;
@@ -377,13 +414,13 @@ impl NFTMarketplace {
     }
 
     pub async fn approve_nft(&self, nft_contract: Address, approved: Address, token_id: U256) -> Result<H256, Box<dyn std::error::Error>> {
-        let nft_contract_instance = super::smart_contract::SmartContract::new(nft_contract, vec![], self.web3.clone());
+        let nft_contract_instance = self.contract(nft_contract, abi::ERC721_ABI)?;
 
         let tx_hash = nft_contract_instance.send_transaction(
             "approve",
             vec![
-                self.encode_address(approved),
-                self.encode_uint256(token_id),
+                Token::Address(approved),
+                Token::Uint(token_id),
             ],
             U256::zero(),
         ).await?;
@@ -392,13 +429,13 @@ impl NFTMarketplace {
     }
 
     pub async fn set_approval_for_all(&self, nft_contract: Address, operator: Address, approved: bool) -> Result<H256, Box<dyn std::error::Error>> {
-        let nft_contract_instance = super::smart_contract::SmartContract::new(nft_contract, vec![], self.web3.clone());
+        let nft_contract_instance = self.contract(nft_contract, abi::ERC721_ABI)?;
 
         let tx_hash = nft_contract_instance.send_transaction(
             "setApprovalForAll",
             vec![
-                self.encode_address(operator),
-                self.encode_bool(approved),
+                Token::Address(operator),
+                Token::Bool(approved),
             ],
             U256::zero(),
         ).await?;
@@ -407,27 +444,27 @@ impl NFTMarketplace {
     }
 
     pub async fn get_nft_balance(&self, nft_contract: Address, owner: Address) -> Result<U256, Box<dyn std::error::Error>> {
-        let nft_contract_instance = super::smart_contract::SmartContract::new(nft_contract, vec![], self.web3.clone());
+        let nft_contract_instance = self.contract(nft_contract, abi::ERC721_ABI)?;
 
-        let balance: U256 = self.decode_uint256(&nft_contract_instance.call("balanceOf", vec![self.encode_address(owner)]).await?);
+        let balance: U256 = abi::uint_at(&nft_contract_instance.call("balanceOf", vec![Token::Address(owner)]).await?, 0)?;
         Ok(balance)
     }
 
     pub async fn get_nft_owner(&self, nft_contract: Address, token_id: U256) -> Result<Address, Box<dyn std::error::Error>> {
-        let nft_contract_instance = super::smart_contract::SmartContract::new(nft_contract, vec![], self.web3.clone());
+        let nft_contract_instance = self.contract(nft_contract, abi::ERC721_ABI)?;
 
-        let owner: Address = self.decode_address(&nft_contract_instance.call("ownerOf", vec![self.encode_uint256(token_id)]).await?);
+        let owner: Address = abi::address_at(&nft_contract_instance.call("ownerOf", vec![Token::Uint(token_id)]).await?, 0)?;
         Ok(owner)
     }
 
     pub async fn mint_nft(&self, nft_contract: Address, to: Address, token_uri: String) -> Result<H256, Box<dyn std::error::Error>> {
-        let nft_contract_instance = super::smart_contract::SmartContract::new(nft_contract, vec![], self.web3.clone());
+        let nft_contract_instance = self.contract(nft_contract, abi::ERC721_ABI)?;
 
         let tx_hash = nft_contract_instance.send_transaction(
             "mint",
             vec![
-                self.encode_address(to),
-                self.encode_string(token_uri),
+                Token::Address(to),
+                Token::String(token_uri),
             ],
             U256::zero(),
         ).await?;
@@ -436,11 +473,11 @@ impl NFTMarketplace {
     }
 
     pub async fn burn_nft(&self, nft_contract: Address, token_id: U256) -> Result<H256, Box<dyn std::error::Error>> {
-        let nft_contract_instance = super::smart_contract::SmartContract::new(nft_contract, vec![], self.web3.clone());
+        let nft_contract_instance = self.contract(nft_contract, abi::ERC721_ABI)?;
 
         let tx_hash = nft_contract_instance.send_transaction(
             "burn",
-            vec![self.encode_uint256(token_id)],
+            vec![Token::Uint(token_id)],
             U256::zero(),
         ).await?;
 
@@ -448,15 +485,15 @@ impl NFTMarketplace {
     }
 
     pub async fn create_auction(&self, nft_contract: Address, token_id: U256, starting_price: U256, duration: U256) -> Result<H256, Box<dyn std::error::Error>> {
-        let marketplace_contract = super::smart_contract::SmartContract::new(self.marketplace_contract, vec![], self.web3.clone());
+        let marketplace_contract = self.contract(self.marketplace_contract, abi::NFT_MARKETPLACE_ABI)?;
 
         let tx_hash = marketplace_contract.send_transaction(
             "createAuction",
             vec![
-                self.encode_address(nft_contract),
-                self.encode_uint256(token_id),
-                self.encode_uint256(starting_price),
-                self.encode_uint256(duration),
+                Token::Address(nft_contract),
+                Token::Uint(token_id),
+                Token::Uint(starting_price),
+                Token::Uint(duration),
             ],
             U256::zero(),
         ).await?;
@@ -465,13 +502,13 @@ impl NFTMarketplace {
     }
 
     pub async fn bid_on_auction(&self, nft_contract: Address, token_id: U256, bid_amount: U256) -> Result<H256, Box<dyn std::error::Error>> {
-        let marketplace_contract = super::smart_contract::SmartContract::new(self.marketplace_contract, vec![], self.web3.clone());
+        let marketplace_contract = self.contract(self.marketplace_contract, abi::NFT_MARKETPLACE_ABI)?;
 
         let tx_hash = marketplace_contract.send_transaction(
             "bid",
             vec![
-                self.encode_address(nft_contract),
-                self.encode_uint256(token_id),
+                Token::Address(nft_contract),
+                Token::Uint(token_id),
             ],
             bid_amount,
         ).await?;
@@ -480,13 +517,13 @@ impl NFTMarketplace {
     }
 
     pub async fn end_auction(&self, nft_contract: Address, token_id: U256) -> Result<H256, Box<dyn std::error::Error>> {
-        let marketplace_contract = super::smart_contract::SmartContract::new(self.marketplace_contract, vec![], self.web3.clone());
+        let marketplace_contract = self.contract(self.marketplace_contract, abi::NFT_MARKETPLACE_ABI)?;
 
         let tx_hash = marketplace_contract.send_transaction(
             "endAuction",
             vec![
-                self.encode_address(nft_contract),
-                self.encode_uint256(token_id),
+                Token::Address(nft_contract),
+                Token::Uint(token_id),
             ],
             U256::zero(),
         ).await?;
@@ -495,55 +532,31 @@ impl NFTMarketplace {
     }
 
     pub async fn get_auction_info(&self, nft_contract: Address, token_id: U256) -> Result<HashMap<String, U256>, Box<dyn std::error::Error>> {
-        let marketplace_contract = super::smart_contract::SmartContract::new(self.marketplace_contract, vec![], self.web3.clone());
+        let marketplace_contract = self.contract(self.marketplace_contract, abi::NFT_MARKETPLACE_ABI)?;
 
         let result = marketplace_contract.call(
             "getAuction",
             vec![
-                self.encode_address(nft_contract),
-                self.encode_uint256(token_id),
+                Token::Address(nft_contract),
+                Token::Uint(token_id),
             ],
         ).await?;
 
+        let highest_bidder = abi::address_at(&result, 1)?;
+
         let mut auction_info = HashMap::new();
-        auction_info.insert("highest_bid".to_string(), self.decode_uint256(&result[0..32]));
-        auction_info.insert("highest_bidder".to_string(), self.decode_uint256(&result[32..64])); // Address encoded as uint
-        auction_info.insert("end_time".to_string(), self.decode_uint256(&result[64..96]));
+        auction_info.insert("highest_bid".to_string(), abi::uint_at(&result, 0)?);
+        auction_info.insert("highest_bidder".to_string(), U256::from_big_endian(highest_bidder.as_bytes())); // Address encoded as uint
+        auction_info.insert("end_time".to_string(), abi::uint_at(&result, 2)?);
 
         Ok(auction_info)
     }
 
-    // Helper functions for encoding/decoding
-    fn encode_address(&self, address: Address) -> web3::types::Bytes {
-        let mut bytes = vec![0u8; 32];
-        bytes[12..32].copy_from_slice(address.as_bytes());
-        bytes.into()
-    }
-
-    fn encode_uint256(&self, value: U256) -> web3::types::Bytes {
-        let mut bytes = vec![0u8; 32];
-        value.to_big_endian(&mut bytes);
-        bytes.into()
-    }
-
-    fn encode_bool(&self, value: bool) -> web3::types::Bytes {
-        let byte = if value { 1u8 } else { 0u8 };
-        vec![byte].into()
-    }
-
-    fn encode_string(&self, value: String) -> web3::types::Bytes {
-        let mut bytes = vec![0u8; 32];
-        let string_bytes = value.as_bytes();
-        let len = std::cmp::min(string_bytes.len(), 32);
-        bytes[32-len..32].copy_from_slice(&string_bytes[string_bytes.len()-len..]);
-        bytes.into()
-    }
-
-    fn decode_address(&self, data: &[u8]) -> Address {
-        Address::from_slice(&data[12..32])
-    }
-
-    fn decode_uint256(&self, data: &[u8]) -> U256 {
-        U256::from_big_endian(data)
+    fn contract(&self, address: Address, contract_abi: &str) -> Result<SmartContract<T>, Box<dyn std::error::Error>> {
+        let contract = SmartContract::new(address, contract_abi.as_bytes().to_vec(), self.web3.clone(), self.nonce_manager.clone(), self.fee_oracle.clone())?;
+        Ok(match &self.signer {
+            Some(signer) => contract.with_signer(signer.clone()),
+            None => contract,
+        })
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,173 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use web3::types::{Address, BlockNumber, H256, U256};
+use web3::{Transport, Web3};
+
+// How many times a send is retried after the node rejects its nonce
+const MAX_NONCE_RETRIES: usize = 3;
+
+#[derive(Debug, Default)]
+struct AccountNonces {
+    // Next nonce that has never been handed out
+    next: U256,
+    // Handed out but never broadcast, or dropped from the mempool; reused lowest first
+    released: BTreeSet<U256>,
+    // Broadcast and not yet mined
+    in_flight: BTreeMap<U256, H256>,
+}
+
+// Hands out nonces per (chain, address) without a round trip to the node, so concurrent
+// tasks signing for the same account never pick the same nonce. One instance should be
+// shared by every component that sends from a given account.
+#[derive(Debug, Clone, Default)]
+pub struct NonceManager {
+    accounts: Arc<Mutex<HashMap<(u64, Address), AccountNonces>>>,
+}
+
+impl NonceManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn reserve<T: Transport>(&self, web3: &Web3<T>, chain_id: u64, address: Address) -> Result<U256, web3::Error> {
+        let mut accounts = self.accounts.lock().await;
+
+        if !accounts.contains_key(&(chain_id, address)) {
+            let pending = web3.eth().transaction_count(address, Some(BlockNumber::Pending)).await?;
+            accounts.insert((chain_id, address), AccountNonces { next: pending, ..Default::default() });
+        }
+
+        let account = accounts.get_mut(&(chain_id, address)).unwrap();
+        if let Some(nonce) = account.released.iter().next().cloned() {
+            account.released.remove(&nonce);
+            return Ok(nonce);
+        }
+
+        let nonce = account.next;
+        account.next += U256::one();
+        Ok(nonce)
+    }
+
+    pub async fn mark_sent(&self, chain_id: u64, address: Address, nonce: U256, tx_hash: H256) {
+        let mut accounts = self.accounts.lock().await;
+        if let Some(account) = accounts.get_mut(&(chain_id, address)) {
+            account.in_flight.insert(nonce, tx_hash);
+        }
+    }
+
+    // The transaction using this nonce was never broadcast
+    pub async fn release(&self, chain_id: u64, address: Address, nonce: U256) {
+        let mut accounts = self.accounts.lock().await;
+        if let Some(account) = accounts.get_mut(&(chain_id, address)) {
+            account.in_flight.remove(&nonce);
+            if nonce < account.next {
+                account.released.insert(nonce);
+            }
+        }
+    }
+
+    pub async fn mark_mined(&self, chain_id: u64, address: Address, nonce: U256) {
+        let mut accounts = self.accounts.lock().await;
+        if let Some(account) = accounts.get_mut(&(chain_id, address)) {
+            account.in_flight.remove(&nonce);
+            account.released.remove(&nonce);
+        }
+    }
+
+    pub async fn in_flight(&self, chain_id: u64, address: Address) -> Vec<(U256, H256)> {
+        let accounts = self.accounts.lock().await;
+        accounts.get(&(chain_id, address))
+            .map(|account| account.in_flight.iter().map(|(nonce, hash)| (*nonce, *hash)).collect())
+            .unwrap_or_default()
+    }
+
+    // Re-reads the account from the node. Mined nonces are forgotten, in-flight
+    // transactions the node no longer knows about are treated as dropped and their
+    // nonces become gaps to fill, and the local counter never falls behind the node.
+    pub async fn resync<T: Transport>(&self, web3: &Web3<T>, chain_id: u64, address: Address) -> Result<U256, web3::Error> {
+        let mined = web3.eth().transaction_count(address, Some(BlockNumber::Latest)).await?;
+        let pending = web3.eth().transaction_count(address, Some(BlockNumber::Pending)).await?;
+
+        let in_flight = self.in_flight(chain_id, address).await;
+        let mut dropped = Vec::new();
+        for (nonce, tx_hash) in in_flight {
+            if nonce >= mined && web3.eth().transaction(tx_hash.into()).await?.is_none() {
+                dropped.push(nonce);
+            }
+        }
+
+        let mut accounts = self.accounts.lock().await;
+        let account = accounts.entry((chain_id, address)).or_default();
+
+        account.in_flight = account.in_flight.split_off(&mined);
+        account.released = account.released.split_off(&mined);
+        for nonce in dropped {
+            account.in_flight.remove(&nonce);
+            account.released.insert(nonce);
+        }
+
+        if account.next < pending {
+            account.next = pending;
+        }
+
+        Ok(account.next)
+    }
+
+    // Reserves a nonce, runs `send` with it and records the result. A stale nonce triggers
+    // a resync and another attempt. "Already known" means the node has this exact
+    // transaction, so the nonce stays consumed and the error is returned rather than
+    // risking a duplicate. Any other failure releases the nonce so the next transaction
+    // fills the gap.
+    pub async fn send_with_nonce<T, F, Fut>(&self, web3: &Web3<T>, chain_id: u64, address: Address, send: F) -> Result<(U256, H256), web3::Error>
+    where
+        T: Transport,
+        F: Fn(U256) -> Fut,
+        Fut: Future<Output = Result<H256, web3::Error>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let nonce = self.reserve(web3, chain_id, address).await?;
+
+            match send(nonce).await {
+                Ok(tx_hash) => {
+                    self.mark_sent(chain_id, address, nonce, tx_hash).await;
+                    return Ok((nonce, tx_hash));
+                }
+                Err(error) if is_already_known(&error) => {
+                    self.resync(web3, chain_id, address).await?;
+                    return Err(error);
+                }
+                Err(error) if is_nonce_error(&error) && attempt < MAX_NONCE_RETRIES => {
+                    attempt += 1;
+                    self.release(chain_id, address, nonce).await;
+                    self.resync(web3, chain_id, address).await?;
+                }
+                Err(error) => {
+                    self.release(chain_id, address, nonce).await;
+                    return Err(error);
+                }
+            }
+        }
+    }
+}
+
+pub fn is_nonce_error(error: &web3::Error) -> bool {
+    let message = error_message(error);
+    message.contains("nonce too low")
+        || message.contains("nonce too high")
+        || message.contains("invalid nonce")
+}
+
+pub fn is_already_known(error: &web3::Error) -> bool {
+    let message = error_message(error);
+    message.contains("already known") || message.contains("known transaction")
+}
+
+fn error_message(error: &web3::Error) -> String {
+    match error {
+        web3::Error::Rpc(rpc_error) => rpc_error.message.to_lowercase(),
+        other => other.to_string().to_lowercase(),
+    }
+}
@@ -0,0 +1,356 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use futures::future::{join_all, BoxFuture, FutureExt};
+use jsonrpc_core as rpc;
+use serde::{Deserialize, Serialize};
+use web3::transports::Http;
+use web3::{helpers, RequestId, Transport};
+
+// Methods that can safely be repeated against another endpoint after a failure
+const IDEMPOTENT_METHODS: &[&str] = &[
+    "eth_blockNumber",
+    "eth_call",
+    "eth_chainId",
+    "eth_createAccessList",
+    "eth_estimateGas",
+    "eth_feeHistory",
+    "eth_gasPrice",
+    "eth_getBalance",
+    "eth_getBlockByHash",
+    "eth_getBlockByNumber",
+    "eth_getBlockTransactionCountByNumber",
+    "eth_getCode",
+    "eth_getLogs",
+    "eth_getStorageAt",
+    "eth_getTransactionByHash",
+    "eth_getTransactionCount",
+    "eth_getTransactionReceipt",
+    "eth_maxPriorityFeePerGas",
+    "eth_syncing",
+    "net_peerCount",
+    "net_version",
+    "web3_clientVersion",
+];
+
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    pub max_attempts: usize,
+    pub failure_threshold: u32,
+    pub cooldown: Duration,
+    pub max_block_lag: u64,
+    pub lag_penalty_ms: f64,
+    pub ewma_alpha: f64,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            failure_threshold: 3,
+            cooldown: Duration::from_secs(30),
+            max_block_lag: 5,
+            lag_penalty_ms: 100.0,
+            ewma_alpha: 0.2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointHealth {
+    pub url: String,
+    pub latency_ms: Option<f64>,
+    pub error_rate: f64,
+    pub block_height: Option<u64>,
+    pub block_lag: u64,
+    pub requests: u64,
+    pub failures: u64,
+    pub healthy: bool,
+    pub score: f64,
+}
+
+#[derive(Debug, Default)]
+struct EndpointStats {
+    latency_ms: Option<f64>,
+    error_rate: f64,
+    requests: u64,
+    failures: u64,
+    consecutive_failures: u32,
+    last_failure: Option<Instant>,
+    block_height: Option<u64>,
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    url: String,
+    transport: Http,
+    stats: Mutex<EndpointStats>,
+}
+
+#[derive(Debug)]
+struct PoolInner {
+    endpoints: Vec<Endpoint>,
+    config: PoolConfig,
+    next_id: AtomicUsize,
+}
+
+// A web3 transport over several HTTP endpoints. Requests go to the endpoint with the
+// best health score; idempotent reads are retried on the next best endpoint when the
+// transport fails. JSON-RPC errors returned by a node are passed through untouched.
+#[derive(Debug, Clone)]
+pub struct ProviderPool {
+    inner: Arc<PoolInner>,
+}
+
+impl ProviderPool {
+    pub fn new(rpc_urls: &[&str], config: PoolConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        if rpc_urls.is_empty() {
+            return Err("Provider pool needs at least one RPC URL".into());
+        }
+
+        let endpoints = rpc_urls.iter()
+            .map(|url| Ok(Endpoint {
+                url: url.to_string(),
+                transport: Http::new(url)?,
+                stats: Mutex::new(EndpointStats::default()),
+            }))
+            .collect::<Result<Vec<_>, web3::Error>>()?;
+
+        Ok(Self {
+            inner: Arc::new(PoolInner {
+                endpoints,
+                config,
+                next_id: AtomicUsize::new(1),
+            }),
+        })
+    }
+
+    pub fn endpoint_count(&self) -> usize {
+        self.inner.endpoints.len()
+    }
+
+    pub fn health(&self) -> Vec<EndpointHealth> {
+        let best_height = self.best_block_height();
+        self.inner.endpoints.iter()
+            .map(|endpoint| self.endpoint_health(endpoint, best_height))
+            .collect()
+    }
+
+    // Polls eth_blockNumber on every endpoint to update latency and block lag
+    pub async fn refresh_health(&self) -> Vec<EndpointHealth> {
+        let probes = self.inner.endpoints.iter().map(|endpoint| async move {
+            let started = Instant::now();
+            let result = endpoint.transport.execute("eth_blockNumber", vec![]).await;
+            match result.ok().and_then(|value| parse_quantity(&value)) {
+                Some(height) => {
+                    self.record_success(endpoint, started.elapsed());
+                    endpoint.stats.lock().unwrap().block_height = Some(height);
+                }
+                None => self.record_failure(endpoint),
+            }
+        });
+        join_all(probes).await;
+
+        self.health()
+    }
+
+    pub fn spawn_health_monitor(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let pool = self.clone();
+        tokio::spawn(async move {
+            loop {
+                pool.refresh_health().await;
+                tokio::time::sleep(interval).await;
+            }
+        })
+    }
+
+    // Lowest head among healthy endpoints, so a quorum read can pin a block every
+    // participant has already seen
+    pub fn common_block_height(&self) -> Option<u64> {
+        self.health().iter()
+            .filter(|health| health.healthy)
+            .filter_map(|health| health.block_height)
+            .min()
+    }
+
+    // Sends the request to every healthy endpoint and returns the result that at least
+    // `required` of them agree on
+    pub async fn quorum_request(&self, method: &str, params: Vec<rpc::Value>, required: usize) -> web3::error::Result<rpc::Value> {
+        let health = self.health();
+        let candidates: Vec<&Endpoint> = self.inner.endpoints.iter()
+            .zip(&health)
+            .filter(|(_, health)| health.healthy)
+            .map(|(endpoint, _)| endpoint)
+            .collect();
+
+        if candidates.len() < required {
+            return Err(web3::Error::InvalidResponse(format!(
+                "Quorum of {} requested but only {} healthy endpoints are available",
+                required,
+                candidates.len(),
+            )));
+        }
+
+        let requests = candidates.iter().map(|endpoint| {
+            let params = params.clone();
+            async move {
+                let started = Instant::now();
+                let result = endpoint.transport.execute(method, params).await;
+                match &result {
+                    Err(web3::Error::Rpc(_)) | Ok(_) => self.record_success(endpoint, started.elapsed()),
+                    Err(_) => self.record_failure(endpoint),
+                }
+                result
+            }
+        });
+        let results = join_all(requests).await;
+
+        let mut votes: Vec<(rpc::Value, usize)> = Vec::new();
+        for value in results.into_iter().flatten() {
+            match votes.iter_mut().find(|(candidate, _)| *candidate == value) {
+                Some((_, count)) => *count += 1,
+                None => votes.push((value, 1)),
+            }
+        }
+
+        let best = votes.into_iter().max_by_key(|(_, count)| *count);
+        match best {
+            Some((value, count)) if count >= required => Ok(value),
+            Some((_, count)) => Err(web3::Error::InvalidResponse(format!(
+                "Quorum not reached for {}: {} of {} endpoints agreed, {} required",
+                method,
+                count,
+                candidates.len(),
+                required,
+            ))),
+            None => Err(web3::Error::InvalidResponse(format!("No endpoint answered {}", method))),
+        }
+    }
+
+    async fn dispatch(&self, id: RequestId, request: rpc::Call) -> web3::error::Result<rpc::Value> {
+        let attempts = if is_idempotent(&request) {
+            self.inner.config.max_attempts.max(1)
+        } else {
+            1
+        };
+
+        let mut last_error = web3::Error::Unreachable;
+        for index in self.ranked_endpoints().into_iter().take(attempts) {
+            let endpoint = &self.inner.endpoints[index];
+            let started = Instant::now();
+
+            match endpoint.transport.send(id, request.clone()).await {
+                Ok(value) => {
+                    self.record_success(endpoint, started.elapsed());
+                    return Ok(value);
+                }
+                // The node answered, so the endpoint itself is fine
+                Err(web3::Error::Rpc(error)) => {
+                    self.record_success(endpoint, started.elapsed());
+                    return Err(web3::Error::Rpc(error));
+                }
+                Err(error) => {
+                    self.record_failure(endpoint);
+                    last_error = error;
+                }
+            }
+        }
+
+        Err(last_error)
+    }
+
+    fn ranked_endpoints(&self) -> Vec<usize> {
+        let mut ranked: Vec<(usize, EndpointHealth)> = self.health().into_iter().enumerate().collect();
+        ranked.sort_by(|(_, a), (_, b)| {
+            b.healthy.cmp(&a.healthy)
+                .then(a.score.partial_cmp(&b.score).unwrap_or(std::cmp::Ordering::Equal))
+        });
+        ranked.into_iter().map(|(index, _)| index).collect()
+    }
+
+    fn best_block_height(&self) -> Option<u64> {
+        self.inner.endpoints.iter()
+            .filter_map(|endpoint| endpoint.stats.lock().unwrap().block_height)
+            .max()
+    }
+
+    fn endpoint_health(&self, endpoint: &Endpoint, best_height: Option<u64>) -> EndpointHealth {
+        let config = &self.inner.config;
+        let stats = endpoint.stats.lock().unwrap();
+
+        let block_lag = match (best_height, stats.block_height) {
+            (Some(best), Some(height)) => best.saturating_sub(height),
+            _ => 0,
+        };
+        let cooling_down = stats.consecutive_failures >= config.failure_threshold
+            && stats.last_failure.map(|at| at.elapsed() < config.cooldown).unwrap_or(false);
+        let healthy = !cooling_down && block_lag <= config.max_block_lag;
+
+        // Unmeasured endpoints get a neutral latency so they are tried early on
+        let latency = stats.latency_ms.unwrap_or(250.0);
+        let score = latency * (1.0 + 4.0 * stats.error_rate) + block_lag as f64 * config.lag_penalty_ms;
+
+        EndpointHealth {
+            url: endpoint.url.clone(),
+            latency_ms: stats.latency_ms,
+            error_rate: stats.error_rate,
+            block_height: stats.block_height,
+            block_lag,
+            requests: stats.requests,
+            failures: stats.failures,
+            healthy,
+            score,
+        }
+    }
+
+    fn record_success(&self, endpoint: &Endpoint, elapsed: Duration) {
+        let alpha = self.inner.config.ewma_alpha;
+        let elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+        let mut stats = endpoint.stats.lock().unwrap();
+
+        stats.requests += 1;
+        stats.consecutive_failures = 0;
+        stats.error_rate *= 1.0 - alpha;
+        stats.latency_ms = Some(match stats.latency_ms {
+            Some(latency) => latency * (1.0 - alpha) + elapsed_ms * alpha,
+            None => elapsed_ms,
+        });
+    }
+
+    fn record_failure(&self, endpoint: &Endpoint) {
+        let alpha = self.inner.config.ewma_alpha;
+        let mut stats = endpoint.stats.lock().unwrap();
+
+        stats.requests += 1;
+        stats.failures += 1;
+        stats.consecutive_failures += 1;
+        stats.last_failure = Some(Instant::now());
+        stats.error_rate = stats.error_rate * (1.0 - alpha) + alpha;
+    }
+}
+
+impl Transport for ProviderPool {
+    type Out = BoxFuture<'static, web3::error::Result<rpc::Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+        let id = self.inner.next_id.fetch_add(1, Ordering::AcqRel);
+        (id, helpers::build_request(id, method, params))
+    }
+
+    fn send(&self, id: RequestId, request: rpc::Call) -> Self::Out {
+        let pool = self.clone();
+        async move { pool.dispatch(id, request).await }.boxed()
+    }
+}
+
+fn is_idempotent(request: &rpc::Call) -> bool {
+    match request {
+        rpc::Call::MethodCall(call) => IDEMPOTENT_METHODS.contains(&call.method.as_str()),
+        _ => false,
+    }
+}
+
+fn parse_quantity(value: &rpc::Value) -> Option<u64> {
+    let hex = value.as_str()?.trim_start_matches("0x");
+    u64::from_str_radix(hex, 16).ok()
+}
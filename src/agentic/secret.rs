@@ -0,0 +1,188 @@
+use std::fmt;
+use web3::signing::{Key, SecretKey, SecretKeyRef, Signature, SigningError};
+use web3::types::Address;
+use zeroize::Zeroizing;
+
+// Decrypted secret material (seeds, key bytes). Wiped on drop and never printed.
+pub struct SecretBytes(Zeroizing<Vec<u8>>);
+
+impl SecretBytes {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self(Zeroizing::new(bytes))
+    }
+
+    pub fn expose(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for SecretBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretBytes(<redacted, {} bytes>)", self.0.len())
+    }
+}
+
+// An unlocked private key. The bytes live in a zeroize-on-drop buffer; only the address
+// shows up in Debug output.
+pub struct PrivateKey {
+    bytes: Zeroizing<[u8; 32]>,
+    address: Address,
+}
+
+impl PrivateKey {
+    pub fn from_secret_key(secret_key: &SecretKey) -> Self {
+        Self {
+            bytes: Zeroizing::new(secret_key.secret_bytes()),
+            address: SecretKeyRef::new(secret_key).address(),
+        }
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self::from_secret_key(&SecretKey::from_slice(bytes)?))
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    // secp256k1's SecretKey is Copy and isn't wiped on drop, so keep what this returns
+    // short-lived and hold on to the PrivateKey instead
+    pub fn secret_key(&self) -> SecretKey {
+        SecretKey::from_slice(self.bytes.as_ref()).expect("bytes came from a valid SecretKey")
+    }
+
+    pub fn expose_bytes(&self) -> &[u8; 32] {
+        &self.bytes
+    }
+}
+
+// Signs with a PrivateKey wherever web3 wants a Key, without handing out a SecretKey that
+// outlives the call
+#[derive(Clone, Copy)]
+pub struct PrivateKeyRef<'a>(&'a PrivateKey);
+
+impl<'a> PrivateKeyRef<'a> {
+    pub fn new(key: &'a PrivateKey) -> Self {
+        Self(key)
+    }
+}
+
+impl Key for PrivateKeyRef<'_> {
+    fn sign(&self, message: &[u8], chain_id: Option<u64>) -> Result<Signature, SigningError> {
+        SecretKeyRef::new(&self.0.secret_key()).sign(message, chain_id)
+    }
+
+    fn sign_message(&self, message: &[u8]) -> Result<Signature, SigningError> {
+        SecretKeyRef::new(&self.0.secret_key()).sign_message(message)
+    }
+
+    fn address(&self) -> Address {
+        self.0.address
+    }
+}
+
+impl fmt::Debug for PrivateKeyRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PrivateKeyRef").field(self.0).finish()
+    }
+}
+
+impl fmt::Debug for PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PrivateKey")
+            .field("address", &self.address)
+            .field("key", &"<redacted>")
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use futures::executor::block_on;
+    use web3::transports::Http;
+    use web3::Web3;
+    use super::super::fee_oracle::FeeOracle;
+    use super::super::kdf::KdfParams;
+    use super::super::keystore_dir::KeystoreDir;
+    use super::super::nonce_manager::NonceManager;
+    use super::super::session::SessionManager;
+    use super::super::wallet_manager::WalletManager;
+
+    const KEY_HEX: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+    fn contains_hex(output: &str, bytes: &[u8]) -> bool {
+        let lower = output.to_lowercase();
+        lower.contains(&hex::encode(bytes)) || lower.contains(&format!("{:?}", bytes))
+    }
+
+    #[test]
+    fn private_key_debug_shows_address_only() {
+        let bytes = [0x42u8; 32];
+        let key = PrivateKey::from_slice(&bytes).unwrap();
+
+        let output = format!("{:?} {:#?}", key, key);
+        assert!(!contains_hex(&output, &bytes));
+        assert!(output.contains("<redacted>"));
+        assert!(output.to_lowercase().contains(&hex::encode(key.address().as_bytes())));
+    }
+
+    #[test]
+    fn secret_bytes_debug_shows_length_only() {
+        let bytes: Vec<u8> = (1..=64).collect();
+        let secret = SecretBytes::new(bytes.clone());
+
+        let output = format!("{:?} {:#?}", secret, secret);
+        assert!(!contains_hex(&output, &bytes));
+        assert!(!output.contains("1, 2, 3"));
+        assert_eq!(output, "SecretBytes(<redacted, 64 bytes>) SecretBytes(<redacted, 64 bytes>)");
+    }
+
+    #[test]
+    fn wallet_manager_and_encrypted_key_debug_hide_key() {
+        let bytes = hex::decode(KEY_HEX).unwrap();
+        let root = std::env::temp_dir().join(format!("secret-debug-{}", hex::encode(rand::random::<[u8; 8]>())));
+        let web3 = Arc::new(Web3::new(Http::new("http://127.0.0.1:8545").unwrap()));
+
+        let manager = block_on(WalletManager::open(web3.clone(), Arc::new(NonceManager::new()), Arc::new(FeeOracle::new(web3)), &root)).unwrap()
+            .with_kdf(KdfParams::Pbkdf2 { iterations: 1_000 });
+        let address = block_on(manager.import_wallet(KEY_HEX, "agent", "password")).unwrap();
+        let session = block_on(manager.unlock(address, "password", Duration::from_secs(60))).unwrap();
+
+        let output = format!("{:?} {:#?}", manager, manager);
+        assert!(!contains_hex(&output, &bytes));
+
+        let stored = KeystoreDir::open(&root).unwrap().load_keys().unwrap();
+        let output = format!("{:?} {:#?}", stored, stored);
+        assert!(!contains_hex(&output, &bytes));
+
+        let info = block_on(manager.active_sessions());
+        let output = format!("{:?} {:#?} {:?}", session, info, manager.sessions());
+        assert!(!contains_hex(&output, &bytes));
+        assert!(!output.contains(&session.to_string()));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn session_key_debug_shows_address_only() {
+        let bytes = hex::decode(KEY_HEX).unwrap();
+        let sessions = SessionManager::new();
+        let handle = block_on(sessions.open(PrivateKey::from_slice(&bytes).unwrap(), Duration::from_secs(60))).unwrap();
+
+        let key = block_on(sessions.key(&handle)).unwrap();
+        let output = format!("{:?} {:?} {:?}", key, PrivateKeyRef::new(&key), sessions);
+        assert!(!contains_hex(&output, &bytes));
+        assert!(output.contains("<redacted>"));
+    }
+}
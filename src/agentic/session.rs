@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use web3::types::Address;
+
+use super::secret::PrivateKey;
+
+// Opaque, unguessable reference to an unlocked key. Holding one is enough to sign until
+// it expires or is locked, so treat it like a short-lived credential.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionHandle([u8; 16]);
+
+impl SessionHandle {
+    fn random() -> Self {
+        Self(rand::random())
+    }
+}
+
+impl fmt::Display for SessionHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+// Only a prefix, so handles don't end up whole in logs
+impl fmt::Debug for SessionHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SessionHandle({}…)", hex::encode(&self.0[..4]))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    pub handle: SessionHandle,
+    pub address: Address,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub last_used_at: Option<u64>,
+    pub signatures: u64,
+}
+
+struct Session {
+    // Zeroed once the session is dropped, whether by expiry, lock or lock_all, and the
+    // last in-flight signature using it has finished
+    key: Arc<PrivateKey>,
+    deadline: Instant,
+    info: SessionInfo,
+}
+
+// Unlocked keys with a time limit. Expired sessions are removed on every access and by
+// the optional reaper task, so a key doesn't outlive its ttl in memory by more than one
+// reaper interval.
+#[derive(Default)]
+pub struct SessionManager {
+    sessions: Mutex<HashMap<SessionHandle, Session>>,
+}
+
+impl SessionManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn open(&self, key: PrivateKey, ttl: Duration) -> Result<SessionHandle, Box<dyn std::error::Error>> {
+        let now = unix_now()?;
+        let handle = SessionHandle::random();
+        let address = key.address();
+        let session = Session {
+            key: Arc::new(key),
+            deadline: Instant::now() + ttl,
+            info: SessionInfo {
+                handle,
+                address,
+                created_at: now,
+                expires_at: now + ttl.as_secs(),
+                last_used_at: None,
+                signatures: 0,
+            },
+        };
+
+        let mut sessions = self.sessions.lock().await;
+        prune(&mut sessions);
+        sessions.insert(handle, session);
+        Ok(handle)
+    }
+
+    // The key behind a live session, shared rather than copied; every call counts as one
+    // use in the audit info
+    pub async fn key(&self, handle: &SessionHandle) -> Result<Arc<PrivateKey>, Box<dyn std::error::Error>> {
+        let mut sessions = self.sessions.lock().await;
+        prune(&mut sessions);
+
+        let session = sessions.get_mut(handle).ok_or("Session expired or locked")?;
+        session.info.last_used_at = Some(unix_now()?);
+        session.info.signatures += 1;
+        Ok(session.key.clone())
+    }
+
+    pub async fn lock(&self, handle: &SessionHandle) -> bool {
+        self.sessions.lock().await.remove(handle).is_some()
+    }
+
+    pub async fn lock_address(&self, address: Address) -> usize {
+        let mut sessions = self.sessions.lock().await;
+        let before = sessions.len();
+        sessions.retain(|_, session| session.info.address != address);
+        before - sessions.len()
+    }
+
+    pub async fn lock_all(&self) {
+        self.sessions.lock().await.clear();
+    }
+
+    pub async fn active_sessions(&self) -> Vec<SessionInfo> {
+        let mut sessions = self.sessions.lock().await;
+        prune(&mut sessions);
+
+        let mut active: Vec<SessionInfo> = sessions.values().map(|session| session.info.clone()).collect();
+        active.sort_by_key(|info| info.created_at);
+        active
+    }
+
+    pub async fn prune_expired(&self) -> usize {
+        let mut sessions = self.sessions.lock().await;
+        let before = sessions.len();
+        prune(&mut sessions);
+        before - sessions.len()
+    }
+
+    // Holds only a weak reference, so the task ends once the manager is dropped
+    pub fn spawn_reaper(self: Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let sessions = Arc::downgrade(&self);
+        drop(self);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                match sessions.upgrade() {
+                    Some(sessions) => { sessions.prune_expired().await; }
+                    None => break,
+                }
+            }
+        })
+    }
+}
+
+impl fmt::Debug for SessionManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionManager").finish_non_exhaustive()
+    }
+}
+
+fn prune(sessions: &mut HashMap<SessionHandle, Session>) {
+    let now = Instant::now();
+    sessions.retain(|_, session| session.deadline > now);
+}
+
+fn unix_now() -> Result<u64, Box<dyn std::error::Error>> {
+    Ok(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs())
+}
@@ -0,0 +1,338 @@
+use std::collections::HashSet;
+use std::path::Path;
+use bip39::Language;
+use web3::signing::keccak256;
+
+use super::keystore_dir;
+
+const SHARE_VERSION: u8 = 1;
+// version, kind, set id, threshold, index, fingerprint, value length
+const HEADER_LEN: usize = 1 + 1 + 4 + 1 + 1 + 4 + 1;
+const CHECKSUM_LEN: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretKind {
+    Seed,
+    PrivateKey,
+}
+
+impl SecretKind {
+    fn to_byte(self) -> u8 {
+        match self {
+            SecretKind::Seed => 0,
+            SecretKind::PrivateKey => 1,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self, Box<dyn std::error::Error>> {
+        match byte {
+            0 => Ok(SecretKind::Seed),
+            1 => Ok(SecretKind::PrivateKey),
+            other => Err(format!("Unknown share secret kind {}", other).into()),
+        }
+    }
+}
+
+// One share of an M-of-N split. Shares from the same split have the same set id; the
+// fingerprint (4 bytes of the secret's keccak256) confirms a reconstruction is the
+// original secret rather than noise from mixed or corrupted shares.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Share {
+    pub kind: SecretKind,
+    pub set_id: [u8; 4],
+    pub threshold: u8,
+    pub index: u8,
+    pub fingerprint: [u8; 4],
+    pub value: Vec<u8>,
+}
+
+impl Share {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.value.len() + CHECKSUM_LEN);
+        bytes.push(SHARE_VERSION);
+        bytes.push(self.kind.to_byte());
+        bytes.extend_from_slice(&self.set_id);
+        bytes.push(self.threshold);
+        bytes.push(self.index);
+        bytes.extend_from_slice(&self.fingerprint);
+        bytes.push(self.value.len() as u8);
+        bytes.extend_from_slice(&self.value);
+        let checksum = keccak256(&bytes);
+        bytes.extend_from_slice(&checksum[..CHECKSUM_LEN]);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        if bytes.len() < HEADER_LEN + CHECKSUM_LEN {
+            return Err("Share is too short".into());
+        }
+        if bytes[0] != SHARE_VERSION {
+            return Err(format!("Unsupported share version {}", bytes[0]).into());
+        }
+
+        let value_len = bytes[HEADER_LEN - 1] as usize;
+        let total_len = HEADER_LEN + value_len + CHECKSUM_LEN;
+        // Word encoding can leave up to one zero byte of padding at the end
+        if bytes.len() < total_len || bytes[total_len..].iter().any(|byte| *byte != 0) {
+            return Err("Share length does not match its header".into());
+        }
+
+        let (body, checksum) = bytes[..total_len].split_at(total_len - CHECKSUM_LEN);
+        if keccak256(body)[..CHECKSUM_LEN] != *checksum {
+            return Err("Share checksum mismatch, check for a mistyped word".into());
+        }
+
+        let index = body[7];
+        if index == 0 {
+            return Err("Share index must not be zero".into());
+        }
+
+        let mut set_id = [0u8; 4];
+        set_id.copy_from_slice(&body[2..6]);
+        let mut fingerprint = [0u8; 4];
+        fingerprint.copy_from_slice(&body[8..12]);
+
+        Ok(Self {
+            kind: SecretKind::from_byte(body[1])?,
+            set_id,
+            threshold: body[6],
+            index,
+            fingerprint,
+            value: body[HEADER_LEN..].to_vec(),
+        })
+    }
+
+    // Space-separated words from the BIP-39 English list, 11 bits per word
+    pub fn to_words(&self) -> String {
+        let word_list = Language::English.word_list();
+        let bytes = self.to_bytes();
+
+        let mut words = Vec::new();
+        let mut accumulator: u32 = 0;
+        let mut bits = 0;
+        for byte in bytes {
+            accumulator = (accumulator << 8) | byte as u32;
+            bits += 8;
+            while bits >= 11 {
+                bits -= 11;
+                words.push(word_list[((accumulator >> bits) & 0x7ff) as usize]);
+            }
+        }
+        if bits > 0 {
+            words.push(word_list[((accumulator << (11 - bits)) & 0x7ff) as usize]);
+        }
+
+        words.join(" ")
+    }
+
+    pub fn from_words(words: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let word_list = Language::English.word_list();
+
+        let mut bytes = Vec::new();
+        let mut accumulator: u32 = 0;
+        let mut bits = 0;
+        for word in words.split_whitespace() {
+            let word = word.to_lowercase();
+            let index = word_list.iter().position(|candidate| *candidate == word).ok_or_else(|| format!("Unknown share word {}", word))?;
+            accumulator = (accumulator << 11) | index as u32;
+            bits += 11;
+            while bits >= 8 {
+                bits -= 8;
+                bytes.push(((accumulator >> bits) & 0xff) as u8);
+            }
+        }
+
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+        keystore_dir::write_atomic(path.as_ref(), format!("{}\n", self.to_words()).as_bytes())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_words(&std::fs::read_to_string(path)?)
+    }
+}
+
+pub fn split_secret(secret: &[u8], kind: SecretKind, threshold: u8, count: u8) -> Result<Vec<Share>, Box<dyn std::error::Error>> {
+    if threshold == 0 || threshold > count {
+        return Err(format!("Threshold {} must be between 1 and the share count {}", threshold, count).into());
+    }
+    if secret.is_empty() || secret.len() > u8::MAX as usize {
+        return Err("Secret must be between 1 and 255 bytes".into());
+    }
+
+    let set_id: [u8; 4] = rand::random();
+    let fingerprint = fingerprint(secret);
+
+    // One random polynomial of degree threshold - 1 per secret byte, constant term = the byte
+    let polynomials: Vec<Vec<u8>> = secret
+        .iter()
+        .map(|byte| {
+            let mut coefficients = vec![*byte];
+            coefficients.extend((1..threshold).map(|_| rand::random::<u8>()));
+            coefficients
+        })
+        .collect();
+
+    Ok((1..=count)
+        .map(|index| Share {
+            kind,
+            set_id,
+            threshold,
+            index,
+            fingerprint,
+            value: polynomials.iter().map(|coefficients| evaluate(coefficients, index)).collect(),
+        })
+        .collect())
+}
+
+pub fn combine_shares(shares: &[Share]) -> Result<(SecretKind, Vec<u8>), Box<dyn std::error::Error>> {
+    let first = shares.first().ok_or("No shares given")?;
+
+    for share in shares {
+        if share.set_id != first.set_id || share.fingerprint != first.fingerprint {
+            return Err(format!("Share {} belongs to a different split", share.index).into());
+        }
+        if share.kind != first.kind || share.threshold != first.threshold || share.value.len() != first.value.len() {
+            return Err(format!("Share {} does not match the other shares' header", share.index).into());
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let unique: Vec<&Share> = shares.iter().filter(|share| seen.insert(share.index)).collect();
+    if unique.len() < first.threshold as usize {
+        return Err(format!("Need {} distinct shares, got {}", first.threshold, unique.len()).into());
+    }
+    let unique = &unique[..first.threshold as usize];
+
+    let secret: Vec<u8> = (0..first.value.len())
+        .map(|position| interpolate_at_zero(unique.iter().map(|share| (share.index, share.value[position]))))
+        .collect();
+
+    if fingerprint(&secret) != first.fingerprint {
+        return Err("Shares reconstruct to the wrong secret; one of them is damaged".into());
+    }
+
+    Ok((first.kind, secret))
+}
+
+fn fingerprint(secret: &[u8]) -> [u8; 4] {
+    let hash = keccak256(secret);
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+// GF(2^8) with the AES polynomial x^8 + x^4 + x^3 + x + 1
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+// a^254 = a^-1 for nonzero a
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    let mut base = a;
+    let mut exponent = 254u8;
+    while exponent != 0 {
+        if exponent & 1 != 0 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exponent >>= 1;
+    }
+    result
+}
+
+fn evaluate(coefficients: &[u8], x: u8) -> u8 {
+    coefficients.iter().rev().fold(0, |accumulator, coefficient| gf_mul(accumulator, x) ^ coefficient)
+}
+
+fn interpolate_at_zero(points: impl Iterator<Item = (u8, u8)> + Clone) -> u8 {
+    let mut result = 0;
+    for (xi, yi) in points.clone() {
+        // Lagrange basis at 0: prod xj / (xj - xi), and subtraction is xor
+        let mut basis = 1;
+        for (xj, _) in points.clone() {
+            if xj != xi {
+                basis = gf_mul(basis, gf_mul(xj, gf_inv(xj ^ xi)));
+            }
+        }
+        result ^= gf_mul(yi, basis);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: [u8; 32] = [
+        0x4c, 0x08, 0x83, 0xa6, 0x91, 0x02, 0x93, 0x7d, 0x62, 0x31, 0x47, 0x1b, 0x5d, 0xbb, 0x62, 0x04,
+        0xfe, 0x51, 0x29, 0x61, 0x70, 0x82, 0x79, 0x2a, 0xe4, 0x68, 0xd0, 0x1a, 0x3f, 0x36, 0x23, 0x18,
+    ];
+
+    #[test]
+    fn field_arithmetic_matches_the_aes_field() {
+        // FIPS-197 section 4.2
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+        assert_eq!(gf_mul(0x57, 0x13), 0xfe);
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+        }
+    }
+
+    #[test]
+    fn any_threshold_subset_reconstructs_the_secret() {
+        let shares = split_secret(&SECRET, SecretKind::PrivateKey, 3, 5).unwrap();
+        for subset in [[0, 1, 2], [0, 2, 4], [4, 3, 1], [1, 2, 3]] {
+            let chosen: Vec<Share> = subset.iter().map(|i| shares[*i].clone()).collect();
+            assert_eq!(combine_shares(&chosen).unwrap(), (SecretKind::PrivateKey, SECRET.to_vec()));
+        }
+    }
+
+    #[test]
+    fn shares_round_trip_through_words() {
+        let shares = split_secret(&SECRET, SecretKind::Seed, 2, 3).unwrap();
+        let decoded: Vec<Share> = shares.iter().map(|share| Share::from_words(&share.to_words()).unwrap()).collect();
+        assert_eq!(decoded, shares);
+        assert_eq!(combine_shares(&decoded[1..]).unwrap(), (SecretKind::Seed, SECRET.to_vec()));
+    }
+
+    #[test]
+    fn below_threshold_is_refused() {
+        let shares = split_secret(&SECRET, SecretKind::PrivateKey, 3, 5).unwrap();
+        assert!(combine_shares(&shares[..2]).is_err());
+        // A repeated share doesn't count twice
+        assert!(combine_shares(&[shares[0].clone(), shares[1].clone(), shares[1].clone()]).is_err());
+    }
+
+    #[test]
+    fn corrupted_shares_are_detected() {
+        let shares = split_secret(&SECRET, SecretKind::PrivateKey, 2, 3).unwrap();
+
+        let mut bytes = shares[0].to_bytes();
+        bytes[HEADER_LEN] ^= 0x01;
+        assert!(Share::from_bytes(&bytes).is_err());
+
+        let mut words: Vec<String> = shares[0].to_words().split(' ').map(str::to_string).collect();
+        words[3] = if words[3] == "abandon" { "ability".to_string() } else { "abandon".to_string() };
+        assert!(Share::from_words(&words.join(" ")).is_err());
+
+        // A value damaged before the checksum was computed still fails the fingerprint
+        let mut damaged = shares[0].clone();
+        damaged.value[0] ^= 0x01;
+        let damaged = Share::from_bytes(&damaged.to_bytes()).unwrap();
+        assert!(combine_shares(&[damaged, shares[1].clone()]).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use std::fmt;
+use std::sync::Arc;
+use web3::ethabi::Token;
+use web3::signing::{self, keccak256, Key};
+use web3::types::{Address, H256, U256};
+use web3::{Transport, Web3};
+
+use super::abi;
+use super::secret::{PrivateKey, PrivateKeyRef};
+use super::smart_contract::SmartContract;
+
+// What isValidSignature(bytes32,bytes) returns when a contract accepts a signature
+pub const ERC1271_MAGIC_VALUE: [u8; 4] = [0x16, 0x26, 0xba, 0x7e];
+
+// secp256k1 group order n
+const SECP256K1_N: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+    0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+];
+
+// EIP-191 version 0x45: keccak256("\x19Ethereum Signed Message:\n" || len(message) || message)
+pub fn eip191_hash(message: &[u8]) -> H256 {
+    let mut payload = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+    payload.extend_from_slice(message);
+    H256::from(keccak256(&payload))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+    pub r: H256,
+    pub s: H256,
+    // 0 or 1; v is 27 + y_parity
+    pub y_parity: u8,
+}
+
+impl Signature {
+    // 65-byte r || s || v, with v as 0/1 or 27/28, or 64-byte EIP-2098 r || yParityAndS
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        match bytes.len() {
+            65 => {
+                let y_parity = match bytes[64] {
+                    0 | 27 => 0,
+                    1 | 28 => 1,
+                    v => return Err(format!("Invalid signature v value {}", v).into()),
+                };
+                Ok(Self { r: H256::from_slice(&bytes[..32]), s: H256::from_slice(&bytes[32..64]), y_parity })
+            }
+            64 => {
+                // The parity rides in the top bit of s, which a low-s value never uses
+                let mut s = [0u8; 32];
+                s.copy_from_slice(&bytes[32..]);
+                let y_parity = s[0] >> 7;
+                s[0] &= 0x7f;
+                Ok(Self { r: H256::from_slice(&bytes[..32]), s: H256(s), y_parity })
+            }
+            len => Err(format!("Signature must be 65 or 64 bytes, found {}", len).into()),
+        }
+    }
+
+    pub fn from_hex(text: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_bytes(&hex::decode(text.trim().trim_start_matches("0x"))?)
+    }
+
+    // r || s || v with v in {27, 28}
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(65);
+        bytes.extend_from_slice(self.r.as_bytes());
+        bytes.extend_from_slice(self.s.as_bytes());
+        bytes.push(27 + self.y_parity);
+        bytes
+    }
+
+    pub fn to_hex(&self) -> String {
+        format!("0x{}", hex::encode(self.to_bytes()))
+    }
+
+    // EIP-2098 only has room for low-s signatures, so this normalizes first
+    pub fn to_compact(&self) -> [u8; 64] {
+        let normalized = self.normalized();
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(normalized.r.as_bytes());
+        bytes[32..].copy_from_slice(normalized.s.as_bytes());
+        bytes[32] |= normalized.y_parity << 7;
+        bytes
+    }
+
+    pub fn is_low_s(&self) -> bool {
+        U256::from_big_endian(self.s.as_bytes()) <= U256::from_big_endian(&SECP256K1_N) / 2
+    }
+
+    // (r, n - s) with the parity flipped recovers the same address; EIP-2 and most
+    // contracts only accept the low-s form
+    pub fn normalized(&self) -> Self {
+        if self.is_low_s() {
+            return *self;
+        }
+        let mut s = [0u8; 32];
+        (U256::from_big_endian(&SECP256K1_N) - U256::from_big_endian(self.s.as_bytes())).to_big_endian(&mut s);
+        Self { r: self.r, s: H256(s), y_parity: self.y_parity ^ 1 }
+    }
+
+    pub fn recover(&self, digest: H256) -> Result<Address, Box<dyn std::error::Error>> {
+        let normalized = self.normalized();
+        let mut rs = [0u8; 64];
+        rs[..32].copy_from_slice(normalized.r.as_bytes());
+        rs[32..].copy_from_slice(normalized.s.as_bytes());
+        signing::recover(digest.as_bytes(), &rs, normalized.y_parity as i32).map_err(|_| "Could not recover signer from signature".into())
+    }
+}
+
+impl fmt::Debug for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Signature({})", self.to_hex())
+    }
+}
+
+// Signs a 32-byte digest as is; callers hash with eip191_hash or an EIP-712 digest first
+pub fn sign_hash(key: &PrivateKey, digest: H256) -> Result<Signature, Box<dyn std::error::Error>> {
+    let signature = PrivateKeyRef::new(key).sign_message(digest.as_bytes())?;
+    let signature = Signature { r: signature.r, s: signature.s, y_parity: (signature.v % 2) as u8 };
+    Ok(signature.normalized())
+}
+
+pub fn recover_message(message: &[u8], signature: &[u8]) -> Result<Address, Box<dyn std::error::Error>> {
+    Signature::from_bytes(signature)?.recover(eip191_hash(message))
+}
+
+// True when `signer` signed `digest`. Addresses with code are asked through ERC-1271
+// isValidSignature, so smart-contract wallets verify as well as plain keys do.
+pub async fn verify_hash<T: Transport>(web3: &Arc<Web3<T>>, signer: Address, digest: H256, signature: &[u8]) -> Result<bool, Box<dyn std::error::Error>> {
+    let code = web3.eth().code(signer, None).await?;
+    if !code.0.is_empty() {
+        return is_valid_erc1271(web3, signer, digest, signature).await;
+    }
+    let parsed = Signature::from_bytes(signature)?;
+    Ok(parsed.recover(digest).map_or(false, |recovered| recovered == signer))
+}
+
+pub async fn verify_message<T: Transport>(web3: &Arc<Web3<T>>, signer: Address, message: &[u8], signature: &[u8]) -> Result<bool, Box<dyn std::error::Error>> {
+    verify_hash(web3, signer, eip191_hash(message), signature).await
+}
+
+// The signature goes to the contract untouched, since wallets define their own formats
+// (e.g. a Safe packs several owner signatures together)
+async fn is_valid_erc1271<T: Transport>(web3: &Arc<Web3<T>>, contract: Address, digest: H256, signature: &[u8]) -> Result<bool, Box<dyn std::error::Error>> {
+    let wallet = SmartContract::read_only(contract, abi::ERC1271_ABI.as_bytes().to_vec(), web3.clone())?;
+    let params = vec![Token::FixedBytes(digest.as_bytes().to_vec()), Token::Bytes(signature.to_vec())];
+    // Contracts without ERC-1271, or that reject by reverting, count as not valid
+    match wallet.call("isValidSignature", params).await {
+        Ok(tokens) => Ok(abi::token_at(&tokens, 0)?.into_fixed_bytes().map_or(false, |value| value == ERC1271_MAGIC_VALUE)),
+        Err(_) => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The examples from EIP-2098, both personal messages signed by this key
+    const KEY_HEX: &str = "1234567890123456789012345678901234567890123456789012345678901234";
+    const SIGNER: &str = "2e988a386a799f506693793c6a5af6b54dfaabfb";
+
+    const EXAMPLES: [(&str, &str, &str, u8, &str); 2] = [
+        (
+            "Hello World",
+            "68a020a209d3d56c46f38cc50a33f704f4a9a10a59377f8dd762ac66910e9b90",
+            "7e865ad05c4035ab5792787d4a0297a43617ae897930a6fe4d822b8faea52064",
+            27,
+            "68a020a209d3d56c46f38cc50a33f704f4a9a10a59377f8dd762ac66910e9b907e865ad05c4035ab5792787d4a0297a43617ae897930a6fe4d822b8faea52064",
+        ),
+        (
+            "It's a small(er) world",
+            "9328da16089fcba9bececa81663203989f2df5fe1faa6291a45381c81bd17f76",
+            "139c6d6b623b42da56557e5e734a43dc83345ddfadec52cbe24d0cc64f550793",
+            28,
+            "9328da16089fcba9bececa81663203989f2df5fe1faa6291a45381c81bd17f76939c6d6b623b42da56557e5e734a43dc83345ddfadec52cbe24d0cc64f550793",
+        ),
+    ];
+
+    fn h256(hex: &str) -> H256 {
+        hex.parse().unwrap()
+    }
+
+    fn signer() -> Address {
+        SIGNER.parse().unwrap()
+    }
+
+    #[test]
+    fn eip2098_examples_round_trip() {
+
+        for (message, r, s, v, compact) in EXAMPLES {
+            let expanded = Signature { r: h256(r), s: h256(s), y_parity: v - 27 };
+            assert_eq!(hex::encode(expanded.to_compact()), compact);
+            assert_eq!(Signature::from_bytes(&hex::decode(compact).unwrap()).unwrap(), expanded);
+            assert_eq!(Signature::from_bytes(&expanded.to_bytes()).unwrap(), expanded);
+
+            assert_eq!(recover_message(message.as_bytes(), &hex::decode(compact).unwrap()).unwrap(), signer());
+            assert_eq!(recover_message(message.as_bytes(), &expanded.to_bytes()).unwrap(), signer());
+        }
+    }
+
+    #[test]
+    fn high_s_signatures_normalize_to_the_same_signer() {
+        let (message, r, s, v, _) = EXAMPLES[0];
+        let low = Signature { r: h256(r), s: h256(s), y_parity: v - 27 };
+
+        let mut high_s = [0u8; 32];
+        (U256::from_big_endian(&SECP256K1_N) - U256::from_big_endian(low.s.as_bytes())).to_big_endian(&mut high_s);
+        let high = Signature { r: low.r, s: H256(high_s), y_parity: low.y_parity ^ 1 };
+
+        assert!(low.is_low_s());
+        assert!(!high.is_low_s());
+        assert_eq!(high.normalized(), low);
+        assert_eq!(high.to_compact(), low.to_compact());
+        assert_eq!(high.recover(eip191_hash(message.as_bytes())).unwrap(), signer());
+    }
+
+    #[test]
+    fn signs_low_s_recoverable_signatures() {
+        let key = PrivateKey::from_slice(&hex::decode(KEY_HEX).unwrap()).unwrap();
+        assert_eq!(key.address(), signer());
+
+        for (message, ..) in EXAMPLES {
+            let signature = sign_hash(&key, eip191_hash(message.as_bytes())).unwrap();
+            assert!(signature.is_low_s());
+            assert_eq!(recover_message(message.as_bytes(), &signature.to_compact()).unwrap(), key.address());
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_signatures() {
+        assert!(Signature::from_bytes(&[0u8; 63]).is_err());
+        let mut bytes = [0u8; 65];
+        bytes[64] = 29;
+        assert!(Signature::from_bytes(&bytes).is_err());
+    }
+}
@@ -0,0 +1,269 @@
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use web3::error::TransportError;
+use web3::transports::Http;
+use web3::types::{AccessList, Address, Bytes, TransactionParameters, TransactionRequest, H256, U256, U64};
+use web3::{helpers, Transport, Web3};
+
+use super::eip712;
+use super::secret::{PrivateKey, PrivateKeyRef};
+use super::signature;
+
+// What a signer produces for a transaction: raw bytes for the caller to broadcast, or the
+// hash of a transaction the signer already broadcast itself (node-managed accounts)
+#[derive(Debug, Clone)]
+pub enum SignerOutput {
+    Raw(Bytes),
+    Sent(H256),
+}
+
+// Signatures are 65 bytes, r || s || v with v in {27, 28}
+#[async_trait]
+pub trait Signer: fmt::Debug + Send + Sync {
+    fn address(&self) -> Address;
+
+    // `tx` must already carry its nonce and chain id
+    async fn sign_transaction(&self, tx: TransactionParameters) -> Result<SignerOutput, web3::Error>;
+
+    // EIP-191 personal message
+    async fn sign_message(&self, message: &[u8]) -> Result<Vec<u8>, web3::Error>;
+
+    // EIP-712 digest keccak256(0x1901 || domain_separator || struct_hash)
+    async fn sign_typed_data(&self, domain_separator: H256, struct_hash: H256) -> Result<Vec<u8>, web3::Error>;
+}
+
+pub async fn send_transaction<T: Transport>(web3: &Web3<T>, signer: &dyn Signer, tx: TransactionParameters) -> Result<H256, web3::Error> {
+    match signer.sign_transaction(tx).await? {
+        SignerOutput::Raw(raw) => web3.eth().send_raw_transaction(raw).await,
+        SignerOutput::Sent(tx_hash) => Ok(tx_hash),
+    }
+}
+
+fn sign_hash(key: &PrivateKey, digest: H256) -> Result<Vec<u8>, web3::Error> {
+    signature::sign_hash(key, digest)
+        .map(|signature| signature.to_bytes())
+        .map_err(|e| signer_error(e.to_string()))
+}
+
+fn signer_error(message: String) -> web3::Error {
+    web3::Error::Transport(TransportError::Message(message))
+}
+
+// A key held in this process, usually unlocked from a WalletManager keystore. Signing is
+// local; the web3 handle is only there because web3's transaction encoder lives on it.
+pub struct LocalSigner<T: Transport = Http> {
+    web3: Arc<Web3<T>>,
+    key: PrivateKey,
+    address: Address,
+}
+
+impl<T: Transport> LocalSigner<T> {
+    pub fn new(web3: Arc<Web3<T>>, key: PrivateKey) -> Self {
+        let address = key.address();
+        Self { web3, key, address }
+    }
+}
+
+impl<T: Transport> fmt::Debug for LocalSigner<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalSigner").field("address", &self.address).finish()
+    }
+}
+
+#[async_trait]
+impl<T: Transport + Send + Sync> Signer for LocalSigner<T>
+where
+    T::Out: Send,
+{
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    async fn sign_transaction(&self, tx: TransactionParameters) -> Result<SignerOutput, web3::Error> {
+        if tx.nonce.is_none() || tx.chain_id.is_none() {
+            return Err(signer_error("Transaction must carry a nonce and chain id before signing".to_string()));
+        }
+        let signed = self.web3.accounts().sign_transaction(tx, PrivateKeyRef::new(&self.key)).await?;
+        Ok(SignerOutput::Raw(signed.raw_transaction))
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Vec<u8>, web3::Error> {
+        sign_hash(&self.key, signature::eip191_hash(message))
+    }
+
+    async fn sign_typed_data(&self, domain_separator: H256, struct_hash: H256) -> Result<Vec<u8>, web3::Error> {
+        sign_hash(&self.key, eip712::typed_data_digest(domain_separator, struct_hash))
+    }
+}
+
+// An account unlocked on the node itself; transactions go out through eth_sendTransaction
+#[derive(Debug)]
+pub struct NodeSigner<T: Transport = Http> {
+    web3: Arc<Web3<T>>,
+    address: Address,
+}
+
+impl<T: Transport> NodeSigner<T> {
+    pub fn new(web3: Arc<Web3<T>>, address: Address) -> Self {
+        Self { web3, address }
+    }
+
+    pub async fn first_account(web3: Arc<Web3<T>>) -> Result<Self, Box<dyn std::error::Error>> {
+        let accounts = web3.eth().accounts().await?;
+        let address = *accounts.first().ok_or("Node has no unlocked accounts")?;
+        Ok(Self::new(web3, address))
+    }
+}
+
+#[async_trait]
+impl<T: Transport + Send + Sync> Signer for NodeSigner<T>
+where
+    T::Out: Send,
+{
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    async fn sign_transaction(&self, tx: TransactionParameters) -> Result<SignerOutput, web3::Error> {
+        let request = TransactionRequest {
+            from: self.address,
+            to: tx.to,
+            gas: Some(tx.gas),
+            gas_price: tx.gas_price,
+            value: Some(tx.value),
+            data: Some(tx.data),
+            nonce: tx.nonce,
+            transaction_type: tx.transaction_type,
+            access_list: tx.access_list,
+            max_fee_per_gas: tx.max_fee_per_gas,
+            max_priority_fee_per_gas: tx.max_priority_fee_per_gas,
+            ..Default::default()
+        };
+
+        let tx_hash = self.web3.eth().send_transaction(request).await?;
+        Ok(SignerOutput::Sent(tx_hash))
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Vec<u8>, web3::Error> {
+        let signature = self.web3.eth().sign(self.address, Bytes(message.to_vec())).await?;
+        Ok(signature.as_bytes().to_vec())
+    }
+
+    async fn sign_typed_data(&self, _domain_separator: H256, _struct_hash: H256) -> Result<Vec<u8>, web3::Error> {
+        // Nodes only sign typed data from the full eth_signTypedData_v4 document, not a digest
+        Err(signer_error("Node-managed accounts can't sign a precomputed typed data hash".to_string()))
+    }
+}
+
+// Transaction fields as they travel to a remote signer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionPayload {
+    pub nonce: U256,
+    pub to: Option<Address>,
+    pub gas: U256,
+    pub gas_price: Option<U256>,
+    pub max_fee_per_gas: Option<U256>,
+    pub max_priority_fee_per_gas: Option<U256>,
+    pub value: U256,
+    pub data: Bytes,
+    pub chain_id: u64,
+    pub transaction_type: Option<U64>,
+    pub access_list: Option<AccessList>,
+}
+
+impl TransactionPayload {
+    pub fn from_parameters(tx: TransactionParameters) -> Result<Self, web3::Error> {
+        Ok(Self {
+            nonce: tx.nonce.ok_or_else(|| signer_error("Transaction has no nonce".to_string()))?,
+            to: tx.to,
+            gas: tx.gas,
+            gas_price: tx.gas_price,
+            max_fee_per_gas: tx.max_fee_per_gas,
+            max_priority_fee_per_gas: tx.max_priority_fee_per_gas,
+            value: tx.value,
+            data: tx.data,
+            chain_id: tx.chain_id.ok_or_else(|| signer_error("Transaction has no chain id".to_string()))?,
+            transaction_type: tx.transaction_type,
+            access_list: tx.access_list,
+        })
+    }
+
+    pub fn into_parameters(self) -> TransactionParameters {
+        TransactionParameters {
+            nonce: Some(self.nonce),
+            to: self.to,
+            gas: self.gas,
+            gas_price: self.gas_price,
+            value: self.value,
+            data: self.data,
+            chain_id: Some(self.chain_id),
+            transaction_type: self.transaction_type,
+            access_list: self.access_list,
+            max_fee_per_gas: self.max_fee_per_gas,
+            max_priority_fee_per_gas: self.max_priority_fee_per_gas,
+        }
+    }
+}
+
+// A signer reached over HTTP JSON-RPC. Keys never enter this process; the daemon answers
+//   signer_signTransaction(address, TransactionPayload) -> raw transaction bytes
+//   signer_signMessage(address, message bytes)          -> 65-byte signature
+//   signer_signTypedData(address, domainSeparator, structHash) -> 65-byte signature
+#[derive(Debug, Clone)]
+pub struct RemoteSigner {
+    transport: Http,
+    address: Address,
+    next_id: Arc<AtomicUsize>,
+}
+
+impl RemoteSigner {
+    pub fn new(url: &str, address: Address) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            transport: Http::new(url)?,
+            address,
+            next_id: Arc::new(AtomicUsize::new(1)),
+        })
+    }
+
+    async fn request(&self, method: &str, params: Vec<serde_json::Value>) -> Result<serde_json::Value, web3::Error> {
+        let id = self.next_id.fetch_add(1, Ordering::AcqRel);
+        let request = helpers::build_request(id, method, params);
+        self.transport.send(id, request).await
+    }
+
+    async fn request_bytes(&self, method: &str, params: Vec<serde_json::Value>) -> Result<Bytes, web3::Error> {
+        let value = self.request(method, params).await?;
+        serde_json::from_value(value).map_err(|e| web3::Error::InvalidResponse(format!("{} returned malformed bytes: {}", method, e)))
+    }
+}
+
+#[async_trait]
+impl Signer for RemoteSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    async fn sign_transaction(&self, tx: TransactionParameters) -> Result<SignerOutput, web3::Error> {
+        let payload = TransactionPayload::from_parameters(tx)?;
+        let raw = self.request_bytes("signer_signTransaction", vec![helpers::serialize(&self.address), helpers::serialize(&payload)]).await?;
+        Ok(SignerOutput::Raw(raw))
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Vec<u8>, web3::Error> {
+        let signature = self.request_bytes("signer_signMessage", vec![helpers::serialize(&self.address), helpers::serialize(&Bytes(message.to_vec()))]).await?;
+        Ok(signature.0)
+    }
+
+    async fn sign_typed_data(&self, domain_separator: H256, struct_hash: H256) -> Result<Vec<u8>, web3::Error> {
+        let signature = self.request_bytes("signer_signTypedData", vec![
+            helpers::serialize(&self.address),
+            helpers::serialize(&domain_separator),
+            helpers::serialize(&struct_hash),
+        ]).await?;
+        Ok(signature.0)
+    }
+}
@@ -0,0 +1,387 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use jsonrpc_core as rpc;
+use tokio::sync::Mutex;
+use web3::types::{Address, Bytes, H256, U256};
+use web3::transports::Http;
+use web3::Transport;
+
+use super::signer::{LocalSigner, Signer, SignerOutput, TransactionPayload};
+use super::wallet_manager::WalletManager;
+
+const POLICY_VIOLATION: i64 = -32001;
+const UNKNOWN_KEY: i64 = -32002;
+const SIGNING_FAILED: i64 = -32003;
+
+// Limits on what the daemon will sign for one key. `None` leaves that dimension open;
+// contract creation, plain transfers under a selector allowlist and off-chain signatures
+// (messages, typed data such as permits) have to be allowed explicitly.
+#[derive(Debug, Clone, Default)]
+pub struct KeyPolicy {
+    pub allowed_destinations: Option<HashSet<Address>>,
+    pub max_value: Option<U256>,
+    // Per-gas price ceiling, checked against max_fee_per_gas or the legacy gas_price
+    pub max_fee_per_gas: Option<U256>,
+    // Most the transaction may cost in total: gas × max fee + value. Without it an allowed
+    // zero-value call could still spend the balance on fees.
+    pub max_gas_cost: Option<U256>,
+    pub allowed_selectors: Option<HashSet<[u8; 4]>>,
+    // Lets empty calldata through when allowed_selectors is set; without a selector
+    // allowlist plain transfers are only limited by destination and value
+    pub allow_plain_transfers: bool,
+    pub allow_contract_creation: bool,
+    pub allow_off_chain_signatures: bool,
+}
+
+impl KeyPolicy {
+    pub fn check_transaction(&self, tx: &TransactionPayload) -> Result<(), String> {
+        match tx.to {
+            Some(to) => {
+                if let Some(allowed) = &self.allowed_destinations {
+                    if !allowed.contains(&to) {
+                        return Err(format!("Destination {:?} is not allowed for this key", to));
+                    }
+                }
+            }
+            None if !self.allow_contract_creation => return Err("Contract creation is not allowed for this key".to_string()),
+            None => {}
+        }
+
+        if let Some(max_value) = self.max_value {
+            if tx.value > max_value {
+                return Err(format!("Value {} exceeds the limit of {} wei for this key", tx.value, max_value));
+            }
+        }
+
+        if self.max_fee_per_gas.is_some() || self.max_gas_cost.is_some() {
+            // Unpriced payloads would be filled in by the signer, so their cost is unknown here
+            let fee_per_gas = tx.max_fee_per_gas.or(tx.gas_price)
+                .ok_or_else(|| "Transaction must set max_fee_per_gas or gas_price for this key".to_string())?;
+
+            if let Some(max_fee_per_gas) = self.max_fee_per_gas {
+                if fee_per_gas > max_fee_per_gas {
+                    return Err(format!("Fee of {} wei per gas exceeds the limit of {} wei for this key", fee_per_gas, max_fee_per_gas));
+                }
+            }
+
+            if let Some(max_gas_cost) = self.max_gas_cost {
+                let cost = tx.gas.checked_mul(fee_per_gas)
+                    .and_then(|fees| fees.checked_add(tx.value))
+                    .ok_or_else(|| "Transaction cost overflows".to_string())?;
+                if cost > max_gas_cost {
+                    return Err(format!("Worst-case cost of {} wei exceeds the limit of {} wei for this key", cost, max_gas_cost));
+                }
+            }
+        }
+
+        if let Some(allowed) = &self.allowed_selectors {
+            let data = &tx.data.0;
+            if data.is_empty() {
+                if !self.allow_plain_transfers {
+                    return Err("Plain transfers without calldata are not allowed for this key".to_string());
+                }
+            } else {
+                if data.len() < 4 {
+                    return Err("Calldata is shorter than a method selector".to_string());
+                }
+                let selector = [data[0], data[1], data[2], data[3]];
+                if !allowed.contains(&selector) {
+                    return Err(format!("Method selector 0x{} is not allowed for this key", hex::encode(selector)));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn check_off_chain(&self) -> Result<(), String> {
+        if self.allow_off_chain_signatures {
+            Ok(())
+        } else {
+            Err("Message and typed data signing is not allowed for this key".to_string())
+        }
+    }
+}
+
+// The signer is shared so requests can take it out of the key map and sign without
+// holding the map's lock
+struct AuthorizedKey<T: Transport> {
+    signer: Arc<LocalSigner<T>>,
+    policy: KeyPolicy,
+}
+
+// The custody side of RemoteSigner. Keys are unlocked from a WalletManager keystore once,
+// when they are authorized, and every request is checked against that key's policy before
+// anything is signed. The daemon only signs; broadcasting stays with the agent.
+#[derive(Clone)]
+pub struct SignerDaemon<T: Transport = Http> {
+    wallets: Arc<WalletManager<T>>,
+    keys: Arc<Mutex<HashMap<Address, AuthorizedKey<T>>>>,
+}
+
+impl<T: Transport> SignerDaemon<T> {
+    pub fn new(wallets: Arc<WalletManager<T>>) -> Self {
+        Self {
+            wallets,
+            keys: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub async fn authorize(&self, address: Address, password: &str, policy: KeyPolicy) -> Result<(), Box<dyn std::error::Error>> {
+        let signer = self.wallets.signer(address, password).await?;
+        self.keys.lock().await.insert(address, AuthorizedKey { signer: Arc::new(signer), policy });
+        Ok(())
+    }
+
+    pub async fn revoke(&self, address: Address) {
+        self.keys.lock().await.remove(&address);
+    }
+
+    pub async fn set_policy(&self, address: Address, policy: KeyPolicy) -> Result<(), Box<dyn std::error::Error>> {
+        let mut keys = self.keys.lock().await;
+        let key = keys.get_mut(&address).ok_or("Key is not authorized")?;
+        key.policy = policy;
+        Ok(())
+    }
+
+    pub async fn accounts(&self) -> Vec<Address> {
+        self.keys.lock().await.keys().cloned().collect()
+    }
+
+    async fn authorized(&self, address: Address) -> Result<(Arc<LocalSigner<T>>, KeyPolicy), rpc::Error> {
+        let keys = self.keys.lock().await;
+        let key = keys.get(&address).ok_or_else(|| error(UNKNOWN_KEY, format!("Key {:?} is not authorized", address)))?;
+        Ok((key.signer.clone(), key.policy.clone()))
+    }
+}
+
+impl<T: Transport + Send + Sync + 'static> SignerDaemon<T>
+where
+    T::Out: Send,
+{
+    // Serves the protocol RemoteSigner speaks. Bind to a loopback or otherwise private
+    // address: the daemon has no authentication of its own beyond the key policies.
+    // The handle resolves with the server's error if it stops
+    pub fn serve(&self, addr: SocketAddr) -> Result<(SocketAddr, tokio::task::JoinHandle<Result<(), hyper::Error>>), Box<dyn std::error::Error>> {
+        let daemon = self.clone();
+        let make_service = make_service_fn(move |_| {
+            let daemon = daemon.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let daemon = daemon.clone();
+                    async move { Ok::<_, Infallible>(daemon.handle_http(request).await) }
+                }))
+            }
+        });
+
+        let server = Server::try_bind(&addr)?.serve(make_service);
+        let local_addr = server.local_addr();
+        let handle = tokio::spawn(server);
+
+        Ok((local_addr, handle))
+    }
+
+    // Runs the daemon on an ephemeral loopback port, for running both sides on one machine
+    pub fn serve_local(&self) -> Result<(String, tokio::task::JoinHandle<Result<(), hyper::Error>>), Box<dyn std::error::Error>> {
+        let (addr, handle) = self.serve(SocketAddr::from(([127, 0, 0, 1], 0)))?;
+        Ok((format!("http://{}", addr), handle))
+    }
+
+    async fn handle_http(&self, request: Request<Body>) -> Response<Body> {
+        let body = match hyper::body::to_bytes(request.into_body()).await {
+            Ok(body) => body,
+            Err(e) => return json_response(&rpc::Response::from(rpc::Error::invalid_params(e.to_string()), Some(rpc::Version::V2))),
+        };
+
+        let response = match serde_json::from_slice::<rpc::Request>(&body) {
+            Ok(rpc::Request::Single(call)) => self.handle_call(call).await.map(rpc::Response::Single),
+            Ok(rpc::Request::Batch(calls)) => {
+                let mut outputs = Vec::new();
+                for call in calls {
+                    outputs.extend(self.handle_call(call).await);
+                }
+                Some(rpc::Response::Batch(outputs))
+            }
+            Err(_) => Some(rpc::Response::from(rpc::Error::parse_error(), Some(rpc::Version::V2))),
+        };
+
+        match response {
+            Some(response) => json_response(&response),
+            None => Response::new(Body::empty()),
+        }
+    }
+
+    async fn handle_call(&self, call: rpc::Call) -> Option<rpc::Output> {
+        let call = match call {
+            rpc::Call::MethodCall(call) => call,
+            // Notifications get no answer, and nothing is signed for them
+            rpc::Call::Notification(_) => return None,
+            rpc::Call::Invalid { id } => return Some(rpc::Output::from(Err(rpc::Error::invalid_request()), id, Some(rpc::Version::V2))),
+        };
+
+        let params: Vec<serde_json::Value> = match call.params {
+            rpc::Params::Array(params) => params,
+            rpc::Params::None => Vec::new(),
+            rpc::Params::Map(_) => return Some(rpc::Output::from(Err(rpc::Error::invalid_params("Expected positional parameters")), call.id, call.jsonrpc)),
+        };
+
+        let result = self.dispatch(&call.method, params).await;
+        Some(rpc::Output::from(result, call.id, call.jsonrpc))
+    }
+
+    async fn dispatch(&self, method: &str, params: Vec<serde_json::Value>) -> Result<serde_json::Value, rpc::Error> {
+        match method {
+            "signer_accounts" => Ok(serde_json::json!(self.accounts().await)),
+            "signer_signTransaction" => {
+                let address: Address = param(&params, 0)?;
+                let payload: TransactionPayload = param(&params, 1)?;
+
+                let (signer, policy) = self.authorized(address).await?;
+                policy.check_transaction(&payload).map_err(|message| error(POLICY_VIOLATION, message))?;
+
+                match signer.sign_transaction(payload.into_parameters()).await {
+                    Ok(SignerOutput::Raw(raw)) => Ok(serde_json::json!(raw)),
+                    Ok(SignerOutput::Sent(_)) => Err(error(SIGNING_FAILED, "Local signer broadcast instead of signing".to_string())),
+                    Err(e) => Err(error(SIGNING_FAILED, e.to_string())),
+                }
+            }
+            "signer_signMessage" => {
+                let address: Address = param(&params, 0)?;
+                let message: Bytes = param(&params, 1)?;
+
+                let (signer, policy) = self.authorized(address).await?;
+                policy.check_off_chain().map_err(|message| error(POLICY_VIOLATION, message))?;
+
+                let signature = signer.sign_message(&message.0).await.map_err(|e| error(SIGNING_FAILED, e.to_string()))?;
+                Ok(serde_json::json!(Bytes(signature)))
+            }
+            "signer_signTypedData" => {
+                let address: Address = param(&params, 0)?;
+                let domain_separator: H256 = param(&params, 1)?;
+                let struct_hash: H256 = param(&params, 2)?;
+
+                let (signer, policy) = self.authorized(address).await?;
+                policy.check_off_chain().map_err(|message| error(POLICY_VIOLATION, message))?;
+
+                let signature = signer.sign_typed_data(domain_separator, struct_hash).await.map_err(|e| error(SIGNING_FAILED, e.to_string()))?;
+                Ok(serde_json::json!(Bytes(signature)))
+            }
+            _ => Err(rpc::Error::method_not_found()),
+        }
+    }
+}
+
+fn param<P: serde::de::DeserializeOwned>(params: &[serde_json::Value], index: usize) -> Result<P, rpc::Error> {
+    let value = params.get(index).cloned().ok_or_else(|| rpc::Error::invalid_params(format!("Missing parameter {}", index)))?;
+    serde_json::from_value(value).map_err(|e| rpc::Error::invalid_params(format!("Parameter {}: {}", index, e)))
+}
+
+fn error(code: i64, message: String) -> rpc::Error {
+    rpc::Error {
+        code: rpc::ErrorCode::ServerError(code),
+        message,
+        data: None,
+    }
+}
+
+fn json_response(response: &rpc::Response) -> Response<Body> {
+    let body = serde_json::to_vec(response).unwrap_or_default();
+    Response::builder()
+        .header("content-type", "application/json")
+        .body(Body::from(body))
+        .unwrap_or_else(|_| Response::new(Body::empty()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use web3::types::TransactionParameters;
+    use web3::Web3;
+    use super::super::fee_oracle::FeeOracle;
+    use super::super::kdf::KdfParams;
+    use super::super::nonce_manager::NonceManager;
+    use super::super::signature;
+    use super::super::signer::RemoteSigner;
+
+    const KEY_HEX: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+    const TRANSFER_SELECTOR: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+
+    fn assert_policy_violation<O>(result: Result<O, web3::Error>) {
+        match result {
+            Err(web3::Error::Rpc(e)) => assert_eq!(e.code, rpc::ErrorCode::ServerError(POLICY_VIOLATION), "{}", e.message),
+            Err(e) => panic!("expected a policy violation, got {:?}", e),
+            Ok(_) => panic!("expected a policy violation, got a signature"),
+        }
+    }
+
+    #[tokio::test]
+    async fn remote_signer_requests_are_checked_against_the_key_policy() {
+        let web3 = Arc::new(Web3::new(Http::new("http://127.0.0.1:8545").unwrap()));
+        let wallets = WalletManager::new(web3.clone(), Arc::new(NonceManager::new()), Arc::new(FeeOracle::new(web3)))
+            .with_kdf(KdfParams::Pbkdf2 { iterations: 1_000 });
+        let address = wallets.import_wallet(KEY_HEX, "agent", "password").await.unwrap();
+
+        let token = Address::from_low_u64_be(0x1000);
+        let policy = KeyPolicy {
+            allowed_destinations: Some(vec![token].into_iter().collect()),
+            max_value: Some(U256::from(1_000)),
+            max_fee_per_gas: Some(U256::from(100_000_000_000u64)),
+            allowed_selectors: Some(vec![TRANSFER_SELECTOR].into_iter().collect()),
+            ..Default::default()
+        };
+
+        let daemon = SignerDaemon::new(Arc::new(wallets));
+        daemon.authorize(address, "password", policy.clone()).await.unwrap();
+        let (url, server) = daemon.serve_local().unwrap();
+        let signer = RemoteSigner::new(&url, address).unwrap();
+
+        let mut transfer = TRANSFER_SELECTOR.to_vec();
+        transfer.extend_from_slice(&[0u8; 64]);
+        let allowed = TransactionParameters {
+            nonce: Some(U256::zero()),
+            to: Some(token),
+            gas: U256::from(60_000),
+            value: U256::zero(),
+            data: Bytes(transfer.clone()),
+            chain_id: Some(1),
+            transaction_type: Some(2.into()),
+            max_fee_per_gas: Some(U256::from(30_000_000_000u64)),
+            max_priority_fee_per_gas: Some(U256::from(1_000_000_000u64)),
+            ..Default::default()
+        };
+
+        match signer.sign_transaction(allowed.clone()).await.unwrap() {
+            SignerOutput::Raw(raw) => assert!(!raw.0.is_empty()),
+            SignerOutput::Sent(_) => panic!("daemon broadcast instead of signing"),
+        }
+
+        let wrong_destination = TransactionParameters { to: Some(Address::from_low_u64_be(0x2000)), ..allowed.clone() };
+        assert_policy_violation(signer.sign_transaction(wrong_destination).await);
+
+        let too_much_value = TransactionParameters { value: U256::from(1_001), ..allowed.clone() };
+        assert_policy_violation(signer.sign_transaction(too_much_value).await);
+
+        let mut approve = vec![0x09, 0x5e, 0xa7, 0xb3];
+        approve.extend_from_slice(&transfer[4..]);
+        let wrong_selector = TransactionParameters { data: Bytes(approve), ..allowed.clone() };
+        assert_policy_violation(signer.sign_transaction(wrong_selector).await);
+
+        let plain_transfer = TransactionParameters { data: Bytes::default(), ..allowed.clone() };
+        assert_policy_violation(signer.sign_transaction(plain_transfer.clone()).await);
+
+        assert_policy_violation(signer.sign_message(b"hello").await);
+        assert_policy_violation(signer.sign_typed_data(H256::repeat_byte(1), H256::repeat_byte(2)).await);
+
+        let opened = KeyPolicy { allow_plain_transfers: true, allow_off_chain_signatures: true, ..policy };
+        daemon.set_policy(address, opened).await.unwrap();
+        assert!(matches!(signer.sign_transaction(plain_transfer).await.unwrap(), SignerOutput::Raw(_)));
+        let message_signature = signer.sign_message(b"hello").await.unwrap();
+        assert_eq!(signature::recover_message(b"hello", &message_signature).unwrap(), address);
+
+        server.abort();
+    }
+}
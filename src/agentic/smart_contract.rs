@@ -1,79 +1,201 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use web3::ethabi::{Contract, Event, Function, Token};
 use web3::types::{Address, U256, H256};
 use web3::Web3;
 use web3::transports::Http;
+use web3::{DuplexTransport, Transport};
+
+use super::abi;
+use super::access_list::{self, AccessListResult};
+use super::contract_error::ContractError;
+use super::events::{self, DecodedLog};
+use super::eip712::TypedData;
+use super::fee_oracle::{FeeOracle, FeeSpeed};
+use super::log_fetcher::{self, LogFetcher, LogQuery};
+use super::nonce_manager::NonceManager;
+use super::signature::{self, Signature};
+use super::signer::{self, Signer};
+
+#[derive(Debug, Clone, Default)]
+pub struct TxOptions {
+    pub fee_speed: FeeSpeed,
+    pub gas_limit: Option<U256>,
+    // Run eth_createAccessList first and attach the list when it lowers gas
+    pub access_list: bool,
+}
 
 #[derive(Debug, Clone)]
-pub struct SmartContract {
+pub struct SentTransaction {
+    pub tx_hash: H256,
+    pub access_list: Option<AccessListResult>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SmartContract<T: Transport = Http> {
     address: Address,
-    abi: Vec<u8>,
-    web3: Arc<Web3<Http>>,
+    abi: Contract,
+    web3: Arc<Web3<T>>,
+    // Both None for read-only contracts, which refuse to send
+    nonce_manager: Option<Arc<NonceManager>>,
+    fee_oracle: Option<Arc<FeeOracle<T>>>,
+    signer: Option<Arc<dyn Signer>>,
 }
 
-impl SmartContract {
-    pub fn new(address: Address, abi: Vec<u8>, web3: Arc<Web3<Http>>) -> Self {
-        Self { address, abi, web3 }
+impl<T: Transport> SmartContract<T> {
+    // Sends draw nonces and fees from the given manager and oracle, which should be the ones
+    // shared by every other sender for the same accounts
+    pub fn new(address: Address, abi: Vec<u8>, web3: Arc<Web3<T>>, nonce_manager: Arc<NonceManager>, fee_oracle: Arc<FeeOracle<T>>) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut contract = Self::read_only(address, abi, web3)?;
+        contract.nonce_manager = Some(nonce_manager);
+        contract.fee_oracle = Some(fee_oracle);
+        Ok(contract)
     }
 
-    pub async fn deploy(&self, bytecode: Vec<u8>, constructor_args: Vec<u8>) -> Result<H256, Box<dyn std::error::Error>> {
-        let accounts = self.web3.eth().accounts().await?;
-        let from = accounts[0];
+    // For calls, gas estimates and event queries only; deploys and sends fail
+    pub fn read_only(address: Address, abi: Vec<u8>, web3: Arc<Web3<T>>) -> Result<Self, Box<dyn std::error::Error>> {
+        let abi = abi::parse_abi(&abi)?;
+        Ok(Self {
+            address,
+            abi,
+            web3,
+            nonce_manager: None,
+            fee_oracle: None,
+            signer: None,
+        })
+    }
 
-        let tx = web3::types::TransactionRequest {
-            from: Some(from),
-            to: None,
-            gas: Some(U256::from(3000000)),
-            gas_price: Some(self.web3.eth().gas_price().await?),
-            value: Some(U256::zero()),
-            data: Some(bytecode.into()),
-            nonce: Some(self.web3.eth().transaction_count(from, None).await?),
-            ..Default::default()
-        };
+    pub fn with_signer(mut self, signer: Arc<dyn Signer>) -> Self {
+        self.signer = Some(signer);
+        self
+    }
 
-        let signed_tx = self.web3.accounts().sign_transaction(tx, &web3::signing::SecretKeyRef::new(&[0; 32])).await?;
-        let tx_hash = self.web3.eth().send_raw_transaction(signed_tx.raw_transaction).await?;
+    pub fn address(&self) -> Address {
+        self.address
+    }
 
-        Ok(tx_hash)
+    pub fn signer(&self) -> Result<Arc<dyn Signer>, Box<dyn std::error::Error>> {
+        self.signer.clone().ok_or_else(|| "No signer configured for this contract".into())
     }
 
-    pub async fn call(&self, method: &str, params: Vec<web3::types::Bytes>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let function_signature = web3::contract::tokens::Function::new(method, vec![], vec![], false)?;
-        let data = function_signature.encode_input(params)?;
+    pub fn abi(&self) -> &Contract {
+        &self.abi
+    }
 
-        let tx = web3::types::TransactionRequest {
+    pub fn function(&self, method: &str, params: &[Token]) -> Result<Function, Box<dyn std::error::Error>> {
+        abi::resolve_function(&self.abi, method, params)
+    }
+
+    pub fn encode_call(&self, method: &str, params: &[Token]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        abi::encode_call(&self.abi, method, params)
+    }
+
+    pub async fn deploy(&self, bytecode: Vec<u8>, constructor_args: Vec<Token>) -> Result<H256, Box<dyn std::error::Error>> {
+        let data = match self.abi.constructor() {
+            Some(constructor) => constructor.encode_input(bytecode, &constructor_args)?,
+            None if constructor_args.is_empty() => bytecode,
+            None => return Err("ABI has no constructor but constructor arguments were given".into()),
+        };
+
+        let options = TxOptions { gas_limit: Some(U256::from(3000000)), ..Default::default() };
+        Ok(self.submit(None, data, U256::zero(), &options).await?.tx_hash)
+    }
+
+    pub async fn call(&self, method: &str, params: Vec<Token>) -> Result<Vec<Token>, Box<dyn std::error::Error>> {
+        let function = self.function(method, &params)?;
+        let data = function.encode_input(&params)?;
+
+        let result = self.call_raw(data).await?;
+        abi::decode_output(&function, &result)
+    }
+
+    pub async fn call_raw(&self, data: Vec<u8>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let tx = web3::types::CallRequest {
             to: Some(self.address),
             data: Some(data.into()),
             ..Default::default()
         };
 
-        let result = self.web3.eth().call(tx, None).await?;
+        let result = self.web3.eth().call(tx, None).await.map_err(|e| self.revert_error(e))?;
         Ok(result.0)
     }
 
-    pub async fn send_transaction(&self, method: &str, params: Vec<web3::types::Bytes>, value: U256) -> Result<H256, Box<dyn std::error::Error>> {
-        let accounts = self.web3.eth().accounts().await?;
-        let from = accounts[0];
+    pub async fn send_transaction(&self, method: &str, params: Vec<Token>, value: U256) -> Result<H256, Box<dyn std::error::Error>> {
+        Ok(self.send_transaction_with(method, params, value, TxOptions::default()).await?.tx_hash)
+    }
+
+    pub async fn send_transaction_with(&self, method: &str, params: Vec<Token>, value: U256, options: TxOptions) -> Result<SentTransaction, Box<dyn std::error::Error>> {
+        let data = self.encode_call(method, &params)?;
+        self.submit(Some(self.address), data, value, &options).await
+    }
+
+    pub async fn create_access_list(&self, method: &str, params: Vec<Token>, value: U256) -> Result<AccessListResult, Box<dyn std::error::Error>> {
+        let data = self.encode_call(method, &params)?;
+        let from = self.signer()?.address();
 
-        let function_signature = web3::contract::tokens::Function::new(method, vec![], vec![], false)?;
-        let data = function_signature.encode_input(params)?;
+        self.access_list_for(from, Some(self.address), &data, value).await
+    }
 
-        let tx = web3::types::TransactionRequest {
+    async fn access_list_for(&self, from: Address, to: Option<Address>, data: &[u8], value: U256) -> Result<AccessListResult, Box<dyn std::error::Error>> {
+        let request = web3::types::CallRequest {
             from: Some(from),
-            to: Some(self.address),
-            gas: Some(U256::from(200000)),
-            gas_price: Some(self.web3.eth().gas_price().await?),
+            to,
             value: Some(value),
-            data: Some(data.into()),
-            nonce: Some(self.web3.eth().transaction_count(from, None).await?),
+            data: Some(data.to_vec().into()),
+            transaction_type: Some(2.into()),
             ..Default::default()
         };
 
-        let signed_tx = self.web3.accounts().sign_transaction(tx, &web3::signing::SecretKeyRef::new(&[0; 32])).await?;
-        let tx_hash = self.web3.eth().send_raw_transaction(signed_tx.raw_transaction).await?;
+        access_list::create_access_list(&self.web3, request).await
+    }
+
+    async fn submit(&self, to: Option<Address>, data: Vec<u8>, value: U256, options: &TxOptions) -> Result<SentTransaction, Box<dyn std::error::Error>> {
+        let (nonce_manager, fee_oracle) = match (&self.nonce_manager, &self.fee_oracle) {
+            (Some(nonce_manager), Some(fee_oracle)) => (nonce_manager, fee_oracle),
+            _ => return Err("Contract was opened read-only and cannot send transactions".into()),
+        };
+        let signer = self.signer()?;
+        let from = signer.address();
+        let chain_id = self.web3.eth().chain_id().await?.as_u64();
+        let fees = fee_oracle.estimate(options.fee_speed).await?;
+
+        let access_list = if options.access_list {
+            Some(self.access_list_for(from, to, &data, value).await?)
+        } else {
+            None
+        };
+        let attached = access_list.as_ref().filter(|result| result.saves_gas());
+
+        let gas = match (options.gas_limit, attached, access_list.as_ref()) {
+            (Some(gas_limit), _, _) => gas_limit,
+            (None, Some(result), _) => result.gas_limit(),
+            // The list didn't save gas, but the plain estimate that showed it is still good
+            (None, None, Some(result)) => result.gas_limit_without_access_list(),
+            (None, None, None) => U256::from(200000),
+        };
+
+        let (_, tx_hash) = nonce_manager.send_with_nonce(&self.web3, chain_id, from, |nonce| {
+            let web3 = self.web3.clone();
+            let signer = signer.clone();
+            let tx = web3::types::TransactionParameters {
+                to,
+                gas,
+                max_fee_per_gas: Some(fees.max_fee_per_gas),
+                max_priority_fee_per_gas: Some(fees.max_priority_fee_per_gas),
+                transaction_type: Some(2.into()),
+                access_list: attached.map(|result| result.access_list.clone()),
+                value,
+                data: data.clone().into(),
+                nonce: Some(nonce),
+                chain_id: Some(chain_id),
+                ..Default::default()
+            };
+
+            async move { signer::send_transaction(&web3, signer.as_ref(), tx).await }
+        }).await?;
 
-        Ok(tx_hash)
+        Ok(SentTransaction { tx_hash, access_list })
     }
 
     pub async fn get_balance(&self, address: Address) -> Result<U256, Box<dyn std::error::Error>> {
@@ -81,33 +203,50 @@ impl SmartContract {
         Ok(balance)
     }
 
-    pub async fn estimate_gas(&self, method: &str, params: Vec<web3::types::Bytes>) -> Result<U256, Box<dyn std::error::Error>> {
-        let function_signature = web3::contract::tokens::Function::new(method, vec![], vec![], false)?;
-        let data = function_signature.encode_input(params)?;
+    pub async fn estimate_gas(&self, method: &str, params: Vec<Token>) -> Result<U256, Box<dyn std::error::Error>> {
+        let data = self.encode_call(method, &params)?;
 
-        let tx = web3::types::TransactionRequest {
+        let tx = web3::types::CallRequest {
             to: Some(self.address),
             data: Some(data.into()),
             ..Default::default()
         };
 
-        let gas_estimate = self.web3.eth().estimate_gas(tx, None).await?;
+        let gas_estimate = self.web3.eth().estimate_gas(tx, None).await.map_err(|e| self.revert_error(e))?;
         Ok(gas_estimate)
     }
 
-    pub async fn get_events(&self, event_name: &str, from_block: U256, to_block: U256) -> Result<Vec<web3::types::Log>, Box<dyn std::error::Error>> {
-        let event_signature = web3::contract::tokens::Event::new(event_name, vec![])?;
-        let topics = vec![Some(event_signature.signature())];
+    // Reverts come back as a ContractError decoded against this contract's ABI, anything
+    // else as the original error
+    fn revert_error(&self, error: web3::Error) -> Box<dyn std::error::Error> {
+        match ContractError::from_web3_error(&error, Some(&self.abi)) {
+            Some(contract_error) => Box::new(contract_error),
+            None => Box::new(error),
+        }
+    }
+
+    // `event` is a name or full signature from this contract's ABI
+    pub async fn get_events(&self, event: &str, from_block: U256, to_block: U256) -> Result<Vec<DecodedLog>, Box<dyn std::error::Error>> {
+        self.get_events_where(event, &[], from_block, to_block).await
+    }
 
-        let filter = web3::types::FilterBuilder::default()
-            .address(vec![self.address])
-            .topics(Some(topics), None, None, None)
-            .from_block(web3::types::BlockNumber::Number(from_block))
-            .to_block(web3::types::BlockNumber::Number(to_block))
-            .build();
+    // As get_events, narrowed to logs whose indexed parameters match `indexed` in order;
+    // None leaves a parameter unconstrained
+    pub async fn get_events_where(&self, event: &str, indexed: &[Option<Token>], from_block: U256, to_block: U256) -> Result<Vec<DecodedLog>, Box<dyn std::error::Error>> {
+        let event = events::resolve_event(&self.abi, event)?;
+        let query = self.event_query(&event, indexed)?;
 
-        let logs = self.web3.eth().logs(filter).await?;
-        Ok(logs)
+        let logs = LogFetcher::new(self.web3.clone()).fetch(&query, log_fetcher::block_number(from_block)?, log_fetcher::block_number(to_block)?).await?;
+        events::decode_logs(&event, &logs)
+    }
+
+    // None when the log isn't one of this contract's ABI events
+    pub fn decode_log(&self, log: &web3::types::Log) -> Option<DecodedLog> {
+        events::decode_log(&self.abi, log)
+    }
+
+    pub fn event_query(&self, event: &Event, indexed: &[Option<Token>]) -> Result<LogQuery, Box<dyn std::error::Error>> {
+        Ok(LogQuery::new(vec![self.address]).with_topics(events::topics_for(event, indexed)?))
     }
 
     pub async fn get_contract_code(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
@@ -150,18 +289,27 @@ impl SmartContract {
         Ok(accounts)
     }
 
+    // EIP-191 personal_sign; signatures come back as low-s r || s || v with v in {27, 28}
     pub async fn sign_message(&self, message: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let accounts = self.web3.eth().accounts().await?;
-        let signature = self.web3.accounts().sign(message, &web3::signing::SecretKeyRef::new(&[0; 32])).await?;
-        Ok(signature.to_vec())
+        let signature = self.signer()?.sign_message(message).await?;
+        Ok(Signature::from_bytes(&signature)?.normalized().to_bytes())
     }
 
+    // Accepts 65-byte and EIP-2098 compact signatures; contract signers are checked via ERC-1271
     pub async fn verify_signature(&self, message: &[u8], signature: &[u8], address: Address) -> Result<bool, Box<dyn std::error::Error>> {
-        let recovered_address = self.web3.accounts().recover(message, signature)?;
-        Ok(recovered_address == address)
+        signature::verify_message(&self.web3, address, message, signature).await
     }
 
-    pub async fn batch_call(&self, calls: Vec<(String, Vec<web3::types::Bytes>)>) -> Result<Vec<Vec<u8>>, Box<dyn std::error::Error>> {
+    pub async fn sign_typed_data(&self, typed_data: &TypedData) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let signature = self.signer()?.sign_typed_data(typed_data.domain_separator()?, typed_data.struct_hash()?).await?;
+        Ok(Signature::from_bytes(&signature)?.normalized().to_bytes())
+    }
+
+    pub async fn verify_typed_data(&self, typed_data: &TypedData, signature: &[u8], address: Address) -> Result<bool, Box<dyn std::error::Error>> {
+        signature::verify_hash(&self.web3, address, typed_data.digest()?, signature).await
+    }
+
+    pub async fn batch_call(&self, calls: Vec<(String, Vec<Token>)>) -> Result<Vec<Vec<Token>>, Box<dyn std::error::Error>> {
         let mut results = Vec::new();
         for (method, params) in calls {
             let result = self.call(&method, params).await?;
@@ -185,7 +333,7 @@ impl SmartContract {
     }
 
     pub async fn simulate_transaction(&self, tx: web3::types::TransactionRequest) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let result = self.web3.eth().call(tx, None).await?;
+        let result = self.web3.eth().call(tx, None).await.map_err(|e| self.revert_error(e))?;
         Ok(result.0)
     }
 
@@ -194,24 +342,6 @@ impl SmartContract {
         Ok(logs)
     }
 
-    pub async fn subscribe_to_events(&self, event_name: &str) -> Result<web3::api::SubscriptionStream<web3::transports::Http, web3::types::Log>, Box<dyn std::error::Error>> {
-        let event_signature = web3::contract::tokens::Event::new(event_name, vec![])?;
-        let topics = vec![Some(event_signature.signature())];
-
-        let filter = web3::types::FilterBuilder::default()
-            .address(vec![self.address])
-            .topics(Some(topics), None, None, None)
-            .build();
-
-        let subscription = self.web3.eth_subscribe().subscribe_logs(filter).await?;
-        Ok(subscription)
-    }
-
-    pub async fn unsubscribe(&self, subscription_id: U256) -> Result<bool, Box<dyn std::error::Error>> {
-        let result = self.web3.eth_unsubscribe().unsubscribe(subscription_id).await?;
-        Ok(result)
-    }
-
     pub async fn get_transaction_count(&self, address: Address) -> Result<U256, Box<dyn std::error::Error>> {
         let nonce = self.web3.eth().transaction_count(address, None).await?;
         Ok(nonce)
@@ -361,4 +491,20 @@ impl SmartContract {
         let messages = self.web3.shh().get_messages(filter_id).await?;
         Ok(messages)
     }
-}
\ No newline at end of file
+}
+
+impl<T: DuplexTransport> SmartContract<T> {
+    // Raw logs, since a subscription can outlive this contract handle; decode them with decode_log
+    pub async fn subscribe_to_events(&self, event: &str) -> Result<web3::api::SubscriptionStream<T, web3::types::Log>, Box<dyn std::error::Error>> {
+        let event = events::resolve_event(&self.abi, event)?;
+        let filter = self.event_query(&event, &[])?.filter_builder().build();
+
+        let subscription = self.web3.eth_subscribe().subscribe_logs(filter).await?;
+        Ok(subscription)
+    }
+
+    pub async fn unsubscribe(&self, subscription: web3::api::SubscriptionStream<T, web3::types::Log>) -> Result<bool, Box<dyn std::error::Error>> {
+        let result = subscription.unsubscribe().await?;
+        Ok(result)
+    }
+}
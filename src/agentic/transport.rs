@@ -0,0 +1,109 @@
+use std::sync::Arc;
+use web3::Web3;
+use web3::transports::{Either, Http, Ipc, WebSocket};
+
+// Any transport that supports eth_subscribe
+pub type DuplexAny = Either<WebSocket, Ipc>;
+
+// Any supported transport, picked from the endpoint at runtime
+pub type AnyTransport = Either<Http, DuplexAny>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportKind {
+    Http,
+    WebSocket,
+    Ipc,
+}
+
+impl TransportKind {
+    pub fn from_endpoint(endpoint: &str) -> Self {
+        let lower = endpoint.to_ascii_lowercase();
+        if lower.starts_with("http://") || lower.starts_with("https://") {
+            TransportKind::Http
+        } else if lower.starts_with("ws://") || lower.starts_with("wss://") {
+            TransportKind::WebSocket
+        } else {
+            // Anything else is a local socket path, e.g. ~/.ethereum/geth.ipc or anvil's --ipc path
+            TransportKind::Ipc
+        }
+    }
+
+    pub fn supports_subscriptions(&self) -> bool {
+        !matches!(self, TransportKind::Http)
+    }
+}
+
+pub async fn connect(endpoint: &str) -> Result<Web3<AnyTransport>, Box<dyn std::error::Error>> {
+    let transport = match TransportKind::from_endpoint(endpoint) {
+        TransportKind::Http => Either::Left(Http::new(endpoint)?),
+        TransportKind::WebSocket => Either::Right(Either::Left(WebSocket::new(endpoint).await?)),
+        TransportKind::Ipc => Either::Right(Either::Right(Ipc::new(endpoint).await?)),
+    };
+
+    Ok(Web3::new(transport))
+}
+
+pub async fn connect_duplex(endpoint: &str) -> Result<Web3<DuplexAny>, Box<dyn std::error::Error>> {
+    let transport = match TransportKind::from_endpoint(endpoint) {
+        TransportKind::Http => return Err(format!("{} does not support subscriptions, use a ws:// URL or an IPC path", endpoint).into()),
+        TransportKind::WebSocket => Either::Left(WebSocket::new(endpoint).await?),
+        TransportKind::Ipc => Either::Right(Ipc::new(endpoint).await?),
+    };
+
+    Ok(Web3::new(transport))
+}
+
+// A pair of connections to the same node: HTTP for calls and transactions, and an
+// optional WebSocket or IPC connection for eth_subscribe.
+#[derive(Debug, Clone)]
+pub struct Connections {
+    pub rpc: Arc<Web3<Http>>,
+    pub subscriptions: Option<Arc<Web3<DuplexAny>>>,
+}
+
+impl Connections {
+    pub async fn new(rpc_url: &str, subscription_endpoint: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        let rpc = Arc::new(Web3::new(Http::new(rpc_url)?));
+
+        let subscriptions = match subscription_endpoint {
+            Some(endpoint) => Some(Arc::new(connect_duplex(endpoint).await?)),
+            None => None,
+        };
+
+        Ok(Self { rpc, subscriptions })
+    }
+
+    pub fn subscriptions(&self) -> Result<Arc<Web3<DuplexAny>>, Box<dyn std::error::Error>> {
+        self.subscriptions.clone().ok_or_else(|| "No WebSocket or IPC endpoint configured for subscriptions".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_transport_from_the_endpoint() {
+        let cases = [
+            ("http://127.0.0.1:8545", TransportKind::Http),
+            ("HTTPS://mainnet.example.org/v3/key", TransportKind::Http),
+            ("ws://localhost:8546", TransportKind::WebSocket),
+            ("WSS://mainnet.example.org/ws", TransportKind::WebSocket),
+            ("/home/agent/.ethereum/geth.ipc", TransportKind::Ipc),
+            ("geth.ipc", TransportKind::Ipc),
+            // Without a scheme nothing says it is a URL
+            ("localhost:8545", TransportKind::Ipc),
+        ];
+
+        for (endpoint, expected) in cases {
+            assert_eq!(TransportKind::from_endpoint(endpoint), expected, "{}", endpoint);
+        }
+    }
+
+    #[test]
+    fn only_http_lacks_subscriptions() {
+        assert!(!TransportKind::Http.supports_subscriptions());
+        assert!(TransportKind::WebSocket.supports_subscriptions());
+        assert!(TransportKind::Ipc.supports_subscriptions());
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use web3::types::{Address, BlockId, BlockNumber, H256, U256, U64};
+use web3::transports::Http;
+use web3::{Transport, Web3};
+
+use super::nonce_manager::NonceManager;
+use super::wallet_manager::{TransactionRecord, TransactionStatus, Wallet};
+
+#[derive(Debug, Clone)]
+pub struct TrackerConfig {
+    pub confirmations: u64,
+    pub poll_interval: Duration,
+    // How long a transaction may be missing from the node before it counts as dropped
+    pub drop_timeout: Duration,
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        Self {
+            confirmations: 3,
+            poll_interval: Duration::from_secs(5),
+            drop_timeout: Duration::from_secs(600),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StatusChange {
+    pub hash: H256,
+    pub from: Address,
+    pub status: TransactionStatus,
+    pub confirmations: u64,
+    pub reorged: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PollReport {
+    pub changes: Vec<StatusChange>,
+    // Records whose check failed this round; they stay pending and are retried next poll
+    pub errors: Vec<(H256, String)>,
+}
+
+// Follows every pending transaction recorded by a WalletManager until it is final:
+// confirmed or failed after the configured number of confirmations, dropped from the
+// mempool, or replaced by another transaction at the same nonce.
+#[derive(Debug, Clone)]
+pub struct TransactionTracker<T: Transport = Http> {
+    web3: Arc<Web3<T>>,
+    wallets: Arc<Mutex<HashMap<Address, Wallet>>>,
+    nonce_manager: Arc<NonceManager>,
+    config: TrackerConfig,
+}
+
+impl<T: Transport + Send + Sync + 'static> TransactionTracker<T>
+where
+    T::Out: Send,
+{
+    // Failed polls and records are simply retried on the next tick; call poll_once
+    // directly to see the errors
+    pub fn spawn(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let _ = self.poll_once().await;
+                tokio::time::sleep(self.config.poll_interval).await;
+            }
+        })
+    }
+}
+
+impl<T: Transport> TransactionTracker<T> {
+    pub fn new(web3: Arc<Web3<T>>, wallets: Arc<Mutex<HashMap<Address, Wallet>>>, nonce_manager: Arc<NonceManager>, config: TrackerConfig) -> Self {
+        Self { web3, wallets, nonce_manager, config }
+    }
+
+    pub async fn poll_once(&self) -> Result<PollReport, Box<dyn std::error::Error + Send + Sync>> {
+        let pending: Vec<TransactionRecord> = {
+            let wallets = self.wallets.lock().await;
+            wallets.values()
+                .flat_map(|wallet| wallet.transactions.iter())
+                .filter(|record| record.status == TransactionStatus::Pending)
+                .cloned()
+                .collect()
+        };
+
+        if pending.is_empty() {
+            return Ok(PollReport::default());
+        }
+
+        let chain_id = self.web3.eth().chain_id().await?.as_u64();
+        let head = self.web3.eth().block_number().await?;
+
+        // One record's failed lookup must not hold up the others
+        let mut report = PollReport::default();
+        for record in pending {
+            match self.check(&record, head, chain_id).await {
+                Ok(Some(change)) => {
+                    self.apply(&change).await;
+                    report.changes.push(change);
+                }
+                Ok(None) => {}
+                Err(e) => report.errors.push((record.hash, e.to_string())),
+            }
+        }
+
+        Ok(report)
+    }
+
+    async fn check(&self, record: &TransactionRecord, head: U64, chain_id: u64) -> Result<Option<StatusChange>, Box<dyn std::error::Error + Send + Sync>> {
+        let receipt = self.web3.eth().transaction_receipt(record.hash).await?;
+
+        if let Some(receipt) = receipt {
+            let block_number = match receipt.block_number {
+                Some(block_number) => block_number,
+                None => return Ok(None),
+            };
+
+            // A receipt whose block is no longer canonical was reorged out
+            let canonical = self.web3.eth().block(BlockId::Number(BlockNumber::Number(block_number))).await?
+                .and_then(|block| block.hash);
+            if canonical != receipt.block_hash {
+                return Ok(Some(self.change(record, TransactionStatus::Pending, 0, true)));
+            }
+
+            let confirmations = head.saturating_sub(block_number).as_u64() + 1;
+            let reorged = record.block_hash.is_some() && record.block_hash != receipt.block_hash;
+
+            if confirmations < self.config.confirmations {
+                self.update_inclusion(record.hash, record.from, Some(block_number), receipt.block_hash, confirmations, receipt.gas_used).await;
+                return Ok(if reorged { Some(self.change(record, TransactionStatus::Pending, confirmations, true)) } else { None });
+            }
+
+            self.update_inclusion(record.hash, record.from, Some(block_number), receipt.block_hash, confirmations, receipt.gas_used).await;
+            if let Some(nonce) = record.nonce {
+                self.nonce_manager.mark_mined(chain_id, record.from, nonce).await;
+            }
+
+            let status = if receipt.status == Some(U64::one()) {
+                TransactionStatus::Confirmed
+            } else {
+                TransactionStatus::Failed
+            };
+            return Ok(Some(self.change(record, status, confirmations, reorged)));
+        }
+
+        // We had a receipt before and it's gone: reorged back into the mempool or out of it
+        if record.block_hash.is_some() {
+            self.update_inclusion(record.hash, record.from, None, None, 0, None).await;
+            return Ok(Some(self.change(record, TransactionStatus::Pending, 0, true)));
+        }
+
+        let known = self.web3.eth().transaction(record.hash.into()).await?.is_some();
+
+        // Another transaction was mined at this nonce
+        if let Some(nonce) = record.nonce {
+            let mined_nonce = self.web3.eth().transaction_count(record.from, Some(BlockNumber::Latest)).await?;
+            if mined_nonce > nonce && !known {
+                let status = if record.replaced_by.is_some() {
+                    TransactionStatus::Replaced
+                } else {
+                    TransactionStatus::Dropped
+                };
+                return Ok(Some(self.change(record, status, 0, false)));
+            }
+        }
+
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs();
+        let age = now.saturating_sub(record.timestamp.as_u64());
+        if !known && age > self.config.drop_timeout.as_secs() {
+            // Let the nonce manager hand the nonce out again
+            self.nonce_manager.resync(&self.web3, chain_id, record.from).await?;
+            return Ok(Some(self.change(record, TransactionStatus::Dropped, 0, false)));
+        }
+
+        Ok(None)
+    }
+
+    fn change(&self, record: &TransactionRecord, status: TransactionStatus, confirmations: u64, reorged: bool) -> StatusChange {
+        StatusChange {
+            hash: record.hash,
+            from: record.from,
+            status,
+            confirmations,
+            reorged,
+        }
+    }
+
+    async fn update_inclusion(&self, hash: H256, from: Address, block_number: Option<U64>, block_hash: Option<H256>, confirmations: u64, gas_used: Option<U256>) {
+        let mut wallets = self.wallets.lock().await;
+        if let Some(record) = find_record(&mut wallets, from, hash) {
+            record.block_number = block_number;
+            record.block_hash = block_hash;
+            record.confirmations = confirmations;
+            record.gas_used = gas_used;
+        }
+    }
+
+    async fn apply(&self, change: &StatusChange) {
+        let mut wallets = self.wallets.lock().await;
+        if let Some(record) = find_record(&mut wallets, change.from, change.hash) {
+            record.status = change.status.clone();
+            record.confirmations = change.confirmations;
+        }
+    }
+}
+
+fn find_record<'a>(wallets: &'a mut HashMap<Address, Wallet>, from: Address, hash: H256) -> Option<&'a mut TransactionRecord> {
+    wallets.get_mut(&from)?
+        .transactions
+        .iter_mut()
+        .find(|record| record.hash == hash)
+}
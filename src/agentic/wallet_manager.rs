@@ -1,21 +1,67 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use web3::types::{Address, U256, H256, Transaction, SignedTransaction};
+use web3::types::{AccessList, Address, U256, U64, H256, Transaction, SignedTransaction};
 use web3::Web3;
 use web3::transports::Http;
-use web3::signing::{Key, SecretKey, SecretKeyRef};
-use secp256k1::{Secp256k1, Message, ecdsa};
+use web3::Transport;
+use web3::signing::SecretKey;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct WalletManager {
-    web3: Arc<Web3<Http>>,
-    wallets: Arc<Mutex<HashMap<String, Wallet>>>,
-    keystore: Arc<Mutex<HashMap<String, EncryptedKey>>>,
+use web3::ethabi::Token;
+use zeroize::Zeroizing;
+
+use super::abi;
+use super::address;
+use super::address_book::{AddressBook, Contact, ContactTrust, Counterparty, Direction, RecipientCheck, RecipientWarning};
+use super::access_list::{self, AccessListResult};
+use super::eip712::TypedData;
+use super::ens::{EnsResolver, NameOrAddress};
+use super::backup_bundle::{self, BackupBundle, ConflictResolution, RestoreMode, RestoreReport};
+use super::fee_oracle::{FeeEstimate, FeeOracle, FeeSpeed};
+use super::hd_wallet::{self, DerivationPath, MnemonicLength};
+use super::kdf::KdfParams;
+use super::keystore_dir::{self, BackupContents, KeystoreDir, StoredKey};
+use super::keystore_v3::{KeystoreKdf, KeystoreV3};
+use super::nonce_manager::NonceManager;
+use super::secret::{PrivateKey, PrivateKeyRef, SecretBytes};
+use super::session::{SessionHandle, SessionInfo, SessionManager};
+use super::shamir::{self, SecretKind, Share};
+use super::signature;
+use super::signer::LocalSigner;
+use super::tx_tracker::{TrackerConfig, TransactionTracker};
+use super::smart_contract::SmartContract;
+
+// Replacement transactions pay 12.5% more than the one they replace
+const REPLACEMENT_FEE_BUMP_PER_MILLE: u64 = 1125;
+// Upper bound on how long an expired session key stays in memory
+const SESSION_REAPER_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+
+// Not Serialize and no derived Debug: the keystore and seeds are encrypted, but still
+// shouldn't leak through logs or an accidental serde_json::to_string
+#[derive(Clone)]
+pub struct WalletManager<T: Transport = Http> {
+    web3: Arc<Web3<T>>,
+    wallets: Arc<Mutex<HashMap<Address, Wallet>>>,
+    keystore: Arc<Mutex<HashMap<Address, EncryptedKey>>>,
+    seeds: Arc<Mutex<HashMap<String, EncryptedSeed>>>,
+    // Seed that generate_new_address derives from, the last one created or imported
+    default_seed: Arc<Mutex<Option<String>>>,
+    keystore_dir: Option<KeystoreDir>,
+    sessions: Arc<SessionManager>,
+    nonce_manager: Arc<NonceManager>,
+    fee_oracle: Arc<FeeOracle<T>>,
+    // Used for new entries; entries under weaker parameters are re-encrypted on unlock
+    kdf: KdfParams,
+    ens: EnsResolver<T>,
+    address_book: Arc<Mutex<AddressBook>>,
+    recipient_guard: Option<RecipientGuard>,
 }
 
+// Called with every send's recipient check; an Err aborts the send with that message.
+// This is where an agent policy layer decides what to do about unknown counterparties.
+pub type RecipientGuard = Arc<dyn Fn(&RecipientCheck) -> Result<(), String> + Send + Sync>;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Wallet {
     pub address: Address,
@@ -24,6 +70,10 @@ pub struct Wallet {
     pub nonce: U256,
     pub tokens: HashMap<Address, U256>,
     pub transactions: Vec<TransactionRecord>,
+    #[serde(default)]
+    pub seed_id: Option<String>,
+    #[serde(default)]
+    pub derivation_path: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,14 +85,56 @@ pub struct TransactionRecord {
     pub timestamp: U256,
     pub status: TransactionStatus,
     pub gas_used: Option<U256>,
-    pub gas_price: U256,
+    #[serde(alias = "gas_price")]
+    pub max_fee_per_gas: U256,
+    #[serde(default)]
+    pub max_priority_fee_per_gas: Option<U256>,
+    #[serde(default)]
+    pub nonce: Option<U256>,
+    #[serde(default)]
+    pub gas_limit: Option<U256>,
+    #[serde(default)]
+    pub data: Option<Vec<u8>>,
+    #[serde(default)]
+    pub block_number: Option<U64>,
+    #[serde(default)]
+    pub block_hash: Option<H256>,
+    #[serde(default)]
+    pub confirmations: u64,
+    #[serde(default)]
+    pub replaced_by: Option<H256>,
+    #[serde(default)]
+    pub access_list: Option<AccessList>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl Wallet {
+    // Folds in records another process wrote for this wallet. Known hashes keep our copy
+    // unless theirs has reached a final status we haven't seen yet.
+    pub fn merge_transactions(&mut self, others: &[TransactionRecord]) {
+        for other in others {
+            match self.transactions.iter_mut().find(|record| record.hash == other.hash) {
+                Some(record) => {
+                    if record.status == TransactionStatus::Pending && other.status != TransactionStatus::Pending {
+                        *record = other.clone();
+                    }
+                    if record.replaced_by.is_none() {
+                        record.replaced_by = other.replaced_by;
+                    }
+                }
+                None => self.transactions.push(other.clone()),
+            }
+        }
+        self.transactions.sort_by_key(|record| record.timestamp);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TransactionStatus {
     Pending,
     Confirmed,
     Failed,
+    Dropped,
+    Replaced,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -51,6 +143,18 @@ pub struct EncryptedKey {
     pub encrypted_private_key: Vec<u8>,
     pub salt: Vec<u8>,
     pub iv: Vec<u8>,
+    #[serde(default = "KdfParams::legacy")]
+    pub kdf: KdfParams,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedSeed {
+    pub seed_id: String,
+    pub encrypted_seed: Vec<u8>,
+    pub salt: Vec<u8>,
+    pub iv: Vec<u8>,
+    #[serde(default = "KdfParams::legacy")]
+    pub kdf: KdfParams,
 }
 
 #[derive(Debug, Clone)]
@@ -58,9 +162,15 @@ pub struct TransactionRequest {
     pub to: Address,
     pub value: U256,
     pub gas_limit: Option<U256>,
-    pub gas_price: Option<U256>,
+    // Explicit fees override the oracle's estimate for `fee_speed`
+    pub max_fee_per_gas: Option<U256>,
+    pub max_priority_fee_per_gas: Option<U256>,
+    pub fee_speed: FeeSpeed,
     pub data: Option<Vec<u8>>,
     pub nonce: Option<U256>,
+    pub access_list: Option<AccessList>,
+    // Generate an access list with eth_createAccessList and attach it when it lowers gas
+    pub create_access_list: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -72,46 +182,253 @@ pub struct TokenBalance {
     pub name: String,
 }
 
-impl WalletManager {
-    pub fn new(web3: Arc<Web3<Http>>) -> Self {
+impl<T: Transport> std::fmt::Debug for WalletManager<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WalletManager")
+            .field("keystore_dir", &self.keystore_dir)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Transport> WalletManager<T> {
+    // `nonce_manager` and `fee_oracle` are shared with DeFiProtocol and NFTMarketplace sends
+    // from the same accounts. Inside a tokio runtime this also starts the session reaper;
+    // outside one, call `sessions().spawn_reaper(..)` once a runtime is available.
+    pub fn new(web3: Arc<Web3<T>>, nonce_manager: Arc<NonceManager>, fee_oracle: Arc<FeeOracle<T>>) -> Self {
+        let sessions = Arc::new(SessionManager::new());
+        if tokio::runtime::Handle::try_current().is_ok() {
+            sessions.clone().spawn_reaper(SESSION_REAPER_INTERVAL);
+        }
+
         Self {
+            kdf: KdfParams::recommended(),
+            ens: EnsResolver::new(web3.clone()),
+            address_book: Arc::new(Mutex::new(AddressBook::new())),
+            recipient_guard: None,
             web3,
             wallets: Arc::new(Mutex::new(HashMap::new())),
             keystore: Arc::new(Mutex::new(HashMap::new())),
+            seeds: Arc::new(Mutex::new(HashMap::new())),
+            default_seed: Arc::new(Mutex::new(None)),
+            keystore_dir: None,
+            sessions,
+            nonce_manager,
+            fee_oracle,
         }
     }
 
+    // A manager backed by a keystore directory: every key change is written through to disk
+    pub async fn open(web3: Arc<Web3<T>>, nonce_manager: Arc<NonceManager>, fee_oracle: Arc<FeeOracle<T>>, keystore_path: impl AsRef<std::path::Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut manager = Self::new(web3, nonce_manager, fee_oracle);
+        manager.keystore_dir = Some(KeystoreDir::open(keystore_path)?);
+        manager.reload().await?;
+        Ok(manager)
+    }
+
+    // Picks up keys and seeds other processes have written to the keystore directory
+    pub async fn reload(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let dir = match &self.keystore_dir {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+        let stored_keys = dir.load_keys()?;
+        let stored_seeds = dir.load_seeds()?;
+
+        // Keys whose files are gone were deleted by another process
+        let removed: Vec<Address> = {
+            let mut wallets = self.wallets.lock().await;
+            let mut keystore = self.keystore.lock().await;
+            let on_disk: HashSet<Address> = stored_keys.iter().map(|stored| stored.wallet.address).collect();
+            let removed = keystore.keys().filter(|address| !on_disk.contains(address)).cloned().collect();
+            wallets.retain(|address, _| on_disk.contains(address));
+            keystore.retain(|address, _| on_disk.contains(address));
+
+            for stored in stored_keys {
+                let address = stored.wallet.address;
+                wallets.insert(address, stored.wallet);
+                keystore.insert(address, stored.key);
+            }
+            removed
+        };
+        for address in removed {
+            self.sessions.lock_address(address).await;
+        }
+
+        let mut seeds = self.seeds.lock().await;
+        seeds.clear();
+        for stored in stored_seeds {
+            seeds.insert(stored.seed.seed_id.clone(), stored.seed);
+        }
+
+        *self.address_book.lock().await = dir.load_address_book()?;
+
+        Ok(())
+    }
+
+    pub fn with_kdf(mut self, kdf: KdfParams) -> Self {
+        self.kdf = kdf;
+        self
+    }
+
+    pub fn kdf(&self) -> KdfParams {
+        self.kdf
+    }
+
+    pub fn with_ens_registry(mut self, registry: Address) -> Self {
+        self.ens = self.ens.with_registry(registry);
+        self
+    }
+
+    pub fn with_recipient_guard(mut self, guard: RecipientGuard) -> Self {
+        self.recipient_guard = Some(guard);
+        self
+    }
+
+    pub fn nonce_manager(&self) -> Arc<NonceManager> {
+        self.nonce_manager.clone()
+    }
+
+    // Pass to DeFiProtocol and NFTMarketplace so the fee cap covers their sends too
+    pub fn fee_oracle(&self) -> Arc<FeeOracle<T>> {
+        self.fee_oracle.clone()
+    }
+
+    pub fn set_fee_cap(&self, fee_cap: Option<U256>) {
+        self.fee_oracle.set_fee_cap(fee_cap);
+    }
+
     pub async fn create_wallet(&self, name: &str, password: &str) -> Result<Address, Box<dyn std::error::Error>> {
-        let secret_key = SecretKey::new(&mut rand::thread_rng());
-        let public_key = secret_key.public_key(&Secp256k1::new());
-        let address = public_key_to_address(&public_key);
+        let private_key = PrivateKey::from_secret_key(&SecretKey::new(&mut rand::thread_rng()));
+        self.insert_key(&private_key, name, password, None).await
+    }
 
-        let encrypted_key = self.encrypt_private_key(&secret_key, password)?;
+    // Creates a seed and its first account at m/44'/60'/0'/0/0. The mnemonic is returned
+    // once and not stored; write it down, it recreates every account derived from it.
+    pub async fn create_hd_wallet(&self, name: &str, password: &str, length: MnemonicLength, passphrase: &str) -> Result<(String, Address), Box<dyn std::error::Error>> {
+        let mnemonic = hd_wallet::generate_mnemonic(length)?;
+        let address = self.import_mnemonic(&mnemonic, passphrase, name, password).await?;
+        Ok((mnemonic, address))
+    }
 
-        let wallet = Wallet {
-            address,
-            name: name.to_string(),
-            balance: U256::zero(),
-            nonce: U256::zero(),
-            tokens: HashMap::new(),
-            transactions: Vec::new(),
+    pub async fn import_mnemonic(&self, phrase: &str, passphrase: &str, name: &str, password: &str) -> Result<Address, Box<dyn std::error::Error>> {
+        let seed_id = self.store_seed(phrase, passphrase, password).await?;
+        self.derive_account(&seed_id, &DerivationPath::ethereum(0), name, password).await
+    }
+
+    // Re-derives `count` standard accounts from a mnemonic, e.g. an agent fleet's sub-accounts
+    pub async fn recover_accounts(&self, phrase: &str, passphrase: &str, count: u32, name_prefix: &str, password: &str) -> Result<Vec<Address>, Box<dyn std::error::Error>> {
+        let seed_id = self.store_seed(phrase, passphrase, password).await?;
+
+        let mut addresses = Vec::new();
+        for index in 0..count {
+            let name = format!("{} {}", name_prefix, index);
+            addresses.push(self.derive_account(&seed_id, &DerivationPath::ethereum(index), &name, password).await?);
+        }
+        Ok(addresses)
+    }
+
+    pub async fn derive_account(&self, seed_id: &str, path: &DerivationPath, name: &str, password: &str) -> Result<Address, Box<dyn std::error::Error>> {
+        let seed = self.unlock_seed(seed_id, password).await?;
+        let private_key = PrivateKey::from_secret_key(&hd_wallet::derive_key(seed.expose(), path)?);
+        self.insert_key(&private_key, name, password, Some((seed_id, path))).await
+    }
+
+    // Derives the account after the highest standard index already derived from this seed
+    pub async fn derive_next_account(&self, seed_id: &str, name: &str, password: &str) -> Result<Address, Box<dyn std::error::Error>> {
+        let next_index = {
+            let wallets = self.wallets.lock().await;
+            wallets.values()
+                .filter(|wallet| wallet.seed_id.as_deref() == Some(seed_id))
+                .filter_map(|wallet| wallet.derivation_path.as_ref()?.parse::<DerivationPath>().ok()?.ethereum_index())
+                .max()
+                .map(|index| index + 1)
+                .unwrap_or(0)
         };
 
-        let mut wallets = self.wallets.lock().await;
-        let mut keystore = self.keystore.lock().await;
+        self.derive_account(seed_id, &DerivationPath::ethereum(next_index), name, password).await
+    }
 
-        wallets.insert(address.to_string(), wallet);
-        keystore.insert(address.to_string(), encrypted_key);
+    // Splits a stored seed into `count` shares, any `threshold` of which restore it
+    pub async fn split_seed(&self, seed_id: &str, password: &str, threshold: u8, count: u8) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let seed = self.unlock_seed(seed_id, password).await?;
+        let shares = shamir::split_secret(seed.expose(), SecretKind::Seed, threshold, count)?;
+        Ok(shares.iter().map(|share| share.to_words()).collect())
+    }
 
-        Ok(address)
+    pub async fn split_private_key(&self, address: Address, password: &str, threshold: u8, count: u8) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let private_key = self.unlock_wallet(address, password).await?;
+        let shares = shamir::split_secret(private_key.expose_bytes(), SecretKind::PrivateKey, threshold, count)?;
+        Ok(shares.iter().map(|share| share.to_words()).collect())
+    }
+
+    // Rebuilds a split seed or key; a seed comes back with its first standard account derived
+    pub async fn recover_from_shares(&self, shares: &[String], name: &str, password: &str) -> Result<Address, Box<dyn std::error::Error>> {
+        let shares = shares.iter().map(|words| Share::from_words(words)).collect::<Result<Vec<_>, _>>()?;
+        let (kind, secret) = shamir::combine_shares(&shares)?;
+        let secret = SecretBytes::new(secret);
+
+        match kind {
+            SecretKind::Seed => {
+                let seed_id = self.store_seed_bytes(secret.expose(), password).await?;
+                self.derive_account(&seed_id, &DerivationPath::ethereum(0), name, password).await
+            }
+            SecretKind::PrivateKey => self.insert_key(&PrivateKey::from_slice(secret.expose())?, name, password, None).await,
+        }
+    }
+
+    async fn store_seed(&self, phrase: &str, passphrase: &str, password: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let seed = Zeroizing::new(hd_wallet::seed_from_mnemonic(phrase, passphrase)?);
+        self.store_seed_bytes(seed.as_ref(), password).await
+    }
+
+    async fn store_seed_bytes(&self, seed: &[u8], password: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let seed_id = hd_wallet::seed_id(seed)?;
+
+        let encrypted_seed = self.encrypt_seed(&seed_id, seed, password)?;
+        if let Some(dir) = &self.keystore_dir {
+            dir.save_seed(&encrypted_seed)?;
+        }
+        self.seeds.lock().await.insert(seed_id.clone(), encrypted_seed);
+        *self.default_seed.lock().await = Some(seed_id.clone());
+
+        Ok(seed_id)
+    }
+
+    async fn unlock_seed(&self, seed_id: &str, password: &str) -> Result<SecretBytes, Box<dyn std::error::Error>> {
+        let encrypted_seed = self.seeds.lock().await.get(seed_id).cloned().ok_or("Seed not found")?;
+        let seed = self.decrypt_secret(&encrypted_seed.encrypted_seed, &encrypted_seed.salt, &encrypted_seed.iv, &encrypted_seed.kdf, password)?;
+
+        if encrypted_seed.kdf.is_outdated(&self.kdf) {
+            let upgraded = self.encrypt_seed(seed_id, seed.expose(), password)?;
+            if let Some(dir) = &self.keystore_dir {
+                dir.save_seed(&upgraded)?;
+            }
+            self.seeds.lock().await.insert(seed_id.to_string(), upgraded);
+        }
+
+        Ok(seed)
     }
 
     pub async fn import_wallet(&self, private_key_hex: &str, name: &str, password: &str) -> Result<Address, Box<dyn std::error::Error>> {
-        let secret_key = SecretKey::from_slice(&hex::decode(private_key_hex)?)?;
-        let public_key = secret_key.public_key(&Secp256k1::new());
-        let address = public_key_to_address(&public_key);
+        let key_bytes = Zeroizing::new(hex::decode(private_key_hex.trim_start_matches("0x"))?);
+        let private_key = PrivateKey::from_slice(&key_bytes)?;
+        self.insert_key(&private_key, name, password, None).await
+    }
 
-        let encrypted_key = self.encrypt_private_key(&secret_key, password)?;
+    // Imports a Web3 Secret Storage (keystore v3) file, e.g. from geth, MetaMask or foundry
+    pub async fn import_keystore(&self, keystore_json: &str, keystore_password: &str, name: &str, password: &str) -> Result<Address, Box<dyn std::error::Error>> {
+        let private_key = KeystoreV3::from_json(keystore_json)?.decrypt(keystore_password)?;
+        self.insert_key(&private_key, name, password, None).await
+    }
+
+    pub async fn export_keystore(&self, address: Address, password: &str, export_password: &str, kdf: KeystoreKdf) -> Result<String, Box<dyn std::error::Error>> {
+        let private_key = self.unlock_wallet(address, password).await?;
+        KeystoreV3::encrypt(&private_key, export_password, kdf)?.to_json()
+    }
+
+    async fn insert_key(&self, private_key: &PrivateKey, name: &str, password: &str, derivation: Option<(&str, &DerivationPath)>) -> Result<Address, Box<dyn std::error::Error>> {
+        let address = private_key.address();
+        let encrypted_key = self.encrypt_private_key(private_key, password)?;
 
         let wallet = Wallet {
             address,
@@ -120,28 +437,79 @@ impl WalletManager {
             nonce: U256::zero(),
             tokens: HashMap::new(),
             transactions: Vec::new(),
+            seed_id: derivation.map(|(seed_id, _)| seed_id.to_string()),
+            derivation_path: derivation.map(|(_, path)| path.to_string()),
+        };
+
+        // Another process may already hold this key; its history is kept
+        let wallet = match &self.keystore_dir {
+            Some(dir) => dir.update_key(address, |current| {
+                let mut wallet = wallet;
+                if let Some(current) = current {
+                    wallet.merge_transactions(&current.wallet.transactions);
+                }
+                StoredKey { schema_version: keystore_dir::SCHEMA_VERSION, wallet, key: encrypted_key.clone() }
+            })?.wallet,
+            None => wallet,
         };
 
         let mut wallets = self.wallets.lock().await;
         let mut keystore = self.keystore.lock().await;
 
-        wallets.insert(address.to_string(), wallet);
-        keystore.insert(address.to_string(), encrypted_key);
+        wallets.insert(address, wallet);
+        keystore.insert(address, encrypted_key);
 
         Ok(address)
     }
 
-    pub async fn unlock_wallet(&self, address: Address, password: &str) -> Result<SecretKey, Box<dyn std::error::Error>> {
-        let keystore = self.keystore.lock().await;
-        let encrypted_key = keystore.get(&address.to_string())
+    pub async fn unlock_wallet(&self, address: Address, password: &str) -> Result<PrivateKey, Box<dyn std::error::Error>> {
+        let encrypted_key = self.keystore.lock().await.get(&address).cloned()
             .ok_or("Wallet not found")?;
 
-        self.decrypt_private_key(encrypted_key, password)
+        let private_key = self.decrypt_private_key(&encrypted_key, password)?;
+
+        // The password is at hand only now, so this is when old entries get upgraded
+        if encrypted_key.kdf.is_outdated(&self.kdf) {
+            let upgraded = self.encrypt_private_key(&private_key, password)?;
+            self.keystore.lock().await.insert(address, upgraded);
+            self.persist(address).await?;
+        }
+
+        Ok(private_key)
+    }
+
+    // A signer for SmartContract, DeFiProtocol or NFTMarketplace backed by this wallet's key
+    pub async fn signer(&self, address: Address, password: &str) -> Result<LocalSigner<T>, Box<dyn std::error::Error>> {
+        let private_key = self.unlock_wallet(address, password).await?;
+        Ok(LocalSigner::new(self.web3.clone(), private_key))
+    }
+
+    // Decrypts the key once and keeps it for `ttl`; signing calls take the handle instead
+    // of a password
+    pub async fn unlock(&self, address: Address, password: &str, ttl: std::time::Duration) -> Result<SessionHandle, Box<dyn std::error::Error>> {
+        let private_key = self.unlock_wallet(address, password).await?;
+        self.sessions.open(private_key, ttl).await
+    }
+
+    pub async fn lock(&self, session: &SessionHandle) -> bool {
+        self.sessions.lock(session).await
+    }
+
+    pub async fn lock_all(&self) {
+        self.sessions.lock_all().await
+    }
+
+    pub async fn active_sessions(&self) -> Vec<SessionInfo> {
+        self.sessions.active_sessions().await
+    }
+
+    pub fn sessions(&self) -> Arc<SessionManager> {
+        self.sessions.clone()
     }
 
     pub async fn get_wallet(&self, address: Address) -> Result<Wallet, Box<dyn std::error::Error>> {
         let wallets = self.wallets.lock().await;
-        wallets.get(&address.to_string())
+        wallets.get(&address)
             .cloned()
             .ok_or("Wallet not found".into())
     }
@@ -151,7 +519,7 @@ impl WalletManager {
         let nonce = self.web3.eth().transaction_count(address, None).await?;
 
         let mut wallets = self.wallets.lock().await;
-        if let Some(wallet) = wallets.get_mut(&address.to_string()) {
+        if let Some(wallet) = wallets.get_mut(&address) {
             wallet.balance = balance;
             wallet.nonce = nonce;
         }
@@ -159,62 +527,55 @@ impl WalletManager {
         Ok(())
     }
 
-    pub async fn send_transaction(&self, from: Address, to: Address, value: U256, password: &str) -> Result<H256, Box<dyn std::error::Error>> {
-        let secret_key = self.unlock_wallet(from, password).await?;
-        let wallet = self.get_wallet(from).await?;
+    pub async fn send_transaction(&self, session: &SessionHandle, to: NameOrAddress, value: U256) -> Result<H256, Box<dyn std::error::Error>> {
+        let to = self.ens.resolve(&to).await?;
+        self.guard_recipient(to).await?;
+        let key = self.sessions.key(session).await?;
+        let from = key.address();
+        self.get_wallet(from).await?;
 
         let tx_request = TransactionRequest {
             to,
             value,
             gas_limit: Some(U256::from(21000)),
-            gas_price: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            fee_speed: FeeSpeed::Normal,
             data: None,
-            nonce: Some(wallet.nonce),
+            nonce: None,
+            access_list: None,
+            create_access_list: false,
         };
 
-        let tx_hash = self.sign_and_send_transaction(tx_request, &secret_key).await?;
-
-        // Record the transaction
-        let transaction_record = TransactionRecord {
-            hash: tx_hash,
-            from,
-            to: Some(to),
-            value,
-            timestamp: U256::from(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs()),
-            status: TransactionStatus::Pending,
-            gas_used: None,
-            gas_price: self.web3.eth().gas_price().await?,
-        };
-
-        let mut wallets = self.wallets.lock().await;
-        if let Some(wallet) = wallets.get_mut(&from.to_string()) {
-            wallet.transactions.push(transaction_record);
-            wallet.nonce += U256::one();
-        }
+        let tx_hash = self.sign_and_send_transaction(tx_request, &key).await?;
 
         Ok(tx_hash)
     }
 
-    pub async fn send_token(&self, from: Address, to: Address, token_address: Address, amount: U256, password: &str) -> Result<H256, Box<dyn std::error::Error>> {
-        let secret_key = self.unlock_wallet(from, password).await?;
+    pub async fn send_token(&self, session: &SessionHandle, to: NameOrAddress, token_address: Address, amount: U256) -> Result<H256, Box<dyn std::error::Error>> {
+        let to = self.ens.resolve(&to).await?;
+        self.guard_recipient(to).await?;
+        let key = self.sessions.key(session).await?;
+        let from = key.address();
 
         // ERC-20 transfer function call
-        let transfer_signature = "transfer(address,uint256)";
-        let transfer_selector = web3::contract::tokens::Function::new(transfer_signature, vec![], vec![], false)?.encode_input(vec![
-            self.encode_address(to),
-            self.encode_uint256(amount),
-        ])?;
+        let token = SmartContract::read_only(token_address, abi::ERC20_ABI.as_bytes().to_vec(), self.web3.clone())?;
+        let transfer_data = token.encode_call("transfer", &[Token::Address(to), Token::Uint(amount)])?;
 
         let tx_request = TransactionRequest {
             to: token_address,
             value: U256::zero(),
             gas_limit: Some(U256::from(100000)),
-            gas_price: None,
-            data: Some(transfer_selector),
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            fee_speed: FeeSpeed::Normal,
+            data: Some(transfer_data),
             nonce: None,
+            access_list: None,
+            create_access_list: false,
         };
 
-        let tx_hash = self.sign_and_send_transaction(tx_request, &secret_key).await?;
+        let tx_hash = self.sign_and_send_transaction(tx_request, &key).await?;
 
         // Update token balance
         self.update_token_balance(from, token_address).await?;
@@ -222,31 +583,40 @@ impl WalletManager {
         Ok(tx_hash)
     }
 
-    pub async fn sign_message(&self, address: Address, message: &str, password: &str) -> Result<String, Box<dyn std::error::Error>> {
-        let secret_key = self.unlock_wallet(address, password).await?;
-        let message_hash = web3::signing::hash_message(message.as_bytes());
-        let signature = self.web3.accounts().sign(&message_hash, &SecretKeyRef::new(&secret_key)).await?;
-        Ok(format!("0x{}", hex::encode(signature.to_vec())))
+    // EIP-191 personal_sign; returns the 0x-hex low-s r || s || v signature
+    pub async fn sign_message(&self, session: &SessionHandle, message: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
+        let key = self.sessions.key(session).await?;
+        Ok(signature::sign_hash(&key, signature::eip191_hash(message))?.to_hex())
+    }
+
+    // EIP-712 typed data, e.g. a permit or marketplace order
+    pub async fn sign_typed_data(&self, session: &SessionHandle, typed_data: &TypedData) -> Result<String, Box<dyn std::error::Error>> {
+        let key = self.sessions.key(session).await?;
+        Ok(signature::sign_hash(&key, typed_data.digest()?)?.to_hex())
+    }
+
+    pub async fn verify_typed_data(&self, typed_data: &TypedData, signature: &str, address: Address) -> Result<bool, Box<dyn std::error::Error>> {
+        let signature_bytes = hex::decode(signature.trim().trim_start_matches("0x"))?;
+        signature::verify_hash(&self.web3, address, typed_data.digest()?, &signature_bytes).await
     }
 
-    pub async fn verify_signature(&self, message: &str, signature: &str, address: Address) -> Result<bool, Box<dyn std::error::Error>> {
-        let message_hash = web3::signing::hash_message(message.as_bytes());
-        let signature_bytes = hex::decode(&signature[2..])?;
-        let recovered_address = self.web3.accounts().recover(&message_hash, &signature_bytes)?;
-        Ok(recovered_address == address)
+    // Takes 65-byte or EIP-2098 compact signatures; contract wallets are checked via ERC-1271
+    pub async fn verify_signature(&self, message: &[u8], signature: &str, address: Address) -> Result<bool, Box<dyn std::error::Error>> {
+        let signature_bytes = hex::decode(signature.trim().trim_start_matches("0x"))?;
+        signature::verify_message(&self.web3, address, message, &signature_bytes).await
     }
 
     pub async fn get_token_balance(&self, address: Address, token_address: Address) -> Result<U256, Box<dyn std::error::Error>> {
-        let contract = super::smart_contract::SmartContract::new(token_address, vec![], self.web3.clone());
-        let balance_data = contract.call("balanceOf", vec![self.encode_address(address)]).await?;
-        Ok(self.decode_uint256(&balance_data))
+        let contract = SmartContract::read_only(token_address, abi::ERC20_ABI.as_bytes().to_vec(), self.web3.clone())?;
+        let balance = contract.call("balanceOf", vec![Token::Address(address)]).await?;
+        abi::uint_at(&balance, 0)
     }
 
     pub async fn update_token_balance(&self, address: Address, token_address: Address) -> Result<(), Box<dyn std::error::Error>> {
         let balance = self.get_token_balance(address, token_address).await?;
 
         let mut wallets = self.wallets.lock().await;
-        if let Some(wallet) = wallets.get_mut(&address.to_string()) {
+        if let Some(wallet) = wallets.get_mut(&address) {
             wallet.tokens.insert(token_address, balance);
         }
 
@@ -254,18 +624,11 @@ impl WalletManager {
     }
 
     pub async fn get_token_info(&self, token_address: Address) -> Result<TokenBalance, Box<dyn std::error::Error>> {
-        let contract = super::smart_contract::SmartContract::new(token_address, vec![], self.web3.clone());
-
-        let name_data = contract.call("name", vec![]).await?;
-        let name = String::from_utf8_lossy(&name_data).trim_matches('\0').to_string();
-
-        let symbol_data = contract.call("symbol", vec![]).await?;
-        let symbol = String::from_utf8_lossy(&symbol_data).trim_matches('\0').to_string();
-
-        let decimals_data = contract.call("decimals", vec![]).await?;
-        let decimals = self.decode_uint256(&decimals_data).as_u64() as u8;
+        let contract = SmartContract::read_only(token_address, abi::ERC20_ABI.as_bytes().to_vec(), self.web3.clone())?;
 
-        let total_supply = self.decode_uint256(&contract.call("totalSupply", vec![]).await?);
+        let name = abi::string_at(&contract.call("name", vec![]).await?, 0)?;
+        let symbol = abi::string_at(&contract.call("symbol", vec![]).await?, 0)?;
+        let decimals = abi::uint_at(&contract.call("decimals", vec![]).await?, 0)?.as_u64() as u8;
 
         Ok(TokenBalance {
             token_address,
@@ -280,21 +643,25 @@ impl WalletManager {
         let balance = self.get_token_balance(wallet_address, token_address).await?;
         let token_info = self.get_token_info(token_address).await?;
 
-        let mut wallets = self.wallets.lock().await;
-        if let Some(wallet) = wallets.get_mut(&wallet_address.to_string()) {
-            wallet.tokens.insert(token_address, balance);
+        {
+            let mut wallets = self.wallets.lock().await;
+            if let Some(wallet) = wallets.get_mut(&wallet_address) {
+                wallet.tokens.insert(token_address, balance);
+            }
         }
 
-        Ok(())
+        self.persist(wallet_address).await
     }
 
     pub async fn remove_token(&self, wallet_address: Address, token_address: Address) -> Result<(), Box<dyn std::error::Error>> {
-        let mut wallets = self.wallets.lock().await;
-        if let Some(wallet) = wallets.get_mut(&wallet_address.to_string()) {
-            wallet.tokens.remove(&token_address);
+        {
+            let mut wallets = self.wallets.lock().await;
+            if let Some(wallet) = wallets.get_mut(&wallet_address) {
+                wallet.tokens.remove(&token_address);
+            }
         }
 
-        Ok(())
+        self.persist(wallet_address).await
     }
 
     pub async fn get_transaction_history(&self, address: Address) -> Result<Vec<TransactionRecord>, Box<dyn std::error::Error>> {
@@ -302,15 +669,139 @@ impl WalletManager {
         Ok(wallet.transactions)
     }
 
+    // History with each counterparty rendered by contact label or wallet name
+    pub async fn get_labelled_history(&self, address: Address) -> Result<Vec<(TransactionRecord, Counterparty)>, Box<dyn std::error::Error>> {
+        let chain_id = self.web3.eth().chain_id().await?.as_u64();
+        let wallets = self.wallets.lock().await;
+        let wallet = wallets.get(&address).ok_or("Wallet not found")?;
+        let address_book = self.address_book.lock().await;
+
+        let label = |counterparty: Address| {
+            address_book.label_for(chain_id, counterparty).map(|label| label.to_string())
+                .or_else(|| wallets.get(&counterparty).map(|wallet| wallet.name.clone()))
+        };
+
+        Ok(wallet.transactions.iter().map(|record| {
+            let (counterparty, direction) = if record.from != address {
+                (Some(record.from), Direction::Incoming)
+            } else if record.to == Some(address) {
+                (Some(address), Direction::SelfTransfer)
+            } else {
+                (record.to, Direction::Outgoing)
+            };
+            let counterparty = Counterparty {
+                address: counterparty,
+                label: counterparty.and_then(|counterparty| label(counterparty)),
+                direction,
+            };
+            (record.clone(), counterparty)
+        }).collect())
+    }
+
+    pub async fn add_contact(&self, contact: Contact) -> Result<(), Box<dyn std::error::Error>> {
+        let mut address_book = self.address_book.lock().await;
+        address_book.insert(contact)?;
+        self.persist_address_book(&address_book)
+    }
+
+    pub async fn remove_contact(&self, label: &str) -> Result<Option<Contact>, Box<dyn std::error::Error>> {
+        let mut address_book = self.address_book.lock().await;
+        let removed = address_book.remove(label);
+        self.persist_address_book(&address_book)?;
+        Ok(removed)
+    }
+
+    pub async fn set_contact_trust(&self, label: &str, trust: ContactTrust) -> Result<(), Box<dyn std::error::Error>> {
+        let mut address_book = self.address_book.lock().await;
+        address_book.get_mut(label).ok_or("Contact not found")?.trust = trust;
+        self.persist_address_book(&address_book)
+    }
+
+    pub async fn contacts(&self) -> Vec<Contact> {
+        self.address_book.lock().await.contacts().cloned().collect()
+    }
+
+    pub async fn find_contact(&self, address: Address) -> Result<Option<Contact>, Box<dyn std::error::Error>> {
+        let chain_id = self.web3.eth().chain_id().await?.as_u64();
+        Ok(self.address_book.lock().await.find(chain_id, address).cloned())
+    }
+
+    // What the manager knows about a recipient on the current chain, with warnings for
+    // counterparties nobody has vetted
+    pub async fn check_recipient(&self, to: Address) -> Result<RecipientCheck, Box<dyn std::error::Error>> {
+        let chain_id = self.web3.eth().chain_id().await?.as_u64();
+        let contact = self.address_book.lock().await.find(chain_id, to).cloned();
+
+        let (own_wallet, previously_sent) = {
+            let wallets = self.wallets.lock().await;
+            let previously_sent = wallets.values()
+                .flat_map(|wallet| wallet.transactions.iter())
+                .any(|record| record.to == Some(to));
+            (wallets.contains_key(&to), previously_sent)
+        };
+
+        let mut warnings = Vec::new();
+        match contact.as_ref().map(|contact| contact.trust) {
+            Some(ContactTrust::New) => warnings.push(RecipientWarning::UnvettedContact),
+            Some(_) => {}
+            None if !own_wallet && !previously_sent => warnings.push(RecipientWarning::NeverSeen),
+            None => {}
+        }
+
+        Ok(RecipientCheck {
+            address: to,
+            chain_id,
+            label: contact.as_ref().map(|contact| contact.label.clone()),
+            trust: contact.map(|contact| contact.trust),
+            own_wallet,
+            previously_sent,
+            warnings,
+        })
+    }
+
+    async fn guard_recipient(&self, to: Address) -> Result<(), Box<dyn std::error::Error>> {
+        let guard = match &self.recipient_guard {
+            Some(guard) => guard,
+            None => return Ok(()),
+        };
+        let check = self.check_recipient(to).await?;
+        guard(&check).map_err(|reason| format!("Send to {} blocked: {}", address::to_checksum(&to), reason).into())
+    }
+
+    fn persist_address_book(&self, address_book: &AddressBook) -> Result<(), Box<dyn std::error::Error>> {
+        match &self.keystore_dir {
+            Some(dir) => dir.save_address_book(address_book),
+            None => Ok(()),
+        }
+    }
+
+    pub async fn get_transaction_status(&self, tx_hash: H256) -> Result<TransactionStatus, Box<dyn std::error::Error>> {
+        let record = self.find_transaction(tx_hash).await?
+            .ok_or("Transaction not found")?;
+        Ok(record.status)
+    }
+
+    pub async fn speed_up(&self, tx_hash: H256, session: &SessionHandle) -> Result<H256, Box<dyn std::error::Error>> {
+        self.replace_transaction(tx_hash, session, false).await
+    }
+
+    pub async fn cancel(&self, tx_hash: H256, session: &SessionHandle) -> Result<H256, Box<dyn std::error::Error>> {
+        self.replace_transaction(tx_hash, session, true).await
+    }
+
+    pub fn tracker(&self, config: TrackerConfig) -> TransactionTracker<T> {
+        TransactionTracker::new(self.web3.clone(), self.wallets.clone(), self.nonce_manager.clone(), config)
+    }
+
     pub async fn estimate_gas(&self, tx_request: TransactionRequest) -> Result<U256, Box<dyn std::error::Error>> {
-        let web3_tx = web3::types::TransactionRequest {
-            from: None,
+        let web3_tx = web3::types::CallRequest {
             to: Some(tx_request.to),
             gas: tx_request.gas_limit,
-            gas_price: tx_request.gas_price,
+            max_fee_per_gas: tx_request.max_fee_per_gas,
+            max_priority_fee_per_gas: tx_request.max_priority_fee_per_gas,
             value: Some(tx_request.value),
             data: tx_request.data.map(|d| d.into()),
-            nonce: tx_request.nonce,
+            access_list: tx_request.access_list,
             ..Default::default()
         };
 
@@ -318,69 +809,162 @@ impl WalletManager {
         Ok(gas_estimate)
     }
 
+    pub async fn create_access_list(&self, from: Address, tx_request: &TransactionRequest) -> Result<AccessListResult, Box<dyn std::error::Error>> {
+        let request = web3::types::CallRequest {
+            from: Some(from),
+            to: Some(tx_request.to),
+            value: Some(tx_request.value),
+            data: tx_request.data.clone().map(|d| d.into()),
+            transaction_type: Some(2.into()),
+            ..Default::default()
+        };
+
+        access_list::create_access_list(&self.web3, request).await
+    }
+
     pub async fn get_gas_price(&self) -> Result<U256, Box<dyn std::error::Error>> {
         let gas_price = self.web3.eth().gas_price().await?;
         Ok(gas_price)
     }
 
-    pub async fn batch_transactions(&self, transactions: Vec<(Address, TransactionRequest)>, password: &str) -> Result<Vec<H256>, Box<dyn std::error::Error>> {
+    pub async fn estimate_fees(&self, speed: FeeSpeed) -> Result<FeeEstimate, Box<dyn std::error::Error>> {
+        self.fee_oracle.estimate(speed).await
+    }
+
+    pub async fn batch_transactions(&self, transactions: Vec<(SessionHandle, TransactionRequest)>) -> Result<Vec<H256>, Box<dyn std::error::Error>> {
         let mut tx_hashes = Vec::new();
 
-        for (from, tx_request) in transactions {
-            let secret_key = self.unlock_wallet(from, password).await?;
-            let tx_hash = self.sign_and_send_transaction(tx_request, &secret_key).await?;
+        for (session, tx_request) in transactions {
+            let key = self.sessions.key(&session).await?;
+            let tx_hash = self.sign_and_send_transaction(tx_request, &key).await?;
             tx_hashes.push(tx_hash);
         }
 
         Ok(tx_hashes)
     }
 
+    // Keystore v3 JSON under `export_password`, never the plaintext key
     pub async fn export_wallet(&self, address: Address, password: &str, export_password: &str) -> Result<String, Box<dyn std::error::Error>> {
-        let secret_key = self.unlock_wallet(address, password).await?;
-        let private_key_hex = format!("0x{}", hex::encode(secret_key.secret_bytes()));
-        Ok(private_key_hex)
+        self.export_keystore(address, password, export_password, KeystoreKdf::standard()).await
     }
 
     pub async fn delete_wallet(&self, address: Address, password: &str) -> Result<(), Box<dyn std::error::Error>> {
         // Verify password by unlocking
         self.unlock_wallet(address, password).await?;
+        self.sessions.lock_address(address).await;
+
+        if let Some(dir) = &self.keystore_dir {
+            dir.delete_key(address)?;
+        }
 
         let mut wallets = self.wallets.lock().await;
         let mut keystore = self.keystore.lock().await;
 
-        wallets.remove(&address.to_string());
-        keystore.remove(&address.to_string());
+        wallets.remove(&address);
+        keystore.remove(&address);
 
         Ok(())
     }
 
     pub async fn backup_wallets(&self, backup_path: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let wallets = self.wallets.lock().await;
-        let keystore = self.keystore.lock().await;
-
-        let backup_data = serde_json::json!({
-            "wallets": *wallets,
-            "keystore": *keystore,
-        });
+        let backup = self.backup_contents().await;
+        keystore_dir::write_atomic(std::path::Path::new(backup_path), serde_json::to_string_pretty(&backup)?.as_bytes())
+    }
 
-        std::fs::write(backup_path, serde_json::to_string_pretty(&backup_data)?)?;
-        Ok(())
+    // Same contents as backup_wallets, but encrypted as a whole under `bundle_password`
+    pub async fn backup_bundle(&self, backup_path: &str, bundle_password: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let backup = self.backup_contents().await;
+        BackupBundle::seal(&backup, bundle_password)?.write(backup_path)
     }
 
-    pub async fn restore_wallets(&self, backup_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    pub async fn restore_wallets(&self, backup_path: &str, mode: RestoreMode, dry_run: bool) -> Result<RestoreReport, Box<dyn std::error::Error>> {
         let backup_content = std::fs::read_to_string(backup_path)?;
-        let backup_data: serde_json::Value = serde_json::from_str(&backup_content)?;
+        let backup = BackupContents::parse(&backup_content)?;
+        self.restore_contents(backup, mode, dry_run).await
+    }
 
-        let restored_wallets: HashMap<String, Wallet> = serde_json::from_value(backup_data["wallets"].clone())?;
-        let restored_keystore: HashMap<String, EncryptedKey> = serde_json::from_value(backup_data["keystore"].clone())?;
+    pub async fn restore_bundle(&self, backup_path: &str, bundle_password: &str, mode: RestoreMode, dry_run: bool) -> Result<RestoreReport, Box<dyn std::error::Error>> {
+        let backup = BackupBundle::read(backup_path)?.open(bundle_password)?;
+        self.restore_contents(backup, mode, dry_run).await
+    }
+
+    async fn backup_contents(&self) -> BackupContents {
+        let wallets = self.wallets.lock().await;
+        let keystore = self.keystore.lock().await;
+        let seeds = self.seeds.lock().await;
+        let address_book = self.address_book.lock().await;
+
+        BackupContents {
+            schema_version: keystore_dir::SCHEMA_VERSION,
+            wallets: wallets.clone(),
+            keystore: keystore.clone(),
+            seeds: seeds.clone(),
+            address_book: address_book.clone(),
+        }
+    }
 
+    async fn restore_contents(&self, backup: BackupContents, mode: RestoreMode, dry_run: bool) -> Result<RestoreReport, Box<dyn std::error::Error>> {
         let mut wallets = self.wallets.lock().await;
         let mut keystore = self.keystore.lock().await;
+        let mut seeds = self.seeds.lock().await;
 
-        *wallets = restored_wallets;
-        *keystore = restored_keystore;
+        let mut report = backup_bundle::plan_restore(&wallets, &keystore, &seeds, &backup, mode);
+        report.dry_run = dry_run;
+        if dry_run {
+            return Ok(report);
+        }
 
-        Ok(())
+        let taken = report
+            .conflicts
+            .iter()
+            .filter(|conflict| conflict.resolution == ConflictResolution::TookBackup)
+            .map(|conflict| conflict.address);
+        let to_write: Vec<Address> = report.added.iter().cloned().chain(taken).collect();
+
+        // Disk first, so a failed write leaves memory matching what is persisted
+        if let Some(dir) = &self.keystore_dir {
+            for address in &to_write {
+                if let (Some(wallet), Some(encrypted_key)) = (backup.wallets.get(address), backup.keystore.get(address)) {
+                    dir.save_key(wallet, encrypted_key)?;
+                }
+            }
+            for address in &report.removed {
+                dir.delete_key(*address)?;
+            }
+            for seed_id in &report.seeds_added {
+                if let Some(seed) = backup.seeds.get(seed_id) {
+                    dir.save_seed(seed)?;
+                }
+            }
+        }
+
+        for address in &to_write {
+            if let Some(wallet) = backup.wallets.get(address) {
+                wallets.insert(*address, wallet.clone());
+            }
+            match backup.keystore.get(address) {
+                Some(encrypted_key) => keystore.insert(*address, encrypted_key.clone()),
+                None => keystore.remove(address),
+            };
+        }
+        for address in &report.removed {
+            wallets.remove(address);
+            keystore.remove(address);
+        }
+        for seed_id in &report.seeds_added {
+            if let Some(seed) = backup.seeds.get(seed_id) {
+                seeds.insert(seed_id.clone(), seed.clone());
+            }
+        }
+
+        let mut address_book = self.address_book.lock().await;
+        match mode {
+            RestoreMode::Merge => address_book.merge(&backup.address_book),
+            RestoreMode::Replace => *address_book = backup.address_book.clone(),
+        }
+        self.persist_address_book(&address_book)?;
+
+        Ok(report)
     }
 
     pub async fn get_wallet_stats(&self, address: Address) -> Result<HashMap<String, U256>, Box<dyn std::error::Error>> {
@@ -422,115 +1006,298 @@ impl WalletManager {
     }
 
     pub async fn generate_new_address(&self, name: &str, password: &str) -> Result<Address, Box<dyn std::error::Error>> {
-        self.create_wallet(name, password).await
+        let default_seed = self.default_seed.lock().await.clone();
+        match default_seed {
+            Some(seed_id) => self.derive_next_account(&seed_id, name, password).await,
+            None => self.create_wallet(name, password).await,
+        }
     }
 
     pub async fn change_wallet_password(&self, address: Address, old_password: &str, new_password: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let secret_key = self.unlock_wallet(address, old_password).await?;
-        let new_encrypted_key = self.encrypt_private_key(&secret_key, new_password)?;
+        let private_key = self.unlock_wallet(address, old_password).await?;
+        let new_encrypted_key = self.encrypt_private_key(&private_key, new_password)?;
 
-        let mut keystore = self.keystore.lock().await;
-        keystore.insert(address.to_string(), new_encrypted_key);
+        self.keystore.lock().await.insert(address, new_encrypted_key);
 
-        Ok(())
+        self.persist(address).await
     }
 
+    // Rejects mixed-case input with a bad EIP-55 checksum
     pub async fn validate_address(&self, address: &str) -> Result<bool, Box<dyn std::error::Error>> {
-        Ok(address.starts_with("0x") && address.len() == 42 && hex::decode(&address[2..]).is_ok())
+        Ok(address::parse_address(address).is_ok())
+    }
+
+    pub fn checksum_address(&self, address: Address) -> String {
+        address::to_checksum(&address)
     }
 
     pub async fn get_wallet_addresses(&self) -> Result<Vec<Address>, Box<dyn std::error::Error>> {
         let wallets = self.wallets.lock().await;
-        Ok(wallets.keys().filter_map(|k| k.parse().ok()).collect())
+        let mut addresses: Vec<Address> = wallets.keys().cloned().collect();
+        addresses.sort();
+        Ok(addresses)
     }
 
     pub async fn rename_wallet(&self, address: Address, new_name: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let mut wallets = self.wallets.lock().await;
-        if let Some(wallet) = wallets.get_mut(&address.to_string()) {
-            wallet.name = new_name.to_string();
+        {
+            let mut wallets = self.wallets.lock().await;
+            if let Some(wallet) = wallets.get_mut(&address) {
+                wallet.name = new_name.to_string();
+            }
         }
-        Ok(())
+
+        self.persist(address).await
     }
 
     // Private helper methods
-    async fn sign_and_send_transaction(&self, tx_request: TransactionRequest, secret_key: &SecretKey) -> Result<H256, Box<dyn std::error::Error>> {
-        let gas_price = tx_request.gas_price.unwrap_or_else(|| self.web3.eth().gas_price().await.unwrap_or(U256::from(20000000000)));
-        let gas_limit = tx_request.gas_limit.unwrap_or(U256::from(21000));
-        let nonce = tx_request.nonce.unwrap_or_else(|| self.web3.eth().transaction_count(Address::zero(), None).await.unwrap_or(U256::zero()));
+    async fn sign_and_send_transaction(&self, tx_request: TransactionRequest, key: &PrivateKey) -> Result<H256, Box<dyn std::error::Error>> {
+        let from = key.address();
+        let chain_id = self.web3.eth().chain_id().await?.as_u64();
+        let (max_fee_per_gas, max_priority_fee_per_gas) = match (tx_request.max_fee_per_gas, tx_request.max_priority_fee_per_gas) {
+            (Some(max_fee), Some(priority_fee)) => (max_fee, priority_fee),
+            _ => {
+                let fees = self.fee_oracle.estimate(tx_request.fee_speed).await?;
+                (
+                    tx_request.max_fee_per_gas.unwrap_or(fees.max_fee_per_gas),
+                    tx_request.max_priority_fee_per_gas.unwrap_or(fees.max_priority_fee_per_gas),
+                )
+            }
+        };
+        if let Some(cap) = self.fee_oracle.fee_cap() {
+            if max_fee_per_gas > cap {
+                return Err(format!("Max fee of {} wei per gas is above the fee cap of {} wei", max_fee_per_gas, cap).into());
+            }
+        }
+        if max_priority_fee_per_gas > max_fee_per_gas {
+            return Err("Max priority fee per gas can't exceed max fee per gas".into());
+        }
+
+        let generated = if tx_request.access_list.is_none() && tx_request.create_access_list {
+            Some(self.create_access_list(from, &tx_request).await?).filter(|result| result.saves_gas())
+        } else {
+            None
+        };
+        let gas_limit = tx_request.gas_limit
+            .or_else(|| generated.as_ref().map(|result| result.gas_limit()))
+            .unwrap_or(U256::from(21000));
+        let access_list = tx_request.access_list.clone().or_else(|| generated.map(|result| result.access_list));
+
+        let build_and_send = |nonce: U256| {
+            let web3 = self.web3.clone();
+            let tx = web3::types::TransactionParameters {
+                to: Some(tx_request.to),
+                gas: gas_limit,
+                max_fee_per_gas: Some(max_fee_per_gas),
+                max_priority_fee_per_gas: Some(max_priority_fee_per_gas),
+                transaction_type: Some(2.into()),
+                access_list: access_list.clone(),
+                value: tx_request.value,
+                data: tx_request.data.clone().unwrap_or_default().into(),
+                nonce: Some(nonce),
+                chain_id: Some(chain_id),
+                ..Default::default()
+            };
+
+            async move {
+                let signed_tx = web3.accounts().sign_transaction(tx, PrivateKeyRef::new(key)).await?;
+                web3.eth().send_raw_transaction(signed_tx.raw_transaction).await
+            }
+        };
+
+        // An explicit nonce bypasses the manager, e.g. when replacing a pending transaction
+        let (nonce, tx_hash) = match tx_request.nonce {
+            Some(nonce) => (nonce, build_and_send(nonce).await?),
+            None => self.nonce_manager.send_with_nonce(&self.web3, chain_id, from, build_and_send).await?,
+        };
 
-        let tx = web3::types::TransactionRequest {
+        let transaction_record = TransactionRecord {
+            hash: tx_hash,
+            from,
             to: Some(tx_request.to),
-            gas: Some(gas_limit),
-            gas_price: Some(gas_price),
-            value: Some(tx_request.value),
-            data: tx_request.data.map(|d| d.into()),
+            value: tx_request.value,
+            timestamp: U256::from(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs()),
+            status: TransactionStatus::Pending,
+            gas_used: None,
+            max_fee_per_gas,
+            max_priority_fee_per_gas: Some(max_priority_fee_per_gas),
             nonce: Some(nonce),
-            ..Default::default()
+            gas_limit: Some(gas_limit),
+            data: tx_request.data,
+            block_number: None,
+            block_hash: None,
+            confirmations: 0,
+            replaced_by: None,
+            access_list,
         };
 
-        let signed_tx = self.web3.accounts().sign_transaction(tx, &SecretKeyRef::new(secret_key)).await?;
-        let tx_hash = self.web3.eth().send_raw_transaction(signed_tx.raw_transaction).await?;
+        {
+            let mut wallets = self.wallets.lock().await;
+            if let Some(wallet) = wallets.get_mut(&from) {
+                wallet.transactions.push(transaction_record);
+            }
+        }
 
+        self.persist(from).await?;
         Ok(tx_hash)
     }
 
-    fn encrypt_private_key(&self, secret_key: &SecretKey, password: &str) -> Result<EncryptedKey, Box<dyn std::error::Error>> {
-        use aes_gcm::{Aes256Gcm, Key, Nonce};
-        use aes_gcm::aead::{Aead, NewAead};
+    // Resubmits a pending transaction at the same nonce. Nodes only accept a
+    // replacement that raises both the max fee and the priority fee by at least 10%.
+    async fn replace_transaction(&self, tx_hash: H256, session: &SessionHandle, cancel: bool) -> Result<H256, Box<dyn std::error::Error>> {
+        let original = self.find_transaction(tx_hash).await?
+            .ok_or("Transaction not found")?;
 
-        let salt: [u8; 32] = rand::random();
-        let mut key = [0u8; 32];
-        pbkdf2::pbkdf2::<hmac::Hmac<sha2::Sha256>>(password.as_bytes(), &salt, 10000, &mut key);
+        if original.status != TransactionStatus::Pending {
+            return Err(format!("Transaction {:?} is no longer pending", tx_hash).into());
+        }
+        let nonce = original.nonce.ok_or("Transaction was recorded without a nonce")?;
 
-        let cipher = Aes256Gcm::new(Key::from_slice(&key));
-        let nonce: [u8; 12] = rand::random();
-        let nonce = Nonce::from_slice(&nonce);
+        let key = self.sessions.key(session).await?;
+        if key.address() != original.from {
+            return Err(format!("Session is for {:?}, but the transaction was sent from {:?}", key.address(), original.from).into());
+        }
 
-        let encrypted = cipher.encrypt(nonce, secret_key.secret_bytes().as_ref())?;
+        // Legacy records only carry a gas price, which paid the full amount as priority fee
+        let original_priority_fee = original.max_priority_fee_per_gas.unwrap_or(original.max_fee_per_gas);
+        let fees = self.fee_oracle.replacement(original.max_fee_per_gas, original_priority_fee, REPLACEMENT_FEE_BUMP_PER_MILLE).await?;
+
+        let tx_request = if cancel {
+            TransactionRequest {
+                to: original.from,
+                value: U256::zero(),
+                gas_limit: Some(U256::from(21000)),
+                max_fee_per_gas: Some(fees.max_fee_per_gas),
+                max_priority_fee_per_gas: Some(fees.max_priority_fee_per_gas),
+                fee_speed: FeeSpeed::Fast,
+                data: None,
+                nonce: Some(nonce),
+                access_list: None,
+                create_access_list: false,
+            }
+        } else {
+            TransactionRequest {
+                to: original.to.ok_or("Contract creations can't be sped up")?,
+                value: original.value,
+                gas_limit: original.gas_limit,
+                max_fee_per_gas: Some(fees.max_fee_per_gas),
+                max_priority_fee_per_gas: Some(fees.max_priority_fee_per_gas),
+                fee_speed: FeeSpeed::Fast,
+                data: original.data.clone(),
+                nonce: Some(nonce),
+                access_list: original.access_list.clone(),
+                create_access_list: false,
+            }
+        };
+
+        let replacement_hash = self.sign_and_send_transaction(tx_request, &key).await?;
+        self.nonce_manager.mark_sent(self.web3.eth().chain_id().await?.as_u64(), original.from, nonce, replacement_hash).await;
+
+        {
+            let mut wallets = self.wallets.lock().await;
+            if let Some(wallet) = wallets.get_mut(&original.from) {
+                if let Some(record) = wallet.transactions.iter_mut().find(|record| record.hash == tx_hash) {
+                    record.replaced_by = Some(replacement_hash);
+                }
+            }
+        }
+
+        // The tracker needs the link after a restart to report Replaced rather than Dropped
+        self.persist(original.from).await?;
+        Ok(replacement_hash)
+    }
+
+    // Writes one wallet and its key back to the keystore directory, if there is one.
+    // Transactions other processes recorded for the wallet are merged in, on disk and here.
+    async fn persist(&self, address: Address) -> Result<(), Box<dyn std::error::Error>> {
+        let dir = match &self.keystore_dir {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+
+        let wallet = self.wallets.lock().await.get(&address).cloned();
+        let encrypted_key = self.keystore.lock().await.get(&address).cloned();
+        let (wallet, encrypted_key) = match (wallet, encrypted_key) {
+            (Some(wallet), Some(encrypted_key)) => (wallet, encrypted_key),
+            _ => return Ok(()),
+        };
+
+        let stored = dir.update_key(address, |current| {
+            let mut wallet = wallet;
+            if let Some(current) = current {
+                wallet.merge_transactions(&current.wallet.transactions);
+            }
+            StoredKey { schema_version: keystore_dir::SCHEMA_VERSION, wallet, key: encrypted_key }
+        })?;
+
+        if let Some(wallet) = self.wallets.lock().await.get_mut(&address) {
+            wallet.merge_transactions(&stored.wallet.transactions);
+        }
+        Ok(())
+    }
+
+    async fn find_transaction(&self, tx_hash: H256) -> Result<Option<TransactionRecord>, Box<dyn std::error::Error>> {
+        let wallets = self.wallets.lock().await;
+        Ok(wallets.values()
+            .flat_map(|wallet| wallet.transactions.iter())
+            .find(|record| record.hash == tx_hash)
+            .cloned())
+    }
+
+    fn encrypt_private_key(&self, private_key: &PrivateKey, password: &str) -> Result<EncryptedKey, Box<dyn std::error::Error>> {
+        let (encrypted, salt, iv) = self.encrypt_secret(private_key.expose_bytes(), password)?;
 
         Ok(EncryptedKey {
-            address: public_key_to_address(&secret_key.public_key(&Secp256k1::new())),
+            address: private_key.address(),
             encrypted_private_key: encrypted,
-            salt: salt.to_vec(),
-            iv: nonce.to_vec(),
+            salt,
+            iv,
+            kdf: self.kdf,
+        })
+    }
+
+    fn encrypt_seed(&self, seed_id: &str, seed: &[u8], password: &str) -> Result<EncryptedSeed, Box<dyn std::error::Error>> {
+        let (encrypted_seed, salt, iv) = self.encrypt_secret(seed, password)?;
+
+        Ok(EncryptedSeed {
+            seed_id: seed_id.to_string(),
+            encrypted_seed,
+            salt,
+            iv,
+            kdf: self.kdf,
         })
     }
 
-    fn decrypt_private_key(&self, encrypted_key: &EncryptedKey, password: &str) -> Result<SecretKey, Box<dyn std::error::Error>> {
+    fn decrypt_private_key(&self, encrypted_key: &EncryptedKey, password: &str) -> Result<PrivateKey, Box<dyn std::error::Error>> {
+        let decrypted = self.decrypt_secret(&encrypted_key.encrypted_private_key, &encrypted_key.salt, &encrypted_key.iv, &encrypted_key.kdf, password)?;
+        PrivateKey::from_slice(decrypted.expose())
+    }
+
+    // Returns (ciphertext, salt, iv); the key is derived with self.kdf
+    fn encrypt_secret(&self, plaintext: &[u8], password: &str) -> Result<(Vec<u8>, Vec<u8>, Vec<u8>), Box<dyn std::error::Error>> {
         use aes_gcm::{Aes256Gcm, Key, Nonce};
         use aes_gcm::aead::{Aead, NewAead};
 
-        let mut key = [0u8; 32];
-        pbkdf2::pbkdf2::<hmac::Hmac<sha2::Sha256>>(password.as_bytes(), &encrypted_key.salt, 10000, &mut key);
+        let salt: [u8; 32] = rand::random();
+        let key = self.kdf.derive_key(password, &salt)?;
 
-        let cipher = Aes256Gcm::new(Key::from_slice(&key));
-        let nonce = Nonce::from_slice(&encrypted_key.iv);
+        let cipher = Aes256Gcm::new(Key::from_slice(key.as_ref()));
+        let nonce: [u8; 12] = rand::random();
+        let nonce = Nonce::from_slice(&nonce);
 
-        let decrypted = cipher.decrypt(nonce, encrypted_key.encrypted_private_key.as_ref())?;
-        let secret_key = SecretKey::from_slice(&decrypted)?;
+        let encrypted = cipher.encrypt(nonce, plaintext)?;
 
-        Ok(secret_key)
+        Ok((encrypted, salt.to_vec(), nonce.to_vec()))
     }
 
-    fn encode_address(&self, address: Address) -> web3::types::Bytes {
-        let mut bytes = vec![0u8; 32];
-        bytes[12..32].copy_from_slice(address.as_bytes());
-        bytes.into()
-    }
+    fn decrypt_secret(&self, ciphertext: &[u8], salt: &[u8], iv: &[u8], kdf: &KdfParams, password: &str) -> Result<SecretBytes, Box<dyn std::error::Error>> {
+        use aes_gcm::{Aes256Gcm, Key, Nonce};
+        use aes_gcm::aead::{Aead, NewAead};
 
-    fn encode_uint256(&self, value: U256) -> web3::types::Bytes {
-        let mut bytes = vec![0u8; 32];
-        value.to_big_endian(&mut bytes);
-        bytes.into()
-    }
+        let key = kdf.derive_key(password, salt)?;
 
-    fn decode_uint256(&self, data: &[u8]) -> U256 {
-        U256::from_big_endian(data)
+        let cipher = Aes256Gcm::new(Key::from_slice(key.as_ref()));
+        let nonce = Nonce::from_slice(iv);
+
+        Ok(SecretBytes::new(cipher.decrypt(nonce, ciphertext)?))
     }
 }
-
-fn public_key_to_address(public_key: &secp256k1::PublicKey) -> Address {
-    let public_key = public_key.serialize_uncompressed();
-    let hash = web3::signing::keccak256(&public_key[1..]);
-    Address::from_slice(&hash[12..])
-}
\ No newline at end of file